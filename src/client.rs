@@ -80,6 +80,30 @@ pub struct Client {
 }
 
 impl Client {
+    /// Creates a client that is closed from the start and never
+    /// connects, for messages that are not associated with a connection,
+    /// see `Message::new`.
+    pub(crate) fn closed() -> Client {
+        let (flush_kicker, _) = channel::bounded(1);
+        Client {
+            state: Arc::new(State {
+                write: Mutex::new(WriteState {
+                    writer: None,
+                    flush_kicker,
+                    buffer: Buffer::new(0),
+                    next_sid: 1,
+                }),
+                read: Mutex::new(ReadState {
+                    subscriptions: HashMap::new(),
+                    pongs: VecDeque::new(),
+                }),
+            }),
+            server_info: Arc::new(Mutex::new(ServerInfo::default())),
+            shutdown: Arc::new(Mutex::new(true)),
+            options: Arc::new(Options::new()),
+        }
+    }
+
     /// Creates a new client that will begin connecting in the background.
     pub(crate) fn connect(url: &str, options: Options) -> io::Result<Client> {
         // A channel for coordinating flushes.
@@ -277,7 +301,7 @@ impl Client {
         !old
     }
 
-    fn check_shutdown(&self) -> io::Result<()> {
+    pub(crate) fn check_shutdown(&self) -> io::Result<()> {
         if *self.shutdown.lock() {
             Err(Error::new(ErrorKind::NotConnected, "the client is closed"))
         } else {
@@ -50,6 +50,11 @@ pub struct ConnectInfo {
 
     /// Whether the client supports the usage of headers.
     pub headers: bool,
+
+    /// If set to `true` (requires `headers`), the server answers requests
+    /// that nothing is subscribed to with a `503` status message, instead
+    /// of leaving them unanswered.
+    pub no_responders: bool,
 }
 
 impl ConnectInfo {
@@ -62,6 +67,7 @@ impl ConnectInfo {
             version: self.version.clone(),
             tls_required: self.tls_required,
             headers: self.headers,
+            no_responders: self.no_responders,
         };
         if let Some(s) = &self.user_jwt {
             obj.insert("jwt", s.to_string()).ok()?;
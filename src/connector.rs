@@ -312,6 +312,7 @@ impl Connector {
             signature: None,
             echo: !self.options.no_echo,
             headers: true,
+            no_responders: true,
         };
 
         // Fill in the info that authenticates the client.
@@ -0,0 +1,278 @@
+// Copyright 2021 The NATS Authors
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    convert::TryFrom,
+    fmt, io,
+    ops::{Add, Sub},
+    str::FromStr,
+    time,
+};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A span of time as the `JetStream` API represents it, which is
+/// serialized as a number of nanoseconds. All durations in the
+/// configurations and messages of the `JetStream` API use it.
+///
+/// It converts to and from `std::time::Duration`, and serializes to
+/// exactly `std::time::Duration::as_nanos`. The server only accepts
+/// durations that fit into an `i64`, so serializing a longer duration,
+/// of more than about 292 years, returns an error.
+///
+/// Besides a number of nanoseconds, it also deserializes from a string
+/// in the style of Go's `time.Duration` like `"1m30s"`, which is how
+/// configurations exported by the `nats` CLI represent durations, or
+/// from a string of digits counting nanoseconds. It is displayed in the
+/// style of Go as well.
+///
+/// # Example
+///
+/// ```
+/// use nats::jetstream::Duration;
+///
+/// let ack_wait = Duration::from_secs(30);
+/// assert_eq!(serde_json::to_string(&ack_wait).unwrap(), "30000000000");
+/// assert_eq!(
+///     std::time::Duration::from(ack_wait),
+///     std::time::Duration::from_secs(30)
+/// );
+/// assert_eq!(serde_json::from_str::<Duration>("\"30s\"").unwrap(), ack_wait);
+/// assert_eq!(ack_wait.to_string(), "30s");
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration(time::Duration);
+
+impl Duration {
+    /// Creates a duration of `secs` seconds.
+    pub const fn from_secs(secs: u64) -> Duration {
+        Duration(time::Duration::from_secs(secs))
+    }
+
+    /// Creates a duration of `millis` milliseconds.
+    pub const fn from_millis(millis: u64) -> Duration {
+        Duration(time::Duration::from_millis(millis))
+    }
+
+    /// Creates a duration of `nanos` nanoseconds.
+    pub const fn from_nanos(nanos: u64) -> Duration {
+        Duration(time::Duration::from_nanos(nanos))
+    }
+
+    /// Returns the number of nanoseconds in this duration.
+    pub const fn as_nanos(&self) -> u128 {
+        self.0.as_nanos()
+    }
+
+    /// Returns `self - other`, or `None` if `other` is longer.
+    pub fn checked_sub(self, other: Duration) -> Option<Duration> {
+        self.0.checked_sub(other.0).map(Duration)
+    }
+}
+
+impl From<time::Duration> for Duration {
+    fn from(duration: time::Duration) -> Duration {
+        Duration(duration)
+    }
+}
+
+impl From<Duration> for time::Duration {
+    fn from(duration: Duration) -> time::Duration {
+        duration.0
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    /// Adds two durations, panicking on overflow like
+    /// `std::time::Duration`.
+    fn add(self, other: Duration) -> Duration {
+        Duration(self.0 + other.0)
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    /// Subtracts two durations, panicking if `other` is longer like
+    /// `std::time::Duration`. See `Duration::checked_sub`.
+    fn sub(self, other: Duration) -> Duration {
+        Duration(self.0 - other.0)
+    }
+}
+
+impl Serialize for Duration {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let nanos = i64::try_from(self.as_nanos()).map_err(|_| {
+            ser::Error::custom(format!(
+                "the duration {:?} is too long to be sent to the server",
+                self.0
+            ))
+        })?;
+        serializer.serialize_i64(nanos)
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum NanosOrString {
+            Nanos(i64),
+            String(String),
+        }
+
+        match NanosOrString::deserialize(deserializer)? {
+            NanosOrString::Nanos(nanos) => {
+                u64::try_from(nanos).map(Duration::from_nanos).map_err(|_| {
+                    de::Error::custom(format!(
+                        "the duration of {}ns is negative",
+                        nanos
+                    ))
+                })
+            }
+            // some proxies encode large numbers as strings of digits
+            NanosOrString::String(s) => match s.trim().parse::<u64>() {
+                Ok(nanos) => Ok(Duration::from_nanos(nanos)),
+                Err(_) => s.parse().map_err(de::Error::custom),
+            },
+        }
+    }
+}
+
+impl FromStr for Duration {
+    type Err = io::Error;
+
+    /// Parses a non-negative duration in the style of Go's
+    /// `time.Duration`, i.e. a sequence of decimal numbers, each with an
+    /// optional fraction and a unit of `h`, `m`, `s`, `ms`, `us` (or
+    /// `µs`) or `ns`, like `"1h30m"` or `"1.5s"`. A plain `"0"` is also
+    /// accepted.
+    fn from_str(s: &str) -> io::Result<Duration> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid duration {:?}", s),
+            )
+        };
+
+        let s = s.trim();
+        if s == "0" {
+            return Ok(Duration::default());
+        }
+        if s.is_empty() {
+            return Err(invalid());
+        }
+
+        let is_number = |c: char| c.is_ascii_digit() || c == '.';
+        let mut nanos: u128 = 0;
+        let mut rest = s;
+        while !rest.is_empty() {
+            let (number, tail) = rest
+                .split_at(rest.find(|c| !is_number(c)).unwrap_or(rest.len()));
+            let (unit, tail) =
+                tail.split_at(tail.find(is_number).unwrap_or(tail.len()));
+            rest = tail;
+
+            let size: u128 = match unit {
+                "h" => 3_600_000_000_000,
+                "m" => 60_000_000_000,
+                "s" => 1_000_000_000,
+                "ms" => 1_000_000,
+                "us" | "\u{b5}s" | "\u{3bc}s" => 1_000,
+                "ns" => 1,
+                _ => return Err(invalid()),
+            };
+            let mut parts = number.splitn(2, '.');
+            let whole = parts.next().unwrap_or_default();
+            let fraction = parts.next().unwrap_or_default();
+            if whole.is_empty() && fraction.is_empty() {
+                return Err(invalid());
+            }
+
+            let mut value = if whole.is_empty() {
+                0
+            } else {
+                whole
+                    .parse::<u128>()
+                    .ok()
+                    .and_then(|whole| whole.checked_mul(size))
+                    .ok_or_else(invalid)?
+            };
+            // digits below a nanosecond are dropped, like Go does
+            let mut scale = size;
+            for digit in fraction.chars() {
+                let digit = digit.to_digit(10).ok_or_else(invalid)?;
+                scale /= 10;
+                value += u128::from(digit) * scale;
+            }
+            nanos = nanos.checked_add(value).ok_or_else(invalid)?;
+        }
+
+        u64::try_from(nanos)
+            .map(Duration::from_nanos)
+            .map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for Duration {
+    /// Formats the duration in the style of Go's `time.Duration`, which
+    /// is what the `nats` CLI and `Duration::from_str` parse, leaving out
+    /// any zero-valued units so that e.g. 90 seconds becomes `"1m30s"`
+    /// and two hours becomes `"2h"`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [(&str, u128); 6] = [
+            ("h", 3_600_000_000_000),
+            ("m", 60_000_000_000),
+            ("s", 1_000_000_000),
+            ("ms", 1_000_000),
+            ("us", 1_000),
+            ("ns", 1),
+        ];
+
+        let mut remaining = self.as_nanos();
+        if remaining == 0 {
+            return f.write_str("0s");
+        }
+        for (unit, size) in &UNITS {
+            if remaining >= *size {
+                write!(f, "{}{}", remaining / size, unit)?;
+                remaining %= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for Duration {
+    type Parameters = ();
+    type Strategy = proptest::strategy::Map<
+        std::ops::RangeInclusive<u64>,
+        fn(u64) -> Duration,
+    >;
+
+    /// Generates the durations that the server accepts, which fit into an
+    /// `i64` of nanoseconds.
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+
+        (0..=i64::MAX as u64).prop_map(Duration::from_nanos)
+    }
+}
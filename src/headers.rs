@@ -115,6 +115,22 @@ impl TryFrom<&[u8]> for Headers {
             if !line.starts_with("NATS/") {
                 return parse_error("version line does not begin with NATS/");
             }
+
+            // status messages such as `NATS/1.0 204 EOB` carry a code and
+            // a description after the version
+            let mut status = line.splitn(3, ' ').skip(1).map(str::trim);
+            if let Some(code) = status.next().filter(|code| !code.is_empty()) {
+                inner
+                    .entry(Headers::STATUS.to_string())
+                    .or_insert_with(HashSet::default)
+                    .insert(code.to_string());
+            }
+            if let Some(description) = status.next().filter(|d| !d.is_empty()) {
+                inner
+                    .entry(Headers::DESCRIPTION.to_string())
+                    .or_insert_with(HashSet::default)
+                    .insert(description.to_string());
+            }
         } else {
             return parse_error("expected header information not present");
         };
@@ -148,11 +164,34 @@ impl Deref for Headers {
 }
 
 impl Headers {
+    /// The pseudo-header that holds the status code of a status message,
+    /// such as `"204"` or `"404"`, which the server sends on the version
+    /// line rather than as a regular header. Like `Headers::DESCRIPTION`,
+    /// it starts with a `:` so that it can not clash with a header parsed
+    /// from a message, and it is left out when the headers are sent, so
+    /// that republishing a received message does not forge a status.
+    pub const STATUS: &'static str = ":status";
+
+    /// The pseudo-header that holds the description following the status
+    /// code of a status message, such as `"EOB"`.
+    pub const DESCRIPTION: &'static str = ":description";
+
+    /// Returns the first value of the given header, if present.
+    pub(crate) fn get_first(&self, name: &str) -> Option<&str> {
+        self.inner
+            .get(name)
+            .and_then(|values| values.iter().next())
+            .map(String::as_str)
+    }
+
     pub(crate) fn to_bytes(&self) -> Vec<u8> {
         // `<version line>\r\n[headers]\r\n\r\n[payload]\r\n`
         let mut buf = vec![];
         buf.extend_from_slice(b"NATS/1.0\r\n");
         for (k, vs) in &self.inner {
+            if k == Headers::STATUS || k == Headers::DESCRIPTION {
+                continue;
+            }
             for v in vs {
                 buf.extend_from_slice(k.trim().as_bytes());
                 buf.push(b':');
@@ -168,33 +168,95 @@
 //! ```
 
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     convert::TryFrom,
     fmt::Debug,
     io::{self, Error, ErrorKind},
     iter::DoubleEndedIterator,
-    time::Duration,
+    time,
 };
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+pub use crate::duration::Duration;
 pub use crate::jetstream_types::*;
 
-use crate::{Connection as NatsClient, Message};
+use crate::{Connection as NatsClient, Headers, Message};
+
+/// Determines which `JetStream` API the requests of a client are sent to:
+/// the one of the account the client is connected to (`$JS.API.`, the
+/// default), the one of a specific `JetStream` domain
+/// (`$JS.<domain>.API.`), or one imported from another account under a
+/// custom prefix.
+///
+/// # Example
+///
+/// ```
+/// use nats::jetstream::JetStreamOptions;
+///
+/// let opts = JetStreamOptions::new().domain("hub");
+/// assert_eq!(
+///     opts.api_subject("STREAM.INFO.events"),
+///     "$JS.hub.API.STREAM.INFO.events"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JetStreamOptions {
+    pub(crate) api_prefix: String,
+    pub(crate) domain: Option<String>,
+}
 
-/// `ApiResponse` is a standard response from the `JetStream` JSON Api
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-enum ApiResponse<T> {
-    Ok(T),
-    Err { r#type: String, error: ApiError },
+impl Default for JetStreamOptions {
+    fn default() -> JetStreamOptions {
+        JetStreamOptions {
+            api_prefix: "$JS.API.".to_string(),
+            domain: None,
+        }
+    }
 }
 
-/// `ApiError` is included in all Api responses if there was an error.
-#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
-struct ApiError {
-    code: usize,
-    description: Option<String>,
+impl JetStreamOptions {
+    /// Creates options for the `JetStream` API of the account the client is
+    /// connected to.
+    pub fn new() -> JetStreamOptions {
+        JetStreamOptions::default()
+    }
+
+    /// Sets a custom API prefix, e.g. one that the API of another account
+    /// is imported under. This replaces any `domain` set before.
+    pub fn api_prefix(mut self, api_prefix: &str) -> JetStreamOptions {
+        self.api_prefix = api_prefix.to_string();
+        if !self.api_prefix.ends_with('.') {
+            self.api_prefix.push('.');
+        }
+        self.domain = None;
+        self
+    }
+
+    /// Sends requests to the `JetStream` API of the given domain, using the
+    /// prefix `$JS.<domain>.API.`. This replaces any `api_prefix` set
+    /// before.
+    pub fn domain(mut self, domain: &str) -> JetStreamOptions {
+        self.api_prefix = format!("$JS.{}.API.", domain);
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Returns the prefix of all API subjects, which ends with a `.`
+    pub fn prefix(&self) -> &str {
+        &self.api_prefix
+    }
+
+    /// Returns the domain that requests are sent to, if one was set.
+    pub fn domain_name(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// Returns the subject of an API operation such as
+    /// `"STREAM.INFO.<stream>"`.
+    pub fn api_subject(&self, operation: &str) -> String {
+        format!("{}{}", self.api_prefix, operation)
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -278,6 +340,166 @@ where
     }
 }
 
+/// An iterator over the replies to a batched direct get request, which the
+/// server sends as one message per stored message followed by a status
+/// message with the code `204` to mark the end of the batch.
+///
+/// Each item is decoded separately, so a reply that can not be decoded as a
+/// `StreamMsg` is yielded as an error without ending the batch. Any status
+/// other than the end-of-batch marker, such as `404` when no messages match
+/// the request, ends the batch with an error.
+pub struct DirectGetBatch {
+    messages: Box<dyn Iterator<Item = Message> + Send>,
+    done: bool,
+}
+
+impl DirectGetBatch {
+    /// Creates a `DirectGetBatch` over the replies to a batched direct get
+    /// request, in the order they were received.
+    pub fn new<I>(messages: I) -> DirectGetBatch
+    where
+        I: IntoIterator<Item = Message>,
+        I::IntoIter: Send + 'static,
+    {
+        DirectGetBatch {
+            messages: Box::new(messages.into_iter()),
+            done: false,
+        }
+    }
+}
+
+impl std::iter::FusedIterator for DirectGetBatch {}
+
+impl Iterator for DirectGetBatch {
+    type Item = io::Result<StreamMsg>;
+
+    fn next(&mut self) -> Option<io::Result<StreamMsg>> {
+        if self.done {
+            return None;
+        }
+
+        let msg = if let Some(msg) = self.messages.next() {
+            msg
+        } else {
+            self.done = true;
+            return None;
+        };
+
+        let header = |name| {
+            msg.headers
+                .as_ref()
+                .and_then(|headers| headers.get_first(name))
+        };
+        match header(Headers::STATUS) {
+            None => Some(StreamMsg::from_message(&msg)),
+            Some("204") => {
+                self.done = true;
+                None
+            }
+            Some(code) => {
+                self.done = true;
+                let kind = if code == "404" {
+                    ErrorKind::NotFound
+                } else {
+                    ErrorKind::Other
+                };
+                Some(Err(Error::new(
+                    kind,
+                    format!(
+                        "direct get failed with status {}: {}",
+                        code,
+                        header(Headers::DESCRIPTION).unwrap_or_default()
+                    ),
+                )))
+            }
+        }
+    }
+}
+
+/// An iterator that drains a pull-based consumer, issuing one `no_wait`
+/// `NextRequest` after another and yielding the messages of each batch,
+/// until the server reports that no messages are left with a `404`
+/// status. See `Consumer::drain`.
+///
+/// A `408` status, sent when a request expired before it was filled, is
+/// answered with another request. A `409` status, e.g. because the
+/// consumer was deleted or a message exceeds `max_bytes`, and any other
+/// status end the drain with an error, as does a failed request. A batch
+/// without any replies also ends the drain.
+pub struct PullDrain {
+    request: Box<dyn FnMut(&NextRequest) -> io::Result<Vec<Message>> + Send>,
+    next_request: NextRequest,
+    batch: std::vec::IntoIter<Message>,
+    done: bool,
+}
+
+impl PullDrain {
+    /// Creates a `PullDrain` that sends `next_request` with `no_wait` set
+    /// by calling `request`, which returns the replies to a single request
+    /// in the order they were received.
+    pub fn new<F>(next_request: NextRequest, request: F) -> PullDrain
+    where
+        F: FnMut(&NextRequest) -> io::Result<Vec<Message>> + Send + 'static,
+    {
+        PullDrain {
+            request: Box::new(request),
+            next_request: NextRequest {
+                no_wait: true,
+                ..next_request
+            },
+            batch: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+impl std::iter::FusedIterator for PullDrain {}
+
+impl Iterator for PullDrain {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<io::Result<Message>> {
+        while !self.done {
+            let msg = if let Some(msg) = self.batch.next() {
+                msg
+            } else {
+                match (self.request)(&self.next_request) {
+                    Ok(batch) if batch.is_empty() => self.done = true,
+                    Ok(batch) => self.batch = batch.into_iter(),
+                    Err(error) => {
+                        self.done = true;
+                        return Some(Err(error));
+                    }
+                }
+                continue;
+            };
+
+            let header = |name| {
+                msg.headers
+                    .as_ref()
+                    .and_then(|headers| headers.get_first(name))
+            };
+            match header(Headers::STATUS) {
+                None => return Some(Ok(msg)),
+                Some("404") => self.done = true,
+                Some("408") => self.batch = Vec::new().into_iter(),
+                Some(code) => {
+                    self.done = true;
+                    return Some(Err(Error::new(
+                        ErrorKind::Other,
+                        format!(
+                            "pull request failed with status {}: {}",
+                            code,
+                            header(Headers::DESCRIPTION).unwrap_or_default()
+                        ),
+                    )));
+                }
+            }
+        }
+        None
+    }
+}
+
 impl NatsClient {
     /// Create a `JetStream` stream.
     pub fn create_stream<S>(&self, stream_config: S) -> io::Result<StreamInfo>
@@ -311,6 +533,40 @@ impl NatsClient {
         self.js_request(&subject, &req)
     }
 
+    /// Make sure that a `JetStream` stream with the `desired` configuration
+    /// exists, creating it if it does not exist yet and updating it if its
+    /// configuration differs, as decided by `StreamConfig::reconcile`.
+    /// Returns the information of the stream and what was done to it.
+    ///
+    /// Streams that differ in settings that can not be updated are left
+    /// unchanged, and an error wrapping an `ImmutableConflict` is
+    /// returned.
+    pub fn ensure_stream(
+        &self,
+        desired: &StreamConfig,
+    ) -> io::Result<(StreamInfo, ReconcileAction)> {
+        validate_name("stream", &desired.name)?;
+        let subject =
+            format!("{}STREAM.INFO.{}", self.api_prefix(), desired.name);
+        let existing = match self.js_api_request::<StreamInfo>(&subject, b"")? {
+            Ok(info) => Some(info),
+            Err(error) if error.is_stream_not_found() || error.code == 404 => {
+                None
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let action = desired.reconcile(existing.as_ref().map(|i| &i.config))?;
+        let info = match (action, existing) {
+            (ReconcileAction::Unchanged, Some(info)) => info,
+            (ReconcileAction::Created, _) => {
+                self.create_stream::<&StreamConfig>(desired)?
+            }
+            _ => self.update_stream(desired)?,
+        };
+        Ok((info, action))
+    }
+
     /// List all `JetStream` stream names. If you also want stream information,
     /// use the `list_streams` method instead.
     pub fn stream_names(&self) -> PagedIterator<'_, String> {
@@ -378,6 +634,41 @@ impl NatsClient {
         self.js_request(&subject, b"")
     }
 
+    /// Query the number of messages per subject in a `JetStream` stream, for
+    /// all subjects matching `filter`. Servers return large sets of subjects
+    /// in pages, and this requests pages until the set is complete.
+    pub fn stream_subjects<S: AsRef<str>>(
+        &self,
+        stream: S,
+        filter: &str,
+    ) -> io::Result<HashMap<String, u64>> {
+        let stream: &str = stream.as_ref();
+        if stream.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the stream name must not be empty",
+            ));
+        }
+        let subject: String =
+            format!("{}STREAM.INFO.{}", self.api_prefix(), stream);
+
+        let mut req = StreamInfoRequest {
+            subjects_filter: filter.to_string(),
+            offset: 0,
+        };
+        let mut subjects: SubjectsPage =
+            self.js_request(&subject, &serde_json::ser::to_vec(&req)?)?;
+
+        while let Some(offset) = subjects.next_offset() {
+            req.offset = offset;
+            let page: SubjectsPage =
+                self.js_request(&subject, &serde_json::ser::to_vec(&req)?)?;
+            subjects.extend(page);
+        }
+
+        Ok(subjects.subjects)
+    }
+
     /// Purge `JetStream` stream messages.
     pub fn purge_stream<S: AsRef<str>>(
         &self,
@@ -394,6 +685,85 @@ impl NatsClient {
         self.js_request(&subject, b"")
     }
 
+    /// Purge the messages selected by a `PurgeRequest` from a `JetStream`
+    /// stream.
+    pub fn purge_stream_with<S: AsRef<str>>(
+        &self,
+        stream: S,
+        req: &PurgeRequest,
+    ) -> io::Result<PurgeResponse> {
+        let stream: &str = stream.as_ref();
+        if stream.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the stream name must not be empty",
+            ));
+        }
+        req.validate()?;
+        let subject = format!("{}STREAM.PURGE.{}", self.api_prefix(), stream);
+        let req = serde_json::ser::to_vec(req)?;
+        self.js_request(&subject, &req)
+    }
+
+    /// Create a `JetStream` stream that is guaranteed to start out empty.
+    ///
+    /// Creating a stream that already exists with an identical
+    /// configuration succeeds without touching its messages, in which case
+    /// the existing messages are purged, see `PurgeRequest::emptying`. A
+    /// purged stream continues at the sequence after its last message, or
+    /// at `first_seq` if that is set on the configuration and higher.
+    pub fn create_empty_stream<S>(
+        &self,
+        stream_config: S,
+    ) -> io::Result<StreamInfo>
+    where
+        StreamConfig: From<S>,
+    {
+        let cfg: StreamConfig = stream_config.into();
+        let info = self.create_stream::<&StreamConfig>(&cfg)?;
+        if info.state.is_empty() {
+            return Ok(info);
+        }
+
+        let req = PurgeRequest::emptying(&info.state, cfg.first_seq);
+        self.purge_stream_with(&cfg.name, &req)?;
+        self.stream_info(&cfg.name)
+    }
+
+    /// Publishes a message to the stream capturing `subject` and waits for
+    /// the stream to acknowledge it, sending `opts` along as headers, see
+    /// `PubOpts::headers`. The acknowledgement tells which stream stored
+    /// the message under which sequence, or whether it was dropped as a
+    /// duplicate of an earlier message with the same `PubOpts::id`.
+    ///
+    /// Only the server checks whether the options suit the stream. To
+    /// catch a `ttl` for a stream without `allow_msg_ttl` up front, check
+    /// them with `PubOpts::validate_for_stream` first.
+    pub fn publish_with_opts(
+        &self,
+        subject: &str,
+        msg: impl AsRef<[u8]>,
+        opts: &PubOpts,
+    ) -> io::Result<PubAck> {
+        let headers = opts.headers();
+        let headers = Some(&headers).filter(|headers| !headers.is_empty());
+        let reply = self.new_inbox();
+        let sub = self.subscribe(&reply)?;
+        self.publish_with_reply_or_headers(
+            subject,
+            Some(&reply),
+            headers,
+            msg,
+        )?;
+
+        let res_msg = sub
+            .next()
+            .ok_or_else(|| Error::from(ErrorKind::ConnectionReset))?;
+        ApiResponse::<PubAck>::from_reply(subject, &res_msg)?
+            .into_result()
+            .map_err(Error::from)
+    }
+
     /// Delete message in a `JetStream` stream.
     pub fn delete_message<S: AsRef<str>>(
         &self,
@@ -416,7 +786,7 @@ impl NatsClient {
         let subject =
             format!("{}STREAM.MSG.DELETE.{}", self.api_prefix(), stream);
 
-        self.js_request::<DeleteResponse>(&subject, &req)
+        self.js_request::<SuccessResponse>(&subject, &req)
             .map(|dr| dr.success)
     }
 
@@ -431,7 +801,7 @@ impl NatsClient {
         }
 
         let subject = format!("{}STREAM.DELETE.{}", self.api_prefix(), stream);
-        self.js_request::<DeleteResponse>(&subject, b"")
+        self.js_request::<SuccessResponse>(&subject, b"")
             .map(|dr| dr.success)
     }
 
@@ -454,29 +824,130 @@ impl NatsClient {
             ));
         }
 
-        let subject = if let Some(ref durable_name) = config.durable_name {
-            format!(
-                "{}CONSUMER.DURABLE.CREATE.{}.{}",
-                self.api_prefix(),
-                stream,
-                durable_name
-            )
-        } else {
-            format!("{}CONSUMER.CREATE.{}", self.api_prefix(), stream)
-        };
-
         let req = CreateConsumerRequest {
-            stream_name: stream.into(),
+            stream_name: StreamName::try_from(stream)?,
             config: config.clone(),
+            action: ConsumerAction::CreateOrUpdate,
         };
 
         let ser_req = serde_json::ser::to_vec(&req)?;
 
-        let _info: ConsumerInfo = self.js_request(&subject, &ser_req)?;
+        let _info: ConsumerInfo =
+            self.js_request(&self.create_consumer_subject(&req), &ser_req)?;
 
         Consumer::existing::<&str, ConsumerConfig>(self.clone(), stream, config)
     }
 
+    /// Create a `JetStream` consumer for each of `configs`, returning the
+    /// result of each in the order of `configs` instead of stopping at the
+    /// first consumer that the server fails to create. If the stream does
+    /// not exist, every consumer fails with the same error.
+    ///
+    /// Only an invalid stream name and errors of the connection itself,
+    /// such as timeouts, fail the whole call.
+    pub fn create_consumers<S: AsRef<str>>(
+        &self,
+        stream: S,
+        configs: Vec<ConsumerConfig>,
+    ) -> io::Result<Vec<Result<ConsumerInfo, ApiError>>> {
+        let stream = StreamName::try_from(stream.as_ref())?;
+        let mut connection_error = None;
+
+        let results = create_consumers(&stream, configs, |req| {
+            if connection_error.is_some() {
+                return Err(ApiError::default());
+            }
+            let res = serde_json::ser::to_vec(req)
+                .map_err(io::Error::from)
+                .and_then(|ser_req| {
+                    self.js_api_request(
+                        &self.create_consumer_subject(req),
+                        &ser_req,
+                    )
+                });
+            res.unwrap_or_else(|error| {
+                connection_error = Some(error);
+                Err(ApiError::default())
+            })
+        });
+
+        match connection_error {
+            Some(error) => Err(error),
+            None => Ok(results),
+        }
+    }
+
+    /// Make sure that the durable `JetStream` consumer with the `desired`
+    /// configuration exists on `stream`, creating it if it does not exist
+    /// yet and updating it if its configuration differs, as decided by
+    /// `ConsumerConfig::reconcile`. Returns the information of the
+    /// consumer and what was done to it.
+    ///
+    /// Consumers that differ in settings that can not be updated are left
+    /// unchanged, and an error wrapping an `ImmutableConflict` is
+    /// returned. The `desired` configuration needs a `durable_name`.
+    pub fn ensure_consumer<S: AsRef<str>>(
+        &self,
+        stream: S,
+        desired: &ConsumerConfig,
+    ) -> io::Result<(ConsumerInfo, ReconcileAction)> {
+        let stream = StreamName::try_from(stream.as_ref())?;
+        let durable_name =
+            desired.durable_name.as_deref().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "ensuring a consumer requires a durable_name",
+                )
+            })?;
+        validate_name("consumer", durable_name)?;
+
+        let subject = format!(
+            "{}CONSUMER.INFO.{}.{}",
+            self.api_prefix(),
+            stream,
+            durable_name
+        );
+        let existing =
+            match self.js_api_request::<ConsumerInfo>(&subject, b"")? {
+                Ok(info) => Some(info),
+                Err(error) if error.is_consumer_not_found() => None,
+                Err(error) => return Err(error.into()),
+            };
+
+        let action = desired.reconcile(existing.as_ref().map(|i| &i.config))?;
+        if let (ReconcileAction::Unchanged, Some(info)) = (action, &existing) {
+            return Ok((info.clone(), action));
+        }
+
+        let req = CreateConsumerRequest {
+            stream_name: stream,
+            config: desired.clone(),
+            action: if action == ReconcileAction::Created {
+                ConsumerAction::Create
+            } else {
+                ConsumerAction::Update
+            },
+        };
+        let info = self.js_request(
+            &self.create_consumer_subject(&req),
+            &serde_json::ser::to_vec(&req)?,
+        )?;
+        Ok((info, action))
+    }
+
+    fn create_consumer_subject(&self, req: &CreateConsumerRequest) -> String {
+        if let Some(ref durable_name) = req.config.durable_name {
+            format!(
+                "{}CONSUMER.DURABLE.CREATE.{}.{}",
+                self.api_prefix(),
+                req.stream_name,
+                durable_name
+            )
+        } else {
+            format!("{}CONSUMER.CREATE.{}", self.api_prefix(), req.stream_name)
+        }
+    }
+
     /// Delete a `JetStream` consumer.
     pub fn delete_consumer<S, C>(
         &self,
@@ -509,7 +980,7 @@ impl NatsClient {
             consumer
         );
 
-        self.js_request::<DeleteResponse>(&subject, b"")
+        self.js_request::<SuccessResponse>(&subject, b"")
             .map(|dr| dr.success)
     }
 
@@ -545,29 +1016,39 @@ impl NatsClient {
         self.js_request(&format!("{}INFO", self.api_prefix()), b"")
     }
 
-    fn js_request<Res>(&self, subject: &str, req: &[u8]) -> io::Result<Res>
+    pub(crate) fn js_request<Res>(
+        &self,
+        subject: &str,
+        req: &[u8],
+    ) -> io::Result<Res>
+    where
+        Res: DeserializeOwned,
+    {
+        self.js_api_request(subject, req)?.map_err(io::Error::from)
+    }
+
+    /// Like `js_request`, but keeps errors returned by the server separate
+    /// from errors of the connection.
+    pub(crate) fn js_api_request<Res>(
+        &self,
+        subject: &str,
+        req: &[u8],
+    ) -> io::Result<Result<Res, ApiError>>
     where
         Res: DeserializeOwned,
     {
         let res_msg = self.request(subject, req)?;
-        let res: ApiResponse<Res> = serde_json::de::from_slice(&res_msg.data)?;
-        match res {
-            ApiResponse::Ok(stream_info) => Ok(stream_info),
-            ApiResponse::Err { error, .. } => {
-                log::error!(
-                    "failed to parse API response: {:?}",
-                    std::str::from_utf8(&res_msg.data)
-                );
-                if let Some(desc) = error.description {
-                    Err(Error::new(ErrorKind::Other, desc))
-                } else {
-                    Err(Error::new(ErrorKind::Other, "unknown"))
-                }
-            }
-        }
+        let res = ApiResponse::<Res>::from_reply(subject, &res_msg)?;
+        Ok(res.into_result().map_err(|error| {
+            log::error!(
+                "failed to parse API response: {:?}",
+                std::str::from_utf8(&res_msg.data)
+            );
+            error
+        }))
     }
 
-    fn api_prefix(&self) -> &str {
+    pub(crate) fn api_prefix(&self) -> &str {
         &self.0.client.options.jetstream_prefix
     }
 }
@@ -591,7 +1072,7 @@ pub struct Consumer {
     /// out during `process` and `process_batch`. Defaults
     /// to 5ms, which is likely to be far too low for
     /// workloads crossing physical sites.
-    pub timeout: Duration,
+    pub timeout: time::Duration,
 
     /// Contains ranges of processed messages that will be
     /// filtered out upon future receipt.
@@ -676,7 +1157,7 @@ impl Consumer {
             stream,
             cfg,
             push_subscriber,
-            timeout: Duration::from_millis(5),
+            timeout: time::Duration::from_millis(5),
             dedupe_window,
         })
     }
@@ -956,12 +1437,67 @@ impl Consumer {
     /// how the server will keep track of this batch request over time. See the docs for
     /// `NextRequest` for more information about the options.
     ///
+    /// The `expires` of the request is clamped to the `max_expires` of the
+    /// consumer's configuration if it has one, see
+    /// `NextRequest::clamp_expires`, so that the server does not reject it.
+    ///
     /// This is a lower-level method and does not filter messages through the `Consumer`'s
     /// built-in `dedupe_window` as the various `process*` methods do.
     pub fn pull_opt(
         &mut self,
         next_request: NextRequest,
     ) -> io::Result<crate::Subscription> {
+        let subject = self.next_subject()?;
+        let next_request = next_request.clamp_expires(self.cfg.max_expires);
+        next_request.validate()?;
+        let req = serde_json::ser::to_vec(&next_request)
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+        self.nc.request_multi(&subject, &req)
+    }
+
+    /// For pull-based consumers, returns a `PullDrain` that requests
+    /// batches of up to `batch` messages until no messages are left.
+    /// The replies to each request are awaited for up to the `Consumer`'s
+    /// configured `timeout`.
+    ///
+    /// Like `pull_opt`, this does not filter messages through the
+    /// `Consumer`'s built-in `dedupe_window`.
+    pub fn drain(&mut self, batch: usize) -> io::Result<PullDrain> {
+        let subject = self.next_subject()?;
+        let nc = self.nc.clone();
+        let timeout = self.timeout;
+
+        Ok(PullDrain::new(
+            NextRequest {
+                batch,
+                ..Default::default()
+            },
+            move |req| {
+                let sub =
+                    nc.request_multi(&subject, &serde_json::to_vec(req)?)?;
+                let mut replies = vec![];
+                while replies.len() < req.batch {
+                    let reply = match sub.next_timeout(timeout) {
+                        Ok(reply) => reply,
+                        Err(e) if e.kind() == ErrorKind::TimedOut => break,
+                        Err(e) => return Err(e),
+                    };
+                    let is_status = reply.headers.as_ref().map_or(false, |h| {
+                        h.get_first(Headers::STATUS).is_some()
+                    });
+                    replies.push(reply);
+                    if is_status {
+                        break;
+                    }
+                }
+                Ok(replies)
+            },
+        ))
+    }
+
+    /// Returns the subject of pull requests, failing unless this is a
+    /// durable pull-based consumer.
+    fn next_subject(&self) -> io::Result<String> {
         if self.cfg.durable_name.is_none() {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
@@ -978,15 +1514,12 @@ impl Consumer {
             ));
         }
 
-        let subject = format!(
+        Ok(format!(
             "{}CONSUMER.MSG.NEXT.{}.{}",
             self.api_prefix(),
             self.stream,
             self.cfg.durable_name.as_ref().unwrap()
-        );
-
-        let req = serde_json::ser::to_vec(&next_request).unwrap();
-        self.nc.request_multi(&subject, &req)
+        ))
     }
 
     fn api_prefix(&self) -> &str {
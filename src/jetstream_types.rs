@@ -1,39 +1,547 @@
-use std::time::UNIX_EPOCH;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt,
+    hash::{Hash, Hasher},
+    io,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+    time::{self, SystemTime, UNIX_EPOCH},
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
 
-use chrono::{DateTime as ChronoDateTime, Utc};
+use chrono::{DateTime as ChronoDateTime, SecondsFormat, TimeZone, Utc};
 
-/// A UTC time
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+use crate::{duration::Duration, Headers, Message};
+
+/// A UTC time, serialized in RFC 3339 format with a `Z` suffix, e.g.
+/// `2021-07-01T12:00:00.5Z`, as the server expects. Times with another
+/// offset are converted to UTC when parsed or converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct DateTime(pub ChronoDateTime<Utc>);
 
+impl Serialize for DateTime {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer
+            .serialize_str(&self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+}
+
+impl<'de> Deserialize<'de> for DateTime {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl FromStr for DateTime {
+    type Err = chrono::ParseError;
+
+    /// Parses an RFC 3339 time with any offset, converting it to UTC.
+    fn from_str(s: &str) -> Result<DateTime, chrono::ParseError> {
+        ChronoDateTime::parse_from_rfc3339(s).map(DateTime::from)
+    }
+}
+
 impl Default for DateTime {
     fn default() -> DateTime {
         DateTime(UNIX_EPOCH.into())
     }
 }
 
+impl<Tz: TimeZone> From<ChronoDateTime<Tz>> for DateTime {
+    fn from(time: ChronoDateTime<Tz>) -> DateTime {
+        DateTime(time.with_timezone(&Utc))
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> DateTime {
+        DateTime(time.into())
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for DateTime {
+    type Parameters = ();
+    type Strategy = proptest::strategy::Map<
+        (std::ops::Range<i64>, std::ops::Range<u32>),
+        fn((i64, u32)) -> DateTime,
+    >;
+
+    /// Generates times in the years 0000 through 9999, which are all the
+    /// RFC 3339 format can represent.
+    fn arbitrary_with((): ()) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+
+        (-62_167_219_200..253_402_300_800, 0..1_000_000_000).prop_map(
+            |(secs, nanos)| DateTime(Utc.timestamp_opt(secs, nanos).unwrap()),
+        )
+    }
+}
+
+impl DateTime {
+    /// Returns the time that has passed since this time according to the
+    /// local clock, or `None` if it lies in the future.
+    pub(crate) fn elapsed(&self) -> Option<time::Duration> {
+        Utc::now().signed_duration_since(self.0).to_std().ok()
+    }
+
+    /// Returns `true` for the zero time `0001-01-01T00:00:00Z`, which the
+    /// server reports for timestamps that are not set.
+    fn is_zero(&self) -> bool {
+        const ZERO_TIME_SECS: i64 = -62_135_596_800;
+        self.0.timestamp() == ZERO_TIME_SECS
+            && self.0.timestamp_subsec_nanos() == 0
+    }
+}
+
+/// Deserializes an optional timestamp, treating the zero time that the
+/// server reports for unset timestamps as `None`.
+fn zero_time_as_none<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = Option::<DateTime>::deserialize(deserializer)?;
+    Ok(time.filter(|time| !time.is_zero()))
+}
+
 #[derive(Serialize)]
 pub(crate) struct DeleteRequest {
     pub seq: u64,
 }
 
-#[derive(Deserialize)]
-pub(crate) struct DeleteResponse {
+/// `ApiResponse` is a standard response from the `JetStream` JSON Api,
+/// which is either the expected response or an error.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ApiResponse<T> {
+    /// The request succeeded
+    Ok(T),
+    /// The request failed
+    Err {
+        /// The type of the response that was expected
+        r#type: String,
+        /// Why the request failed
+        error: ApiError,
+    },
+}
+
+impl<T> ApiResponse<T> {
+    /// Converts the response into a `Result`, so that errors can be
+    /// propagated with `?`.
+    pub fn into_result(self) -> Result<T, ApiError> {
+        self.into()
+    }
+}
+
+impl<T: serde::de::DeserializeOwned> ApiResponse<T> {
+    /// Decodes the reply to a request sent to the API `subject`.
+    ///
+    /// Returns an `ErrorKind::NotFound` error wrapping `NoResponders` if
+    /// the reply is the `503` status message the server sends when nothing
+    /// is subscribed to the subject, and an error if the reply is not
+    /// valid JSON.
+    pub fn from_reply(subject: &str, reply: &Message) -> io::Result<Self> {
+        if reply.is_no_responders() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                NoResponders {
+                    subject: subject.to_string(),
+                },
+            ));
+        }
+        Ok(serde_json::from_slice(&reply.data)?)
+    }
+}
+
+/// The error wrapped in the `io::Error` returned when nothing answers a
+/// request to the `JetStream` API, usually because `JetStream` is not
+/// enabled on the server or in the account, or because the API prefix or
+/// domain is wrong. Unlike an `ApiError`, it does not come from
+/// `JetStream` itself. It can be recovered with `io::Error::get_ref` and
+/// `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoResponders {
+    /// The API subject that the request was sent to
+    pub subject: String,
+}
+
+impl fmt::Display for NoResponders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no responders for {}, JetStream may not be enabled",
+            self.subject
+        )
+    }
+}
+
+impl std::error::Error for NoResponders {}
+
+impl<T> From<ApiResponse<T>> for Result<T, ApiError> {
+    fn from(res: ApiResponse<T>) -> Result<T, ApiError> {
+        match res {
+            ApiResponse::Ok(res) => Ok(res),
+            ApiResponse::Err { error, .. } => Err(error),
+        }
+    }
+}
+
+/// `ApiError` is included in all Api responses if there was an error.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ApiError {
+    /// The HTTP-like status code of the error, e.g. 404 if the stream or
+    /// consumer does not exist
+    pub code: usize,
+    /// The code that identifies the kind of error more precisely than
+    /// `code`, reported by servers since 2.3, e.g.
+    /// `ApiError::STREAM_NOT_FOUND`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub err_code: Option<u64>,
+    /// A description of the error
+    pub description: Option<String>,
+}
+
+impl ApiError {
+    /// The `err_code` of the error returned for requests to a stream that
+    /// does not exist.
+    pub const STREAM_NOT_FOUND: u64 = 10059;
+
+    /// The `err_code` of the error returned for requests to a consumer
+    /// that does not exist.
+    pub const CONSUMER_NOT_FOUND: u64 = 10014;
+
+    /// Returns `true` if the request failed because the stream does not
+    /// exist.
+    pub fn is_stream_not_found(&self) -> bool {
+        self.err_code == Some(ApiError::STREAM_NOT_FOUND)
+    }
+
+    /// Returns `true` if the request failed because the consumer does not
+    /// exist.
+    pub fn is_consumer_not_found(&self) -> bool {
+        self.err_code == Some(ApiError::CONSUMER_NOT_FOUND)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description.as_deref().unwrap_or("unknown"))
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<ApiError> for io::Error {
+    /// Converts the error into an `ErrorKind::Other` error with the
+    /// description of the `ApiError`, as returned by the `Connection`
+    /// methods.
+    fn from(error: ApiError) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, error.to_string())
+    }
+}
+
+/// The response to a request that returns nothing but whether it
+/// succeeded, i.e. deleting a stream, a consumer or a message. Errors,
+/// such as deleting a stream that does not exist, are reported as an
+/// `ApiResponse::Err`. The response to purging a stream additionally
+/// reports the number of purged messages, see `PurgeResponse`.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq,
+)]
+pub struct SuccessResponse {
+    /// Whether the request was successful
     pub success: bool,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub(crate) struct CreateConsumerRequest {
-    pub stream_name: String,
+/// The validated name of a stream, see `validate_name` for the rules.
+/// Names received from the server are not validated again.
+#[derive(
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(transparent)]
+pub struct StreamName(String);
+
+/// The validated name of a consumer, see `validate_name` for the rules.
+/// Names received from the server are not validated again.
+#[derive(
+    Debug,
+    Default,
+    Serialize,
+    Deserialize,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(transparent)]
+pub struct ConsumerName(String);
+
+/// Checks that a stream or consumer name is valid, returning an
+/// `ErrorKind::InvalidInput` error otherwise. Names appear as tokens in
+/// API subjects, so they must be non-empty and may not contain `.`, the
+/// wildcards `*` and `>`, path separators or whitespace.
+pub fn validate_name(kind: &str, name: &str) -> io::Result<()> {
+    if name.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the {} name must not be empty", kind),
+        ));
+    }
+    if let Some(c) = name.chars().find(|c| {
+        matches!(c, '.' | '*' | '>' | '/' | '\\') || c.is_whitespace()
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the {} name {:?} contains the invalid character {:?}",
+                kind, name, c
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Collects the names of the given fields that differ between `$a` and
+/// `$b`, in the order they are listed.
+macro_rules! changed_fields {
+    ($a:expr, $b:expr, [$($field:ident),* $(,)?]) => {{
+        let mut fields: Vec<&'static str> = vec![];
+        $(
+            if $a.$field != $b.$field {
+                fields.push(stringify!($field));
+            }
+        )*
+        fields
+    }};
+}
+
+macro_rules! impl_name {
+    ($name:ident, $kind:expr) => {
+        impl $name {
+            /// Returns the name as a string slice.
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = io::Error;
+
+            fn try_from(name: String) -> io::Result<$name> {
+                validate_name($kind, &name)?;
+                Ok($name(name))
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = io::Error;
+
+            fn try_from(name: &str) -> io::Result<$name> {
+                $name::try_from(name.to_string())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = io::Error;
+
+            fn from_str(name: &str) -> io::Result<$name> {
+                $name::try_from(name)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(name: $name) -> String {
+                name.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+    };
+}
+
+impl_name!(StreamName, "stream");
+impl_name!(ConsumerName, "consumer");
+
+/// The request sent to the server to create a consumer.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
+pub struct CreateConsumerRequest {
+    /// The stream that the consumer will read from
+    pub stream_name: StreamName,
+    /// The configuration of the consumer
     pub config: ConsumerConfig,
+    /// Whether the consumer may be created, updated or both, see
+    /// `ConsumerAction`. Requires a server since 2.10.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub action: ConsumerAction,
+}
+
+impl CreateConsumerRequest {
+    /// Checks this request for values that the server rejects, returning
+    /// an `ErrorKind::InvalidInput` error for the first one found, and
+    /// otherwise the `Lint`s of `ConsumerConfig::validate`.
+    ///
+    /// Only durable consumers can be updated, so `ConsumerAction::Update`
+    /// requires a `durable_name`.
+    pub fn validate(&self) -> io::Result<Vec<Lint>> {
+        if self.action == ConsumerAction::Update
+            && self.config.durable_name.is_none()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "updating a consumer requires a durable_name",
+            ));
+        }
+        self.config.validate()
+    }
+
+    /// Checks that the server would carry out the request's `action`
+    /// given the `existing` consumer of the same name, if any. Returns an
+    /// `ErrorKind::AlreadyExists` error when creating a consumer that
+    /// exists with a different configuration, because that would be an
+    /// update, and an `ErrorKind::NotFound` error when updating a
+    /// consumer that does not exist. Recreating a consumer with its
+    /// current configuration is accepted, as the server does, which is
+    /// decided by `ConsumerConfig::semantically_eq` so that the defaults
+    /// the server filled into `existing` do not count as differences.
+    pub fn validate_action(
+        &self,
+        existing: Option<&ConsumerInfo>,
+    ) -> io::Result<()> {
+        match (self.action, existing) {
+            (ConsumerAction::Create, Some(existing))
+                if !self.config.semantically_eq(&existing.config) =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "the consumer {} already exists with a different \
+                         configuration",
+                        existing.name
+                    ),
+                ))
+            }
+            (ConsumerAction::Update, None) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "the consumer to update does not exist",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// determines whether a `CreateConsumerRequest` may create a new consumer,
+/// update an existing one, or both.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ConsumerAction {
+    /// The consumer is created if it does not exist and updated
+    /// otherwise. This is the default.
+    #[serde(rename = "")]
+    CreateOrUpdate = 0,
+    /// The consumer is only created, failing if it exists with a
+    /// different configuration.
+    #[serde(rename = "create")]
+    Create = 1,
+    /// The consumer is only updated, failing if it does not exist.
+    #[serde(rename = "update")]
+    Update = 2,
+    /// A value reported by a newer server that this client does not know
+    /// about. Requests containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
+}
+
+impl Default for ConsumerAction {
+    fn default() -> ConsumerAction {
+        ConsumerAction::CreateOrUpdate
+    }
+}
+
+impl From<&ConsumerInfo> for CreateConsumerRequest {
+    /// Builds a request that recreates the consumer described by
+    /// `ConsumerInfo`, e.g. on another stream or in another cluster.
+    ///
+    /// Settings that the server fills in on its own and rejects when
+    /// explicitly given are removed: `max_waiting` only applies to
+    /// pull-based consumers, but may be reported for push-based ones.
+    fn from(info: &ConsumerInfo) -> CreateConsumerRequest {
+        let mut config = info.config.clone();
+        if config.is_push() {
+            config.max_waiting = 0;
+        }
+
+        CreateConsumerRequest {
+            stream_name: StreamName(info.stream_name.clone()),
+            config,
+            action: ConsumerAction::CreateOrUpdate,
+        }
+    }
 }
 
 /// Configuration for consumers. From a high level, the
 /// `durable_name` and `deliver_subject` fields have a particularly
 /// strong influence on the consumer's overall behavior.
-#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+///
+/// It can only be serialized with self-describing formats, as there is
+/// no counterpart to `CompactStreamConfig` for consumers.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
 pub struct ConsumerConfig {
     /// Setting `deliver_subject` to `Some(...)` will cause this consumer
     /// to be "push-based". This is analogous in some ways to a normal
@@ -86,6 +594,7 @@ pub struct ConsumerConfig {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub durable_name: Option<String>,
     /// Allows for a variety of options that determine how this consumer will receive messages
+    #[serde(default)]
     pub deliver_policy: DeliverPolicy,
     /// Used in combination with `DeliverPolicy::ByStartSeq` to only select messages arriving
     /// after this sequence number.
@@ -96,20 +605,37 @@ pub struct ConsumerConfig {
     #[serde(default, skip_serializing_if = "is_default")]
     pub opt_start_time: Option<DateTime>,
     /// How messages should be acknowledged
+    #[serde(default)]
     pub ack_policy: AckPolicy,
     /// How long to allow messages to remain un-acknowledged before attempting redelivery
     #[serde(default, skip_serializing_if = "is_default")]
-    pub ack_wait: i64,
+    pub ack_wait: Duration,
     /// Maximum number of times a specific message will be delivered. Use this to avoid poison pill messages that repeatedly crash your consumer processes forever.
-    #[serde(default, skip_serializing_if = "is_default")]
-    pub max_deliver: i64,
+    ///
+    /// Both `None` and `Some(-1)` mean unlimited, which is what the server
+    /// reports as -1. Both are omitted when serializing, and -1 is
+    /// deserialized as `None`, so that either compares equal to the
+    /// configuration reported by the server. See
+    /// `ConsumerConfig::max_deliver_is_unlimited`.
+    #[serde(
+        default,
+        deserialize_with = "unlimited_as_none",
+        skip_serializing_if = "is_unlimited"
+    )]
+    pub max_deliver: Option<i64>,
     /// When consuming from a Stream with many subjects, or wildcards, this selects only specific incoming subjects. Supports wildcards.
     #[serde(default, skip_serializing_if = "is_default")]
     pub filter_subject: String,
     /// Whether messages are sent as quickly as possible or at the rate of receipt
+    #[serde(default)]
     pub replay_policy: ReplayPolicy,
-    /// The rate of message delivery in bits per second
-    #[serde(default, skip_serializing_if = "is_default")]
+    /// The rate of message delivery in bits per second. Also accepts
+    /// `rate_limit_bps`, the name the server reports it under.
+    #[serde(
+        default,
+        alias = "rate_limit_bps",
+        skip_serializing_if = "is_default"
+    )]
     pub rate_limit: i64,
     /// What percentage of acknowledgements should be samples for observability, 0-100
     #[serde(default, skip_serializing_if = "is_default")]
@@ -122,97 +648,2608 @@ pub struct ConsumerConfig {
     /// this consumer.
     #[serde(default, skip_serializing_if = "is_default")]
     pub max_ack_pending: i64,
+    /// How many replicas to keep of this consumer's state in a clustered
+    /// JetStream, 0 to inherit the replication of the stream
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub num_replicas: usize,
+    /// The queue group that push-based consumers deliver to, so that each
+    /// message is only received by one of the subscribers in the group.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver_group: Option<String>,
+    /// The largest batch that a single pull request may ask for. Only
+    /// applies to pull-based consumers.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub max_batch: i64,
+    /// The longest time that a single pull request may wait for
+    /// messages. Only applies to pull-based consumers.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub max_expires: Duration,
+    /// Makes the server pause delivery after sending a flow control
+    /// message, until the subscriber responds to it. Only applies to
+    /// push-based consumers, and requires an `idle_heartbeat`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub flow_control: bool,
+    /// How often the server sends a heartbeat while there are no
+    /// messages to deliver, which lets the subscriber detect a stalled
+    /// consumer and messages lost at the end of the stream. Only applies
+    /// to push-based consumers.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub idle_heartbeat: Duration,
+    /// Creates the consumer paused until the given time, so that it only
+    /// starts delivering messages then. A time in the past means that
+    /// the consumer is not paused. Requires a server since 2.11; a
+    /// running consumer is paused with a separate pause request instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_until: Option<DateTime>,
+    /// The names of the groups that pull requests may target to take
+    /// part in distributing this consumer's messages according to the
+    /// `priority_policy`, which is required with them. Requires a server
+    /// since 2.11.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_groups: Option<Vec<String>>,
+    /// How messages are distributed among the pull requests of the
+    /// `priority_groups`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_policy: Option<PriorityPolicy>,
+    /// Arbitrary information about the consumer, supported by servers
+    /// since 2.10
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub metadata: BTreeMap<String, String>,
+    /// Fields reported by a newer server that this client does not know
+    /// about. Flattening an empty map adds nothing to the serialized
+    /// config.
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
-impl From<&ConsumerConfig> for ConsumerConfig {
-    fn from(cc: &ConsumerConfig) -> ConsumerConfig {
-        cc.clone()
-    }
-}
+impl ConsumerConfig {
+    /// The `max_ack_pending` used by the `durable_pull` preset. This is
+    /// the same limit that the server applies when none is given, but
+    /// setting it explicitly keeps the flow control of the consumer
+    /// visible in its configuration.
+    pub const DEFAULT_MAX_ACK_PENDING: i64 = 1000;
 
-impl From<&str> for ConsumerConfig {
-    fn from(s: &str) -> ConsumerConfig {
+    /// The `max_waiting` used by the `durable_pull` preset, which is also
+    /// the number of waiting pull requests that the server allows when
+    /// none is given.
+    pub const DEFAULT_MAX_WAITING: i64 = 512;
+
+    /// Creates the configuration for the most common kind of consumer:
+    /// a durable, pull-based consumer that starts with the oldest message
+    /// in the stream, requires every message to be acknowledged
+    /// explicitly, and allows up to `DEFAULT_MAX_ACK_PENDING`
+    /// unacknowledged messages to be in flight at once and up to
+    /// `DEFAULT_MAX_WAITING` pull requests to wait for messages.
+    pub fn durable_pull(name: &str) -> ConsumerConfig {
         ConsumerConfig {
-            durable_name: Some(s.to_string()),
+            durable_name: Some(name.to_string()),
+            deliver_policy: DeliverPolicy::All,
+            ack_policy: AckPolicy::Explicit,
+            max_ack_pending: ConsumerConfig::DEFAULT_MAX_ACK_PENDING,
+            max_waiting: ConsumerConfig::DEFAULT_MAX_WAITING,
             ..Default::default()
         }
     }
-}
 
-/// `StreamConfig` determines the properties for a stream.
-/// There are sensible defaults for most. If no subjects are
-/// given the name will be used as the only subject.
-#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct StreamConfig {
-    /// A name for the Stream. Must not have spaces, tabs or period `.` characters
-    pub name: String,
-    /// How large the Stream may become in total bytes before the configured discard policy kicks in
-    pub max_bytes: i64,
-    /// How large the Stream may become in total messages before the configured discard policy kicks in
-    pub max_msgs: i64,
-    /// Maximum amount of messages to keep per subject
-    pub max_msgs_per_subject: i64,
-    /// When a Stream has reached its configured `max_bytes` or `max_msgs`, this policy kicks in.
-    /// `DiscardPolicy::New` refuses new messages or `DiscardPolicy::Old` (default) deletes old messages to make space
-    pub discard: DiscardPolicy,
-    /// Which NATS subjects to populate this stream with. Supports wildcards. Defaults to just the
-    /// configured stream `name`.
-    #[serde(default, skip_serializing_if = "is_default")]
-    pub subjects: Option<Vec<String>>,
-    /// How message retention is considered, `Limits` (default), `Interest` or `WorkQueue`
-    pub retention: RetentionPolicy,
-    /// How many Consumers can be defined for a given Stream, -1 for unlimited
-    pub max_consumers: i32,
-    /// Maximum age of any message in the stream, expressed in nanoseconds
-    pub max_age: i64,
-    /// The largest message that will be accepted by the Stream
-    #[serde(default, skip_serializing_if = "is_default")]
-    pub max_msg_size: i32,
-    /// The type of storage backend, `File` (default) and `Memory`
-    pub storage: StorageType,
-    /// How many replicas to keep for each message in a clustered JetStream, maximum 5
-    pub num_replicas: usize,
-    /// Disables acknowledging messages that are received by the Stream
-    #[serde(default, skip_serializing_if = "is_default")]
-    pub no_ack: bool,
-    /// The window within which to track duplicate messages.
-    #[serde(default, skip_serializing_if = "is_default")]
-    pub duplicate_window: i64,
-    /// The owner of the template associated with this stream.
-    #[serde(default, skip_serializing_if = "is_default")]
-    pub template_owner: String,
-}
+    /// Serializes this configuration in the form accepted by
+    /// `nats consumer add --config`, which expects durations such
+    /// as `ack_wait` as human-readable strings like `"30s"` rather
+    /// than as nanoseconds.
+    pub fn to_cli_json(&self) -> io::Result<String> {
+        to_cli_json(self, &["ack_wait", "max_expires", "idle_heartbeat"])
+    }
 
-fn is_default<T: Default + Eq>(t: &T) -> bool {
-    t == &T::default()
-}
+    /// Delivers every message still in the stream, setting
+    /// `deliver_policy` to `DeliverPolicy::All` and clearing the start
+    /// position.
+    pub fn deliver_all(self) -> ConsumerConfig {
+        self.deliver_policy(DeliverPolicy::All)
+    }
 
-impl From<&StreamConfig> for StreamConfig {
-    fn from(sc: &StreamConfig) -> StreamConfig {
-        sc.clone()
+    /// Starts delivering with the last message in the stream, setting
+    /// `deliver_policy` to `DeliverPolicy::Last` and clearing the start
+    /// position.
+    pub fn deliver_last(self) -> ConsumerConfig {
+        self.deliver_policy(DeliverPolicy::Last)
     }
-}
 
-impl From<&str> for StreamConfig {
-    fn from(s: &str) -> StreamConfig {
-        StreamConfig {
-            name: s.to_string(),
-            ..Default::default()
+    /// Only delivers messages stored after the consumer is created,
+    /// setting `deliver_policy` to `DeliverPolicy::New` and clearing the
+    /// start position.
+    pub fn deliver_new(self) -> ConsumerConfig {
+        self.deliver_policy(DeliverPolicy::New)
+    }
+
+    /// Starts delivering with the last message of each subject, setting
+    /// `deliver_policy` to `DeliverPolicy::LastPerSubject` and clearing
+    /// the start position.
+    pub fn deliver_last_per_subject(self) -> ConsumerConfig {
+        self.deliver_policy(DeliverPolicy::LastPerSubject)
+    }
+
+    /// Sets a `deliver_policy` that needs no start position.
+    fn deliver_policy(self, deliver_policy: DeliverPolicy) -> ConsumerConfig {
+        ConsumerConfig {
+            deliver_policy,
+            opt_start_seq: 0,
+            opt_start_time: None,
+            ..self
         }
     }
-}
 
-/// Shows config and current state for this stream.
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct StreamInfo {
-    /// The configuration associated with this stream
-    pub config: StreamConfig,
-    /// The time that this stream was created
-    pub created: DateTime,
-    /// Various metrics associated with this stream
-    pub state: StreamState,
+    /// Starts delivering with the first message stored at or after `time`,
+    /// setting `deliver_policy` to `DeliverPolicy::ByStartTime` along with
+    /// `opt_start_time`.
+    pub fn deliver_from_time(
+        self,
+        time: impl Into<DateTime>,
+    ) -> ConsumerConfig {
+        ConsumerConfig {
+            deliver_policy: DeliverPolicy::ByStartTime,
+            opt_start_time: Some(time.into()),
+            opt_start_seq: 0,
+            ..self
+        }
+    }
+
+    /// Starts delivering with the message at stream sequence `seq`, setting
+    /// `deliver_policy` to `DeliverPolicy::ByStartSeq` along with
+    /// `opt_start_seq`.
+    pub fn deliver_from_seq(self, seq: u64) -> ConsumerConfig {
+        ConsumerConfig {
+            deliver_policy: DeliverPolicy::ByStartSeq,
+            opt_start_seq: i64::try_from(seq).unwrap_or(i64::MAX),
+            opt_start_time: None,
+            ..self
+        }
+    }
+
+    /// Same as `ConsumerConfig::deliver_from_seq`, named after
+    /// `DeliverPolicy::ByStartSeq` like the other `deliver_*` builders.
+    pub fn deliver_by_start_seq(self, seq: u64) -> ConsumerConfig {
+        self.deliver_from_seq(seq)
+    }
+
+    /// Same as `ConsumerConfig::deliver_from_time`, named after
+    /// `DeliverPolicy::ByStartTime` like the other `deliver_*` builders.
+    pub fn deliver_by_start_time(
+        self,
+        time: impl Into<DateTime>,
+    ) -> ConsumerConfig {
+        self.deliver_from_time(time)
+    }
+
+    /// Describes where the consumer starts delivering according to its
+    /// `deliver_policy`, `opt_start_seq` and `opt_start_time`, e.g.
+    /// `"start from sequence 1500"` or `"start from
+    /// 2024-01-01T00:00:00Z"`, for showing configurations to people.
+    pub fn start_description(&self) -> String {
+        match self.deliver_policy {
+            DeliverPolicy::All => "start from the first message".to_string(),
+            DeliverPolicy::Last => "start from the last message".to_string(),
+            DeliverPolicy::New => "start from new messages only".to_string(),
+            DeliverPolicy::ByStartSeq => {
+                format!("start from sequence {}", self.opt_start_seq)
+            }
+            DeliverPolicy::ByStartTime => match self.opt_start_time {
+                Some(time) => format!(
+                    "start from {}",
+                    time.0.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                ),
+                None => "start from an unset time".to_string(),
+            },
+            DeliverPolicy::LastPerSubject => {
+                "start from the last message of each subject".to_string()
+            }
+            DeliverPolicy::Unknown => {
+                "start from an unknown position".to_string()
+            }
+        }
+    }
+
+    /// Returns an ephemeral copy of this configuration that only delivers
+    /// messages matching `subject`, e.g. to derive one consumer per worker
+    /// from a template. The `durable_name` is cleared so that the copies
+    /// do not share their state.
+    pub fn with_filter_subject(&self, subject: &str) -> ConsumerConfig {
+        ConsumerConfig {
+            filter_subject: subject.to_string(),
+            durable_name: None,
+            ..self.clone()
+        }
+    }
+
+    /// Returns an ephemeral copy of this configuration that pushes its
+    /// messages to `subject`, clearing the `durable_name` like
+    /// `ConsumerConfig::with_filter_subject`.
+    pub fn with_deliver_subject(&self, subject: &str) -> ConsumerConfig {
+        ConsumerConfig {
+            deliver_subject: Some(subject.to_string()),
+            durable_name: None,
+            ..self.clone()
+        }
+    }
+
+    /// Limits the rate at which messages are delivered to `bits_per_second`,
+    /// saturating at the largest rate the server accepts.
+    pub fn rate_limit(self, bits_per_second: u64) -> ConsumerConfig {
+        ConsumerConfig {
+            rate_limit: i64::try_from(bits_per_second).unwrap_or(i64::MAX),
+            ..self
+        }
+    }
+
+    /// Returns `true` if messages may be delivered any number of times,
+    /// i.e. if `max_deliver` is `None` or -1.
+    pub fn max_deliver_is_unlimited(&self) -> bool {
+        is_unlimited(&self.max_deliver)
+    }
+
+    /// Returns `true` if this consumer pushes messages to a
+    /// `deliver_subject`, optionally shared by a `deliver_group`. A
+    /// `deliver_group` without a `deliver_subject` does not make a
+    /// consumer push-based, and is rejected by
+    /// `ConsumerConfig::validate`.
+    pub fn is_push(&self) -> bool {
+        self.deliver_subject.is_some()
+    }
+
+    /// Returns `true` if this consumer only delivers messages in response
+    /// to pull requests, which may be limited by `max_waiting`,
+    /// `max_batch` and `max_expires`.
+    pub fn is_pull(&self) -> bool {
+        !self.is_push()
+    }
+
+    /// Tags the consumer with the name and version of this client under
+    /// `CLIENT_METADATA_KEY`, unless `metadata` already has that key.
+    pub fn with_client_metadata(mut self) -> ConsumerConfig {
+        insert_client_metadata(&mut self.metadata);
+        self
+    }
+
+    /// Returns `true` if `pause_until` lies in the future according to
+    /// the local clock, i.e. the consumer does not deliver messages yet.
+    pub fn is_paused(&self) -> bool {
+        self.pause_until.map_or(false, |until| until.0 > Utc::now())
+    }
+
+    /// Serializes this configuration as compact JSON with sorted keys, so
+    /// that equal configurations always produce identical output, which
+    /// is convenient for storing and diffing them. Returns an
+    /// `ErrorKind::InvalidInput` error if a duration is too long to be
+    /// sent to the server.
+    pub fn to_canonical_json(&self) -> io::Result<String> {
+        canonical_json(self)
+    }
+
+    /// Returns `true` if the server treats both configurations the same,
+    /// i.e. if they only differ in values that the server replaces with
+    /// its defaults, like a missing `ack_wait` or `max_deliver`, in
+    /// `metadata` keys starting with `_nats.`, or in fields unknown to
+    /// this client. A `num_replicas` of 0 in `self` inherits the
+    /// replication of the stream and matches any other value.
+    pub fn semantically_eq(&self, other: &ConsumerConfig) -> bool {
+        self.diff(other).is_empty()
+    }
+
+    /// Returns the names of the fields in which `other` differs from this
+    /// configuration once both are normalized like in
+    /// `ConsumerConfig::semantically_eq`, which is convenient for logging
+    /// what an update changes. Fields unknown to this client are ignored.
+    pub fn diff(&self, other: &ConsumerConfig) -> Vec<&'static str> {
+        let current = self.normalized();
+        let mut other = other.normalized();
+        if self.num_replicas == 0 {
+            other.num_replicas = 0;
+        }
+        changed_fields!(
+            current,
+            other,
+            [
+                deliver_subject,
+                durable_name,
+                deliver_policy,
+                opt_start_seq,
+                opt_start_time,
+                ack_policy,
+                ack_wait,
+                max_deliver,
+                filter_subject,
+                replay_policy,
+                rate_limit,
+                sample_frequency,
+                max_waiting,
+                max_ack_pending,
+                num_replicas,
+                deliver_group,
+                max_batch,
+                max_expires,
+                flow_control,
+                idle_heartbeat,
+                pause_until,
+                priority_groups,
+                priority_policy,
+                metadata,
+            ]
+        )
+    }
+
+    /// Returns the configuration with the defaults that the server fills
+    /// in, see `ConsumerConfig::semantically_eq`.
+    fn normalized(&self) -> ConsumerConfig {
+        const DEFAULT_ACK_WAIT: Duration = Duration::from_secs(30);
+        fn or<T: Default + PartialEq>(value: T, default: T) -> T {
+            if value == T::default() {
+                default
+            } else {
+                value
+            }
+        }
+
+        let mut config = ConsumerConfig {
+            max_deliver: self
+                .max_deliver
+                .filter(|_| !self.max_deliver_is_unlimited()),
+            metadata: self
+                .metadata
+                .iter()
+                .filter(|(key, _)| !key.starts_with("_nats."))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            extra: UnknownFields::default(),
+            ..self.clone()
+        };
+        if config.ack_policy != AckPolicy::None {
+            config.ack_wait = or(config.ack_wait, DEFAULT_ACK_WAIT);
+            config.max_ack_pending = or(
+                config.max_ack_pending,
+                ConsumerConfig::DEFAULT_MAX_ACK_PENDING,
+            );
+        }
+        if config.is_pull() {
+            config.max_waiting =
+                or(config.max_waiting, ConsumerConfig::DEFAULT_MAX_WAITING);
+        }
+        config
+    }
+
+    /// Decides how an `existing` consumer of the same name, if any, needs
+    /// to be changed to match this configuration, see
+    /// `Connection::ensure_consumer`.
+    ///
+    /// Returns an `ErrorKind::InvalidInput` error wrapping an
+    /// `ImmutableConflict` if the existing consumer differs in settings
+    /// that can not be updated: where it starts delivering, its
+    /// `ack_policy`, `replay_policy` or `filter_subject`, or whether it is
+    /// push-based or pull-based.
+    pub fn reconcile(
+        &self,
+        existing: Option<&ConsumerConfig>,
+    ) -> io::Result<ReconcileAction> {
+        let existing = match existing {
+            None => return Ok(ReconcileAction::Created),
+            Some(existing) if self.semantically_eq(existing) => {
+                return Ok(ReconcileAction::Unchanged)
+            }
+            Some(existing) => existing,
+        };
+
+        let mut fields = vec![];
+        if self.deliver_policy != existing.deliver_policy {
+            fields.push("deliver_policy");
+        }
+        if self.opt_start_seq != existing.opt_start_seq {
+            fields.push("opt_start_seq");
+        }
+        if self.opt_start_time != existing.opt_start_time {
+            fields.push("opt_start_time");
+        }
+        if self.ack_policy != existing.ack_policy {
+            fields.push("ack_policy");
+        }
+        if self.replay_policy != existing.replay_policy {
+            fields.push("replay_policy");
+        }
+        if self.filter_subject != existing.filter_subject {
+            fields.push("filter_subject");
+        }
+        if self.is_push() != existing.is_push() {
+            fields.push("deliver_subject");
+        }
+
+        if fields.is_empty() {
+            Ok(ReconcileAction::Updated)
+        } else {
+            Err(ImmutableConflict {
+                kind: "consumer",
+                name: self.durable_name.clone().unwrap_or_default(),
+                fields,
+            }
+            .into_error())
+        }
+    }
+
+    /// Checks this configuration for values that the server rejects,
+    /// returning an `ErrorKind::InvalidInput` error for the first one
+    /// found, and otherwise a `Lint` for every value that is accepted
+    /// but is unlikely to behave as intended.
+    ///
+    /// A `durable_name` must pass `validate_name`, `rate_limit` must not
+    /// be negative, `max_deliver` must be at least 1 or -1, `max_waiting`
+    /// only applies to pull-based consumers, a `deliver_group` requires a
+    /// `deliver_subject`, `flow_control` and `idle_heartbeat` only apply
+    /// to push-based consumers, `flow_control` requires an
+    /// `idle_heartbeat`, and `priority_groups` require a
+    /// `priority_policy` and vice versa. See `StreamConfig::lint` for
+    /// the checks on `num_replicas`, which may be 0 to inherit the
+    /// replication of the stream.
+    pub fn validate(&self) -> io::Result<Vec<Lint>> {
+        let mut lints = vec![];
+        if let Some(ref durable_name) = self.durable_name {
+            validate_name("consumer", durable_name)?;
+        }
+        validate_replicas(self.num_replicas)?;
+        if self.rate_limit < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "rate_limit must not be negative, got {}",
+                    self.rate_limit
+                ),
+            ));
+        }
+        match self.max_deliver {
+            Some(max_deliver) if max_deliver < 1 && max_deliver != -1 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "max_deliver must be at least 1, or -1 for unlimited, \
+                         got {}",
+                        max_deliver
+                    ),
+                ));
+            }
+            _ => {}
+        }
+        if self.is_pull() && self.deliver_group.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a deliver_group requires a deliver_subject to deliver to",
+            ));
+        }
+        if self.is_push() && self.max_waiting != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_waiting only applies to pull-based consumers, but the \
+                 consumer has a deliver_subject",
+            ));
+        }
+        if self.is_pull() && self.flow_control {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "flow_control only applies to push-based consumers, but the \
+                 consumer has no deliver_subject",
+            ));
+        }
+        if self.is_pull() && self.idle_heartbeat != Duration::default() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "idle_heartbeat only applies to push-based consumers, but the \
+                 consumer has no deliver_subject",
+            ));
+        }
+        if self.flow_control && self.idle_heartbeat == Duration::default() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "flow_control requires an idle_heartbeat",
+            ));
+        }
+        self.validate_priority_groups()?;
+        lint_replicas(self.num_replicas, &mut lints);
+        Ok(lints)
+    }
+
+    /// Checks that `priority_groups` and a `priority_policy` other than
+    /// `PriorityPolicy::None` are either both set or both unset.
+    fn validate_priority_groups(&self) -> io::Result<()> {
+        let has_groups = self
+            .priority_groups
+            .as_ref()
+            .map_or(false, |groups| !groups.is_empty());
+        let has_policy = self
+            .priority_policy
+            .map_or(false, |policy| policy != PriorityPolicy::None);
+
+        match (has_groups, has_policy) {
+            (true, false) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "priority_groups require a priority_policy",
+            )),
+            (false, true) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a priority_policy requires priority_groups",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// determines how the messages of a consumer are distributed among the
+/// pull requests of its priority groups.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum PriorityPolicy {
+    /// Messages are distributed without regard to priority groups. This
+    /// is the default.
+    #[serde(rename = "none")]
+    None = 0,
+    /// Pull requests only receive messages once the consumer has more
+    /// pending messages, or acknowledgements, than their `min_pending` or
+    /// `min_ack_pending`.
+    #[serde(rename = "overflow")]
+    Overflow = 1,
+    /// Only the pull requests of a single pinned client receive messages,
+    /// until it stops pulling or is unpinned.
+    #[serde(rename = "pinned_client")]
+    PinnedClient = 2,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> PriorityPolicy {
+        PriorityPolicy::None
+    }
+}
+
+impl fmt::Display for ConsumerConfig {
+    /// Formats a one-line summary of how the consumer delivers messages,
+    /// using `>` as the filter of consumers without a `filter_subject`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ack={} deliver={} filter={}",
+            self.durable_name.as_deref().unwrap_or("ephemeral"),
+            if self.is_push() { "push" } else { "pull" },
+            serde_name(&self.ack_policy),
+            serde_name(&self.deliver_policy),
+            if self.filter_subject.is_empty() {
+                ">"
+            } else {
+                &self.filter_subject
+            },
+        )
+    }
+}
+
+impl From<&ConsumerConfig> for ConsumerConfig {
+    fn from(cc: &ConsumerConfig) -> ConsumerConfig {
+        cc.clone()
+    }
+}
+
+impl From<&str> for ConsumerConfig {
+    fn from(s: &str) -> ConsumerConfig {
+        ConsumerConfig {
+            durable_name: Some(s.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// `StreamConfig` determines the properties for a stream.
+/// There are sensible defaults for most. If no subjects are
+/// given the name will be used as the only subject.
+///
+/// The serde implementation follows the JSON of the `JetStream` API and
+/// requires a self-describing format: unknown fields are flattened into
+/// `extra`, limits may also be given as strings, and fields at their
+/// default value are left out when serializing. Formats like `bincode`
+/// or `postcard` can not represent this, so convert a configuration to a
+/// `CompactStreamConfig` to store it in one of them.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
+pub struct StreamConfig {
+    /// A name for the Stream. Must not have spaces, tabs or period `.` characters
+    pub name: String,
+    /// How large the Stream may become in total bytes before the configured discard policy kicks in
+    #[serde(deserialize_with = "number_or_string")]
+    pub max_bytes: i64,
+    /// How large the Stream may become in total messages before the configured discard policy kicks in
+    #[serde(deserialize_with = "number_or_string")]
+    pub max_msgs: i64,
+    /// Maximum amount of messages to keep per subject
+    #[serde(deserialize_with = "number_or_string")]
+    pub max_msgs_per_subject: i64,
+    /// When a Stream has reached its configured `max_bytes` or `max_msgs`, this policy kicks in.
+    /// `DiscardPolicy::New` refuses new messages or `DiscardPolicy::Old` (default) deletes old messages to make space
+    pub discard: DiscardPolicy,
+    /// Applies `DiscardPolicy::New` to each subject that reached its
+    /// `max_msgs_per_subject` instead of only to the stream as a whole,
+    /// which requires both of them. Supported by servers since 2.10.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discard_new_per_subject: Option<bool>,
+    /// Which NATS subjects to populate this stream with. Supports wildcards. Defaults to just the
+    /// configured stream `name`. An empty list is treated like `None` and
+    /// left out when serializing, since the server rejects it.
+    #[serde(default, skip_serializing_if = "is_none_or_empty")]
+    pub subjects: Option<Vec<String>>,
+    /// How message retention is considered, `Limits` (default), `Interest` or `WorkQueue`
+    pub retention: RetentionPolicy,
+    /// How many Consumers can be defined for a given Stream, -1 for unlimited
+    pub max_consumers: i32,
+    /// Maximum age of any message in the stream, 0 for unlimited
+    pub max_age: Duration,
+    /// The largest message that will be accepted by the Stream
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub max_msg_size: i32,
+    /// The type of storage backend, `File` (default) and `Memory`
+    pub storage: StorageType,
+    /// How many replicas to keep for each message in a clustered JetStream, maximum 5.
+    /// Also accepts `replicas`, as written by some older tools.
+    #[serde(alias = "replicas")]
+    pub num_replicas: usize,
+    /// Disables acknowledging messages that are received by the Stream.
+    /// This is only sent to the server when set to `true`. See
+    /// `StreamConfig::validate_against_consumers` for how this interacts
+    /// with the `ack_policy` of consumers.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub no_ack: bool,
+    /// The window within which to track duplicate messages.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub duplicate_window: Duration,
+    /// The owner of the template associated with this stream.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub template_owner: String,
+    /// Allows messages to be published with a `ttl` that expires them
+    /// individually. See `PubOpts::ttl`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub allow_msg_ttl: bool,
+    /// Republishes every message stored in the stream that matches
+    /// `Republish::src` to another subject.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub republish: Option<Republish>,
+    /// The sequence that the first message stored in a new stream gets,
+    /// supported by servers since 2.10. See `StreamConfig::first_seq`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub first_seq: u64,
+    /// How the messages of the stream are compressed when stored,
+    /// supported by servers since 2.10. `None` leaves the choice to the
+    /// server, which does not compress messages, and is what servers
+    /// before 2.10 report, while `Some(Compression::None)` explicitly
+    /// asks for uncompressed storage and is sent as `"none"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Arbitrary information about the stream, supported by servers
+    /// since 2.10
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub metadata: BTreeMap<String, String>,
+    /// Rewrites the subjects of incoming messages before they are
+    /// stored, supported by servers since 2.10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject_transform: Option<SubjectTransformConfig>,
+    /// The stream that this stream mirrors, in which case it can not
+    /// have `subjects` or `sources` of its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<StreamSource>,
+    /// The streams that this stream copies messages from, in addition to
+    /// the messages published to its own `subjects`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<StreamSource>>,
+    /// Fields reported by a newer server that this client does not know
+    /// about, which are sent back unchanged so that recreating a stream
+    /// from its `StreamInfo` preserves them.
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+/// The fields of a configuration that this client does not model, keyed
+/// by their name.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct UnknownFields(pub HashMap<String, serde_json::Value>);
+
+impl Hash for UnknownFields {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut fields = self.0.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|(name, _)| *name);
+        for (name, value) in fields {
+            name.hash(state);
+            value.to_string().hash(state);
+        }
+    }
+}
+
+impl Deref for UnknownFields {
+    type Target = HashMap<String, serde_json::Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for UnknownFields {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// What `Connection::ensure_stream` or `Connection::ensure_consumer` did
+/// to make a stream or consumer match the desired configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReconcileAction {
+    /// The stream or consumer did not exist and was created
+    Created,
+    /// The stream or consumer existed with a different configuration and
+    /// was updated
+    Updated,
+    /// The stream or consumer already had the desired configuration
+    Unchanged,
+}
+
+/// The error wrapped in the `io::Error` returned when a stream or a
+/// consumer would have to change settings that can not be updated to
+/// match the desired configuration, see `StreamConfig::reconcile` and
+/// `ConsumerConfig::reconcile`. It can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImmutableConflict {
+    /// Either `"stream"` or `"consumer"`
+    pub kind: &'static str,
+    /// The name of the stream or consumer
+    pub name: String,
+    /// The fields that differ from the existing stream or consumer
+    pub fields: Vec<&'static str>,
+}
+
+impl ImmutableConflict {
+    /// Returns an `ErrorKind::InvalidInput` error wrapping the conflict.
+    fn into_error(self) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, self)
+    }
+}
+
+impl fmt::Display for ImmutableConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} {} can not be updated to the desired configuration \
+             because it changes {}",
+            self.kind,
+            self.name,
+            self.fields.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ImmutableConflict {}
+
+/// The error wrapped in the `io::Error` returned when a configuration can
+/// not be parsed with `StreamConfig::from_json_strict`. Like the other
+/// errors of this crate it comes as an `io::Error`, here of kind
+/// `ErrorKind::InvalidData`, and can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The input is not a valid configuration, e.g. because it is not
+    /// JSON or a field has the wrong type, described by the message of
+    /// the parser
+    Invalid(String),
+    /// The input has a field that the configuration does not have, which
+    /// is probably misspelled, e.g. `sources.1.filter_subjects`
+    UnknownField(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Invalid(message) => {
+                write!(f, "invalid stream config: {}", message)
+            }
+            ConfigError::UnknownField(field) => {
+                write!(f, "unknown field `{}` in stream config", field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for io::Error {
+    fn from(error: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// A stream that another stream mirrors or copies messages from.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
+pub struct StreamSource {
+    /// The name of the stream to copy messages from
+    pub name: String,
+    /// Only copies messages starting with this sequence
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub opt_start_seq: u64,
+    /// Only copies messages stored at or after this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opt_start_time: Option<DateTime>,
+    /// Only copies messages with a subject matching this filter
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub filter_subject: String,
+    /// Only copies messages matching the `src` of any of these transforms,
+    /// changing their subject to its `dest`. Supported by servers since
+    /// 2.10 and can not be combined with `filter_subject`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject_transforms: Option<Vec<SubjectTransformConfig>>,
+    /// Where to find the stream if it belongs to another domain or account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external: Option<ExternalStream>,
+}
+
+impl StreamSource {
+    /// Checks this source for values that the server rejects, returning an
+    /// `ErrorKind::InvalidInput` error if both `filter_subject` and
+    /// `subject_transforms` are set.
+    pub fn validate(&self) -> io::Result<()> {
+        if !self.filter_subject.is_empty() && self.subject_transforms.is_some()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "source {} can not have both a filter_subject and \
+                    subject_transforms",
+                    self.name
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The API prefixes used to reach a stream in another domain or account.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
+pub struct ExternalStream {
+    /// The prefix of the `JetStream` API that the stream belongs to
+    pub api: String,
+    /// The prefix of the subject that the messages are delivered on
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub deliver: String,
+}
+
+/// The state of replicating a stream's mirror or one of its sources, as
+/// reported in `StreamInfo`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StreamSourceInfo {
+    /// The name of the mirrored or sourced stream
+    pub name: String,
+    /// How many messages the stream is behind the mirrored or sourced one
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub lag: u64,
+    /// How long ago messages were last received from the mirrored or
+    /// sourced stream, or `None` if none were received yet
+    #[serde(default, deserialize_with = "negative_as_none")]
+    pub active: Option<Duration>,
+    /// Why replication is failing, if it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+    /// The `JetStream` API the mirrored or sourced stream is accessed
+    /// through if it belongs to another account or domain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external: Option<ExternalStream>,
+}
+
+/// Maps a subject matching `src` to `dest`, where `dest` may refer to
+/// the wildcards of `src` as `{{wildcard(1)}}`, `{{wildcard(2)}}`, etc.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
+pub struct SubjectTransformConfig {
+    /// The subjects to transform. Supports wildcards.
+    pub src: String,
+    /// The subject to transform matching subjects to
+    pub dest: String,
+}
+
+/// Configures a stream to republish the messages it stores, e.g. to let
+/// plain NATS subscribers observe a stream without consuming from it.
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash,
+)]
+pub struct Republish {
+    /// The subjects of the stream to republish. Supports wildcards.
+    pub src: String,
+    /// The subject to republish matching messages to, which must not be
+    /// captured by the stream itself.
+    pub dest: String,
+    /// Republishes only the headers of each message, without its payload
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub headers_only: bool,
+}
+
+/// A `StreamConfig` in a form that formats which are not self-describing,
+/// like `bincode` or `postcard`, can represent, e.g. to cache many
+/// configurations compactly. Every field is always serialized in the same
+/// order, and the `extra` fields, which may hold any JSON, are kept as
+/// JSON text, so converting a `StreamConfig` to it and back loses nothing.
+///
+/// Its layout may change between releases of this crate, so data written
+/// by one release can not necessarily be read by another.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use nats::jetstream::{CompactStreamConfig, StreamConfig};
+///
+/// let config = StreamConfig {
+///     name: "events".to_string(),
+///     ..StreamConfig::limits()
+/// };
+/// let compact = CompactStreamConfig::from(&config);
+/// assert_eq!(StreamConfig::try_from(compact).unwrap(), config);
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CompactStreamConfig {
+    name: String,
+    max_bytes: i64,
+    max_msgs: i64,
+    max_msgs_per_subject: i64,
+    discard: DiscardPolicy,
+    discard_new_per_subject: Option<bool>,
+    subjects: Option<Vec<String>>,
+    retention: RetentionPolicy,
+    max_consumers: i32,
+    max_age: time::Duration,
+    max_msg_size: i32,
+    storage: StorageType,
+    num_replicas: usize,
+    no_ack: bool,
+    duplicate_window: time::Duration,
+    template_owner: String,
+    allow_msg_ttl: bool,
+    // src, dest and headers_only
+    republish: Option<(String, String, bool)>,
+    first_seq: u64,
+    compression: Option<Compression>,
+    metadata: BTreeMap<String, String>,
+    // src and dest
+    subject_transform: Option<(String, String)>,
+    mirror: Option<CompactStreamSource>,
+    sources: Option<Vec<CompactStreamSource>>,
+    // names and JSON values, sorted by name
+    extra: Vec<(String, String)>,
+}
+
+/// The `StreamSource` of a `CompactStreamConfig`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+struct CompactStreamSource {
+    name: String,
+    opt_start_seq: u64,
+    opt_start_time: Option<DateTime>,
+    filter_subject: String,
+    subject_transforms: Option<Vec<(String, String)>>,
+    // api and deliver
+    external: Option<(String, String)>,
+}
+
+impl From<&StreamSource> for CompactStreamSource {
+    fn from(source: &StreamSource) -> CompactStreamSource {
+        CompactStreamSource {
+            name: source.name.clone(),
+            opt_start_seq: source.opt_start_seq,
+            opt_start_time: source.opt_start_time,
+            filter_subject: source.filter_subject.clone(),
+            subject_transforms: source.subject_transforms.as_ref().map(
+                |transforms| {
+                    transforms
+                        .iter()
+                        .map(|t| (t.src.clone(), t.dest.clone()))
+                        .collect()
+                },
+            ),
+            external: source
+                .external
+                .as_ref()
+                .map(|e| (e.api.clone(), e.deliver.clone())),
+        }
+    }
+}
+
+impl From<CompactStreamSource> for StreamSource {
+    fn from(source: CompactStreamSource) -> StreamSource {
+        StreamSource {
+            name: source.name,
+            opt_start_seq: source.opt_start_seq,
+            opt_start_time: source.opt_start_time,
+            filter_subject: source.filter_subject,
+            subject_transforms: source.subject_transforms.map(|transforms| {
+                transforms
+                    .into_iter()
+                    .map(|(src, dest)| SubjectTransformConfig { src, dest })
+                    .collect()
+            }),
+            external: source
+                .external
+                .map(|(api, deliver)| ExternalStream { api, deliver }),
+        }
+    }
+}
+
+impl From<&StreamConfig> for CompactStreamConfig {
+    fn from(config: &StreamConfig) -> CompactStreamConfig {
+        // destructured so that a new field can not be forgotten here
+        let StreamConfig {
+            name,
+            max_bytes,
+            max_msgs,
+            max_msgs_per_subject,
+            discard,
+            discard_new_per_subject,
+            subjects,
+            retention,
+            max_consumers,
+            max_age,
+            max_msg_size,
+            storage,
+            num_replicas,
+            no_ack,
+            duplicate_window,
+            template_owner,
+            allow_msg_ttl,
+            republish,
+            first_seq,
+            compression,
+            metadata,
+            subject_transform,
+            mirror,
+            sources,
+            extra,
+        } = config;
+
+        let mut extra = extra
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string()))
+            .collect::<Vec<_>>();
+        extra.sort();
+
+        CompactStreamConfig {
+            name: name.clone(),
+            max_bytes: *max_bytes,
+            max_msgs: *max_msgs,
+            max_msgs_per_subject: *max_msgs_per_subject,
+            discard: *discard,
+            discard_new_per_subject: *discard_new_per_subject,
+            subjects: subjects.clone(),
+            retention: *retention,
+            max_consumers: *max_consumers,
+            max_age: (*max_age).into(),
+            max_msg_size: *max_msg_size,
+            storage: *storage,
+            num_replicas: *num_replicas,
+            no_ack: *no_ack,
+            duplicate_window: (*duplicate_window).into(),
+            template_owner: template_owner.clone(),
+            allow_msg_ttl: *allow_msg_ttl,
+            republish: republish
+                .as_ref()
+                .map(|r| (r.src.clone(), r.dest.clone(), r.headers_only)),
+            first_seq: *first_seq,
+            compression: *compression,
+            metadata: metadata.clone(),
+            subject_transform: subject_transform
+                .as_ref()
+                .map(|t| (t.src.clone(), t.dest.clone())),
+            mirror: mirror.as_ref().map(CompactStreamSource::from),
+            sources: sources.as_ref().map(|sources| {
+                sources.iter().map(CompactStreamSource::from).collect()
+            }),
+            extra,
+        }
+    }
+}
+
+impl TryFrom<CompactStreamConfig> for StreamConfig {
+    type Error = io::Error;
+
+    /// Recovers the `StreamConfig`, returning an `ErrorKind::InvalidData`
+    /// error if one of the `extra` fields does not hold valid JSON.
+    fn try_from(config: CompactStreamConfig) -> io::Result<StreamConfig> {
+        let mut extra = UnknownFields::default();
+        for (name, json) in config.extra {
+            let value = serde_json::from_str(&json).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid JSON in the field {}: {}", name, err),
+                )
+            })?;
+            extra.insert(name, value);
+        }
+
+        Ok(StreamConfig {
+            name: config.name,
+            max_bytes: config.max_bytes,
+            max_msgs: config.max_msgs,
+            max_msgs_per_subject: config.max_msgs_per_subject,
+            discard: config.discard,
+            discard_new_per_subject: config.discard_new_per_subject,
+            subjects: config.subjects,
+            retention: config.retention,
+            max_consumers: config.max_consumers,
+            max_age: config.max_age.into(),
+            max_msg_size: config.max_msg_size,
+            storage: config.storage,
+            num_replicas: config.num_replicas,
+            no_ack: config.no_ack,
+            duplicate_window: config.duplicate_window.into(),
+            template_owner: config.template_owner,
+            allow_msg_ttl: config.allow_msg_ttl,
+            republish: config.republish.map(|(src, dest, headers_only)| {
+                Republish {
+                    src,
+                    dest,
+                    headers_only,
+                }
+            }),
+            first_seq: config.first_seq,
+            compression: config.compression,
+            metadata: config.metadata,
+            subject_transform: config
+                .subject_transform
+                .map(|(src, dest)| SubjectTransformConfig { src, dest }),
+            mirror: config.mirror.map(StreamSource::from),
+            sources: config
+                .sources
+                .map(|sources| sources.into_iter().map(Into::into).collect()),
+            extra,
+        })
+    }
+}
+
+impl StreamConfig {
+    /// Creates the configuration for a stream that keeps messages until one
+    /// of its limits is reached and then discards the oldest ones. All
+    /// limits start out unlimited, see `StreamConfig::max_age` and
+    /// `StreamConfig::max_bytes`.
+    ///
+    /// The stream still needs a `name`:
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use nats::jetstream::StreamConfig;
+    ///
+    /// let config = StreamConfig {
+    ///     name: "events".to_string(),
+    ///     ..StreamConfig::limits()
+    ///         .max_age(Duration::from_secs(7 * 24 * 60 * 60))
+    ///         .max_bytes(10 * 1024 * 1024 * 1024)
+    /// };
+    /// ```
+    pub fn limits() -> StreamConfig {
+        StreamConfig::with_retention(
+            RetentionPolicy::Limits,
+            DiscardPolicy::Old,
+            None,
+        )
+    }
+
+    /// Creates the configuration for a stream capturing `subjects` that
+    /// keeps each message until a consumer acknowledges it. Because
+    /// discarding unprocessed work is rarely wanted, new messages are
+    /// rejected once a limit is reached.
+    pub fn work_queue<I, S>(subjects: I) -> StreamConfig
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        StreamConfig::with_retention(
+            RetentionPolicy::WorkQueue,
+            DiscardPolicy::New,
+            Some(subjects.into_iter().map(Into::into).collect()),
+        )
+    }
+
+    /// Creates the configuration for a stream capturing `subjects` that
+    /// keeps each message until all consumers interested in it have
+    /// acknowledged it, discarding the oldest messages once a limit is
+    /// reached.
+    pub fn interest<I, S>(subjects: I) -> StreamConfig
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        StreamConfig::with_retention(
+            RetentionPolicy::Interest,
+            DiscardPolicy::Old,
+            Some(subjects.into_iter().map(Into::into).collect()),
+        )
+    }
+
+    fn with_retention(
+        retention: RetentionPolicy,
+        discard: DiscardPolicy,
+        subjects: Option<Vec<String>>,
+    ) -> StreamConfig {
+        StreamConfig {
+            retention,
+            discard,
+            subjects,
+            max_bytes: -1,
+            max_msgs: -1,
+            max_msgs_per_subject: -1,
+            max_consumers: -1,
+            max_msg_size: -1,
+            num_replicas: 1,
+            no_ack: false,
+            ..Default::default()
+        }
+    }
+
+    /// Limits how long messages are kept in the stream.
+    pub fn max_age(self, max_age: impl Into<Duration>) -> StreamConfig {
+        StreamConfig {
+            max_age: max_age.into(),
+            ..self
+        }
+    }
+
+    /// Limits the total size of the messages kept in the stream.
+    pub fn max_bytes(self, max_bytes: i64) -> StreamConfig {
+        StreamConfig { max_bytes, ..self }
+    }
+
+    /// Serializes this configuration in the form accepted by
+    /// `nats stream add --config`, which expects durations such
+    /// as `max_age` as human-readable strings like `"30s"` rather
+    /// than as nanoseconds.
+    pub fn to_cli_json(&self) -> io::Result<String> {
+        to_cli_json(self, &["max_age", "duplicate_window"])
+    }
+
+    /// Returns a copy of this configuration for a stream with a different
+    /// name, e.g. to create a copy of an existing stream.
+    ///
+    /// If `subjects` is `None` the copy will capture its own name as its
+    /// subject. Otherwise it keeps the subjects of the original, which
+    /// the server rejects if both streams exist at the same time, so they
+    /// usually need to be changed as well.
+    pub fn cloned_as(&self, name: &str) -> StreamConfig {
+        StreamConfig {
+            name: name.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// Parses a configuration from JSON like `serde_json::from_str`, but
+    /// returns an error naming the first unknown field in the input
+    /// instead of keeping it in `extra` or ignoring it. This catches
+    /// misspelled fields, e.g. `max_byte` instead of `max_bytes` or
+    /// `republish.header_only` instead of `republish.headers_only`, in
+    /// hand-written configurations that would otherwise be silently
+    /// ignored. Errors are `ErrorKind::InvalidData` errors wrapping a
+    /// `ConfigError`.
+    pub fn from_json_strict(json: &str) -> io::Result<StreamConfig> {
+        /// The fields of a `StreamConfig` that have fields of their own.
+        /// Unknown fields nested in them are ignored rather than kept in
+        /// `extra`, which `serde_ignored` reports. The values themselves
+        /// are never read.
+        #[derive(Deserialize)]
+        #[allow(dead_code)]
+        struct Nested {
+            #[serde(default)]
+            republish: Option<Republish>,
+            #[serde(default)]
+            subject_transform: Option<SubjectTransformConfig>,
+            #[serde(default)]
+            mirror: Option<StreamSource>,
+            #[serde(default)]
+            sources: Option<Vec<StreamSource>>,
+        }
+
+        /// Names a field like `sources.1.filter_subject`, leaving out the
+        /// `?` that `serde_ignored` inserts for options.
+        fn field_name(path: &serde_ignored::Path<'_>) -> String {
+            use serde_ignored::Path;
+            let (parent, name) = match path {
+                Path::Root => return String::new(),
+                Path::Seq { parent, index } => (parent, index.to_string()),
+                Path::Map { parent, key } => (parent, key.clone()),
+                Path::Some { parent }
+                | Path::NewtypeStruct { parent }
+                | Path::NewtypeVariant { parent } => return field_name(parent),
+            };
+            match field_name(parent) {
+                ref parent if parent.is_empty() => name,
+                parent => format!("{}.{}", parent, name),
+            }
+        }
+
+        let invalid = |err: serde_json::Error| -> io::Error {
+            ConfigError::Invalid(err.to_string()).into()
+        };
+        let config: StreamConfig =
+            serde_json::from_str(json).map_err(invalid)?;
+
+        // the other fields of the stream are ignored by `Nested` as well,
+        // so only those that ended up in `extra` are unknown
+        let mut unknown = None;
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        serde_ignored::deserialize(&mut deserializer, |path| {
+            let known = match path {
+                serde_ignored::Path::Map {
+                    parent: serde_ignored::Path::Root,
+                    ref key,
+                } => !config.extra.contains_key(key),
+                _ => false,
+            };
+            if !known && unknown.is_none() {
+                unknown = Some(field_name(&path));
+            }
+        })
+        .map(|_: Nested| ())
+        .map_err(invalid)?;
+
+        if let Some(field) = unknown {
+            return Err(ConfigError::UnknownField(field).into());
+        }
+        Ok(config)
+    }
+
+    /// Checks this configuration for values that the server rejects,
+    /// returning an `ErrorKind::InvalidInput` error for the first one
+    /// found, and otherwise a `Lint` for every value that is accepted
+    /// but is unlikely to behave as intended.
+    ///
+    /// The `name` must pass `validate_name`, `num_replicas` may be at
+    /// most 5, `discard_new_per_subject` requires `DiscardPolicy::New` and
+    /// a `max_msgs_per_subject`, the `duplicate_window` may not exceed a
+    /// limited `max_age` because expired messages can not be deduplicated
+    /// against, `subjects` must pass `StreamConfig::validate_subjects`, the
+    /// `mirror` and `sources` must pass `StreamSource::validate`, and a
+    /// `republish` configuration must consist of valid subjects, and its
+    /// `src` must match at least one of the stream's subjects while its
+    /// `dest` must not match any, because republishing does nothing
+    /// otherwise. See `StreamConfig::lint` for the advisories that are
+    /// returned.
+    pub fn validate(&self) -> io::Result<Vec<Lint>> {
+        validate_name("stream", &self.name)?;
+        validate_replicas(self.num_replicas)?;
+        if self.discard_new_per_subject == Some(true)
+            && (self.discard != DiscardPolicy::New
+                || self.max_msgs_per_subject <= 0)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "discard_new_per_subject requires DiscardPolicy::New and a \
+                 max_msgs_per_subject",
+            ));
+        }
+        if self.max_age != Duration::default()
+            && self.duplicate_window > self.max_age
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the duplicate_window of {} must not exceed the max_age \
+                     of {}",
+                    self.duplicate_window, self.max_age
+                ),
+            ));
+        }
+        self.validate_subjects()?;
+        for source in self.stream_sources() {
+            source.validate()?;
+        }
+        if let Some(ref republish) = self.republish {
+            self.validate_republish(republish)?;
+        }
+        Ok(self.lint())
+    }
+
+    /// Makes a newly created stream start at sequence `seq` instead of 1,
+    /// e.g. to continue the sequences of a stream it replaces.
+    ///
+    /// This only applies when the stream is created. To make an existing
+    /// stream continue at a given sequence, purge it with a
+    /// `PurgeRequest` that has `seq` set instead.
+    pub fn first_seq(self, seq: u64) -> StreamConfig {
+        StreamConfig {
+            first_seq: seq,
+            ..self
+        }
+    }
+
+    /// Tags the stream with the name and version of this client under
+    /// `CLIENT_METADATA_KEY`, unless `metadata` already has that key.
+    pub fn with_client_metadata(mut self) -> StreamConfig {
+        insert_client_metadata(&mut self.metadata);
+        self
+    }
+
+    /// Returns the oldest server version that supports every field set in
+    /// this configuration, so that a configuration can be checked before
+    /// it is sent to an older server, which may otherwise ignore the field
+    /// or reject it with a confusing error. Configurations only using
+    /// fields that predate 2.3.0 report 2.2.0, the first version with
+    /// `JetStream` enabled by default.
+    pub fn min_server_version(&self) -> semver::Version {
+        let limited = |limit: i64| limit != 0 && limit != -1;
+        let requirements = [
+            (limited(self.max_msgs_per_subject), (2, 3, 0)),
+            (self.republish.is_some(), (2, 9, 0)),
+            (self.first_seq != 0, (2, 10, 0)),
+            (self.discard_new_per_subject.is_some(), (2, 10, 0)),
+            (
+                self.compression.map_or(false, |c| c != Compression::None),
+                (2, 10, 0),
+            ),
+            (!self.metadata.is_empty(), (2, 10, 0)),
+            (self.subject_transform.is_some(), (2, 10, 0)),
+            (
+                self.stream_sources()
+                    .any(|source| source.subject_transforms.is_some()),
+                (2, 10, 0),
+            ),
+            (self.allow_msg_ttl, (2, 11, 0)),
+        ];
+
+        let (major, minor, patch) = requirements
+            .iter()
+            .filter(|(used, _)| *used)
+            .map(|(_, version)| *version)
+            .max()
+            .unwrap_or((2, 2, 0));
+        semver::Version::new(major, minor, patch)
+    }
+
+    /// Returns a copy of this configuration without the fields that the
+    /// given server version does not support, so that the same desired
+    /// configuration can be applied to servers of different versions. The
+    /// fields that are cleared are:
+    ///
+    /// * before 2.3.0: `max_msgs_per_subject`
+    /// * before 2.9.0: `republish`
+    /// * before 2.10.0: `first_seq`, `discard_new_per_subject`,
+    ///   `compression`, `metadata`, `subject_transform` and the
+    ///   `subject_transforms` of sources
+    /// * before 2.11.0: `allow_msg_ttl`
+    ///
+    /// The `min_server_version` of the returned configuration is at most
+    /// `version`.
+    pub fn downgrade_to(&self, version: semver::Version) -> StreamConfig {
+        let supports = |major, minor, patch| {
+            version >= semver::Version::new(major, minor, patch)
+        };
+        let mut config = self.clone();
+
+        if !supports(2, 3, 0) {
+            config.max_msgs_per_subject = 0;
+        }
+        if !supports(2, 9, 0) {
+            config.republish = None;
+        }
+        if !supports(2, 10, 0) {
+            config.first_seq = 0;
+            config.discard_new_per_subject = None;
+            config.compression = None;
+            config.metadata.clear();
+            config.subject_transform = None;
+            let sources = config
+                .mirror
+                .iter_mut()
+                .chain(config.sources.iter_mut().flatten());
+            for source in sources {
+                source.subject_transforms = None;
+            }
+        }
+        if !supports(2, 11, 0) {
+            config.allow_msg_ttl = false;
+        }
+
+        config
+    }
+
+    /// Returns the `mirror` and all `sources` of the stream.
+    fn stream_sources(&self) -> impl Iterator<Item = &StreamSource> {
+        self.mirror.iter().chain(self.sources.iter().flatten())
+    }
+
+    /// Returns the subjects that the stream captures, which are the
+    /// configured `subjects`, or just the stream's `name` if there are none,
+    /// as the server does in that case.
+    pub fn effective_subjects(&self) -> Vec<String> {
+        match self.subjects {
+            Some(ref subjects) if !subjects.is_empty() => subjects.clone(),
+            _ => vec![self.name.clone()],
+        }
+    }
+
+    /// Serializes this configuration as compact JSON with sorted keys, so
+    /// that equal configurations always produce identical output, which
+    /// is convenient for storing and diffing them. Returns an
+    /// `ErrorKind::InvalidInput` error if a duration is too long to be
+    /// sent to the server.
+    pub fn to_canonical_json(&self) -> io::Result<String> {
+        canonical_json(self)
+    }
+
+    /// Returns a fingerprint of this configuration, the hex encoded
+    /// SHA-256 digest of `StreamConfig::to_canonical_json`. Equal
+    /// configurations always have the same fingerprint, so it can be
+    /// stored to detect later changes to a desired configuration. Fails
+    /// like `StreamConfig::to_canonical_json`.
+    pub fn fingerprint(&self) -> io::Result<String> {
+        let json = self.to_canonical_json()?;
+        Ok(format!("{:x}", Sha256::digest(json.as_bytes())))
+    }
+
+    /// Returns the number of bytes of the JSON that this configuration is
+    /// sent as when creating or updating the stream, without building it,
+    /// e.g. to check it against `Connection::max_payload` beforehand.
+    /// Returns an `ErrorKind::InvalidInput` error if the configuration can
+    /// not be sent because a duration is too long.
+    pub fn serialized_len(&self) -> io::Result<usize> {
+        serialized_len(self)
+    }
+
+    /// Returns `true` if the server treats both configurations the same,
+    /// i.e. if they only differ in values that the server replaces with
+    /// its defaults, like 0 and -1 for unlimited limits or missing
+    /// `subjects`, in `first_seq`, which only applies to new streams, in
+    /// `metadata` keys starting with `_nats.`, which are managed by the
+    /// server and clients, or in fields unknown to this client.
+    ///
+    /// A configuration is therefore semantically equal to the one the
+    /// server reports after creating a stream with it.
+    pub fn semantically_eq(&self, other: &StreamConfig) -> bool {
+        self.normalized() == other.normalized()
+    }
+
+    /// Returns the names of the fields in which `other` differs from this
+    /// configuration once both are normalized like in
+    /// `StreamConfig::semantically_eq`, each paired with whether the
+    /// change is to a setting that can not be updated: the `name` or one
+    /// of the fields listed in `StreamConfig::reconcile`. Fields unknown
+    /// to this client are ignored.
+    pub fn diff(&self, other: &StreamConfig) -> Vec<(&'static str, bool)> {
+        let current = self.normalized();
+        let other = other.normalized();
+        let fields = changed_fields!(
+            current,
+            other,
+            [
+                name,
+                max_bytes,
+                max_msgs,
+                max_msgs_per_subject,
+                discard,
+                discard_new_per_subject,
+                subjects,
+                retention,
+                max_consumers,
+                max_age,
+                max_msg_size,
+                storage,
+                num_replicas,
+                no_ack,
+                duplicate_window,
+                template_owner,
+                allow_msg_ttl,
+                republish,
+                first_seq,
+                compression,
+                metadata,
+                subject_transform,
+                mirror,
+                sources,
+            ]
+        );
+        fields
+            .into_iter()
+            .map(|field| {
+                let immutable = match field {
+                    "name" | "storage" | "mirror" | "max_consumers" => true,
+                    "retention" => {
+                        current.retention.single_consumer_per_subject()
+                            || other.retention.single_consumer_per_subject()
+                    }
+                    _ => false,
+                };
+                (field, immutable)
+            })
+            .collect()
+    }
+
+    /// Returns the configuration with the defaults that the server fills
+    /// in, see `StreamConfig::semantically_eq`.
+    fn normalized(&self) -> StreamConfig {
+        const DEFAULT_DUPLICATE_WINDOW: Duration = Duration::from_secs(2 * 60);
+        fn unlimited<T: Default + Ord + From<i8>>(limit: T) -> T {
+            if limit <= T::default() {
+                T::from(-1)
+            } else {
+                limit
+            }
+        }
+
+        let unset = Duration::default();
+        let duplicate_window = if self.duplicate_window != unset {
+            self.duplicate_window
+        } else if self.max_age != unset {
+            self.max_age.min(DEFAULT_DUPLICATE_WINDOW)
+        } else {
+            DEFAULT_DUPLICATE_WINDOW
+        };
+        StreamConfig {
+            max_bytes: unlimited(self.max_bytes),
+            max_msgs: unlimited(self.max_msgs),
+            max_msgs_per_subject: unlimited(self.max_msgs_per_subject),
+            discard_new_per_subject: self
+                .discard_new_per_subject
+                .filter(|d| *d),
+            compression: self.compression.filter(|c| *c != Compression::None),
+            subjects: Some(self.effective_subjects()),
+            max_consumers: unlimited(self.max_consumers),
+            max_msg_size: unlimited(self.max_msg_size),
+            num_replicas: self.num_replicas.max(1),
+            duplicate_window,
+            first_seq: 0,
+            metadata: self
+                .metadata
+                .iter()
+                .filter(|(key, _)| !key.starts_with("_nats."))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            extra: UnknownFields::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Decides how an `existing` stream of the same name, if any, needs to
+    /// be changed to match this configuration, see
+    /// `Connection::ensure_stream`.
+    ///
+    /// Returns an `ErrorKind::InvalidInput` error wrapping an
+    /// `ImmutableConflict` if the existing stream differs in settings that
+    /// can not be updated: its `storage`, its `mirror`, its
+    /// `max_consumers`, or a `retention` that changes from or to
+    /// `RetentionPolicy::WorkQueue`.
+    pub fn reconcile(
+        &self,
+        existing: Option<&StreamConfig>,
+    ) -> io::Result<ReconcileAction> {
+        let existing = match existing {
+            None => return Ok(ReconcileAction::Created),
+            Some(existing) if self.semantically_eq(existing) => {
+                return Ok(ReconcileAction::Unchanged)
+            }
+            Some(existing) => existing.normalized(),
+        };
+        let desired = self.normalized();
+
+        let mut fields = vec![];
+        if desired.storage != existing.storage {
+            fields.push("storage");
+        }
+        if desired.mirror != existing.mirror {
+            fields.push("mirror");
+        }
+        if desired.max_consumers != existing.max_consumers {
+            fields.push("max_consumers");
+        }
+        if desired.retention != existing.retention
+            && (desired.retention.single_consumer_per_subject()
+                || existing.retention.single_consumer_per_subject())
+        {
+            fields.push("retention");
+        }
+
+        if fields.is_empty() {
+            Ok(ReconcileAction::Updated)
+        } else {
+            Err(ImmutableConflict {
+                kind: "stream",
+                name: self.name.clone(),
+                fields,
+            }
+            .into_error())
+        }
+    }
+
+    /// Checks that every entry in `subjects` is a well-formed subject,
+    /// returning an `ErrorKind::InvalidInput` error for the first one that
+    /// is empty, contains an empty token or whitespace, or uses `>` before
+    /// its last token.
+    pub fn validate_subjects(&self) -> io::Result<()> {
+        for subject in self.subjects.iter().flatten() {
+            validate_subject("subjects", subject)?;
+        }
+        Ok(())
+    }
+
+    fn validate_republish(&self, republish: &Republish) -> io::Result<()> {
+        validate_subject("republish.src", &republish.src)?;
+        validate_subject("republish.dest", &republish.dest)?;
+
+        let subjects = self.effective_subjects();
+        let overlaps = |subject: &str| {
+            subjects.iter().any(|s| subject_overlaps(s, subject))
+        };
+
+        if !overlaps(&republish.src) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "republish.src {:?} does not match any subject of \
+                    stream {}",
+                    republish.src, self.name
+                ),
+            ));
+        }
+        if overlaps(&republish.dest) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "republish.dest {:?} would be captured by stream {} \
+                    itself",
+                    republish.dest, self.name
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns a `Lint` for every value in this configuration that is
+    /// accepted by the server but is deprecated, has no effect, or is
+    /// unlikely to behave as intended:
+    ///
+    /// * `template_owner` belongs to stream templates, which are deprecated.
+    /// * `DiscardPolicy::New` has no effect without a limit to enforce.
+    /// * A `duplicate_window` has no effect on a mirror, which only
+    ///   receives messages from the mirrored stream and never the
+    ///   `Nats-Msg-Id` of a publisher to deduplicate by.
+    /// * An even `num_replicas` tolerates no more failed servers than the
+    ///   next smaller odd number while needing a larger quorum.
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut lints = vec![];
+
+        if !self.template_owner.is_empty() {
+            lints.push(Lint::new(
+                "template_owner",
+                format!(
+                    "stream {} belongs to the template {}, stream templates \
+                    are deprecated",
+                    self.name, self.template_owner
+                ),
+            ));
+        }
+
+        if self.duplicate_window != Duration::default() {
+            if let Some(ref mirror) = self.mirror {
+                lints.push(Lint::new(
+                    "duplicate_window",
+                    format!(
+                        "stream {} mirrors {} and is never published to, so \
+                        its duplicate_window of {} has no effect",
+                        self.name, mirror.name, self.duplicate_window
+                    ),
+                ));
+            }
+        }
+
+        let limited = |limit: i64| limit > 0;
+        if self.discard == DiscardPolicy::New
+            && !limited(self.max_msgs)
+            && !limited(self.max_bytes)
+            && self.max_age == Duration::default()
+            && !limited(self.max_msgs_per_subject)
+        {
+            lints.push(Lint::new(
+                "discard",
+                format!(
+                    "stream {} uses DiscardPolicy::New without setting any \
+                    of max_msgs, max_bytes, max_age or max_msgs_per_subject, \
+                    so no messages are ever discarded",
+                    self.name
+                ),
+            ));
+        }
+
+        lint_replicas(self.num_replicas, &mut lints);
+        lints
+    }
+
+    /// Checks this stream's configuration against the consumers that
+    /// read from it, returning a `Lint` for every combination that the
+    /// server accepts but that is unlikely to behave as intended.
+    ///
+    /// Currently this flags consumers with `AckPolicy::Explicit` on a
+    /// stream with `no_ack` set, because acknowledgements are disabled
+    /// for the whole stream in that case.
+    pub fn validate_against_consumers(
+        &self,
+        consumers: &[ConsumerConfig],
+    ) -> Vec<Lint> {
+        let mut lints = vec![];
+
+        if self.no_ack {
+            for consumer in consumers {
+                if consumer.ack_policy == AckPolicy::Explicit {
+                    lints.push(Lint::new(
+                        "no_ack",
+                        format!(
+                            "stream {} has no_ack set, but consumer {} \
+                            uses AckPolicy::Explicit",
+                            self.name,
+                            consumer
+                                .durable_name
+                                .as_deref()
+                                .unwrap_or("<ephemeral>"),
+                        ),
+                    ));
+                }
+            }
+        }
+
+        lints
+    }
+}
+
+/// An advisory about a configuration that is accepted by the server but
+/// is likely not to behave as intended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The name of the configuration field that the advisory is about
+    pub field: &'static str,
+    /// A human-readable description of the problem
+    pub message: String,
+}
+
+impl Lint {
+    fn new(field: &'static str, message: String) -> Lint {
+        Lint { field, message }
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Checks that `consumer` may be added to a stream with the given
+/// configuration that already has the `existing` consumers.
+///
+/// Streams with `RetentionPolicy::WorkQueue` only allow a single consumer
+/// for any subject, so a consumer whose `filter_subject` overlaps with the
+/// filter of an existing consumer is rejected with an
+/// `ErrorKind::InvalidInput` error before it is sent to the server. An
+/// empty `filter_subject` matches every subject of the stream. Streams
+/// with any other retention policy accept any combination of consumers.
+pub fn validate_consumer_for_stream(
+    stream: &StreamConfig,
+    existing: &[ConsumerConfig],
+    consumer: &ConsumerConfig,
+) -> io::Result<()> {
+    if !stream.retention.single_consumer_per_subject() {
+        return Ok(());
+    }
+
+    for other in existing {
+        if other.durable_name.is_some()
+            && other.durable_name == consumer.durable_name
+        {
+            // updating an existing consumer does not add a new one
+            continue;
+        }
+        if filters_overlap(&other.filter_subject, &consumer.filter_subject) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "work queue stream {} already has a consumer {} whose \
+                    filter subject {:?} overlaps with {:?}",
+                    stream.name,
+                    other.durable_name.as_deref().unwrap_or("<ephemeral>"),
+                    other.filter_subject,
+                    consumer.filter_subject,
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a consumer on `stream` for each of `configs` by calling
+/// `create` with the request for it, returning the result of every
+/// request in the order of `configs`, so that one failure does not
+/// prevent the remaining consumers from being created.
+///
+/// Once a request fails because the stream does not exist, no further
+/// requests are made and the remaining consumers fail with the same error.
+/// See `Connection::create_consumers`.
+pub fn create_consumers<F>(
+    stream: &StreamName,
+    configs: Vec<ConsumerConfig>,
+    mut create: F,
+) -> Vec<Result<ConsumerInfo, ApiError>>
+where
+    F: FnMut(&CreateConsumerRequest) -> Result<ConsumerInfo, ApiError>,
+{
+    let mut stream_not_found: Option<ApiError> = None;
+    configs
+        .into_iter()
+        .map(|config| {
+            if let Some(ref error) = stream_not_found {
+                return Err(error.clone());
+            }
+            let req = CreateConsumerRequest {
+                stream_name: stream.clone(),
+                config,
+                action: ConsumerAction::CreateOrUpdate,
+            };
+            let result = create(&req);
+            if let Err(ref error) = result {
+                if error.is_stream_not_found() {
+                    stream_not_found = Some(error.clone());
+                }
+            }
+            result
+        })
+        .collect()
+}
+
+/// Checks that `subject` consists of non-empty tokens and only uses the
+/// `>` wildcard as its last token.
+fn validate_subject(field: &str, subject: &str) -> io::Result<()> {
+    let invalid = |reason: &str| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} {:?} is not a valid subject: {}",
+                field, subject, reason
+            ),
+        ))
+    };
+
+    if subject.is_empty() {
+        return invalid("it is empty");
+    }
+    let mut tokens = subject.split('.').peekable();
+    while let Some(token) = tokens.next() {
+        if token.is_empty() {
+            return invalid("it contains an empty token");
+        }
+        if token.contains(char::is_whitespace) {
+            return invalid("it contains whitespace");
+        }
+        if token == ">" && tokens.peek().is_some() {
+            return invalid("`>` may only be used as the last token");
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if a message may be matched by both consumer filters,
+/// treating an empty filter as matching everything.
+fn filters_overlap(a: &str, b: &str) -> bool {
+    a.is_empty() || b.is_empty() || subject_overlaps(a, b)
+}
+
+/// Returns `true` if any subject in `a` may match the same message as
+/// any subject in `b`, taking wildcards into account: `*` matches any
+/// single token and `>` matches one or more trailing tokens. This is the
+/// check the server applies to reject streams with overlapping subjects.
+///
+/// # Examples
+///
+/// ```
+/// use nats::jetstream::subjects_overlap;
+///
+/// let s = |subjects: &[&str]| {
+///     subjects.iter().map(|s| s.to_string()).collect::<Vec<_>>()
+/// };
+/// assert!(subjects_overlap(&s(&["a.*"]), &s(&["a.b"])));
+/// assert!(!subjects_overlap(&s(&["a.*"]), &s(&["a.b.c"])));
+/// ```
+pub fn subjects_overlap(a: &[String], b: &[String]) -> bool {
+    a.iter().any(|a| b.iter().any(|b| subject_overlaps(a, b)))
+}
+
+fn subject_overlaps(a: &str, b: &str) -> bool {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(">"), Some(_)) | (Some(_), Some(">")) => return true,
+            (Some(x), Some(y)) if x == y || x == "*" || y == "*" => {}
+            _ => return false,
+        }
+    }
+}
+
+/// The largest number of replicas supported by a clustered JetStream.
+const MAX_REPLICAS: usize = 5;
+
+fn validate_replicas(replicas: usize) -> io::Result<()> {
+    if replicas > MAX_REPLICAS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "num_replicas must be at most {}, got {}",
+                MAX_REPLICAS, replicas
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn lint_replicas(replicas: usize, lints: &mut Vec<Lint>) {
+    if matches!(replicas, 2 | 4) {
+        lints.push(Lint::new(
+            "num_replicas",
+            format!(
+                "{} replicas tolerate no more failures than {}, \
+                use an odd number of replicas",
+                replicas,
+                replicas - 1
+            ),
+        ));
+    }
+}
+
+/// Deserializes a number that may also be encoded as a string, as some
+/// proxies do for large limits to avoid losing precision in JavaScript,
+/// e.g. `"max_bytes": "9223372036854775807"`.
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString<T> {
+        Number(T),
+        String(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(n) => Ok(n),
+        NumberOrString::String(s) => {
+            s.trim().parse().map_err(de::Error::custom)
+        }
+    }
+}
+
+fn is_default<T: Default + Eq>(t: &T) -> bool {
+    t == &T::default()
+}
+
+/// Returns `true` for lists that are `None` or empty.
+fn is_none_or_empty<T>(list: &Option<Vec<T>>) -> bool {
+    list.as_ref().map_or(true, Vec::is_empty)
+}
+
+/// Returns `true` for the limits that are unlimited, `None` and `-1`.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_unlimited(limit: &Option<i64>) -> bool {
+    matches!(limit, None | Some(-1))
+}
+
+/// Deserializes an optional duration, treating the negative numbers that
+/// the server reports for durations that have no value as `None`.
+fn negative_as_none<'de, D>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DurationOrNegative {
+        Duration(Duration),
+        Negative(i64),
+    }
+
+    match Option::<DurationOrNegative>::deserialize(deserializer)? {
+        Some(DurationOrNegative::Duration(duration)) => Ok(Some(duration)),
+        Some(DurationOrNegative::Negative(nanos)) if nanos < 0 => Ok(None),
+        Some(DurationOrNegative::Negative(nanos)) => Err(de::Error::custom(
+            format!("invalid duration of {}ns", nanos),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes an optional limit, treating the -1 that the server reports
+/// for unlimited limits as `None`.
+fn unlimited_as_none<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let limit = Option::<i64>::deserialize(deserializer)?;
+    Ok(limit.filter(|limit| *limit != -1))
+}
+
+/// Serializes a config as pretty-printed JSON without any `null`
+/// values, rewriting the given nanosecond fields as duration strings.
+fn to_cli_json<T: Serialize>(
+    config: &T,
+    duration_fields: &[&str],
+) -> io::Result<String> {
+    let mut value = serde_json::to_value(config)?;
+    if let serde_json::Value::Object(ref mut map) = value {
+        map.retain(|_, v| !v.is_null());
+        for field in duration_fields {
+            if let Some(nanos) = map.get(*field).and_then(|v| v.as_u64()) {
+                let duration = Duration::from_nanos(nanos).to_string();
+                map.insert((*field).to_string(), duration.into());
+            }
+        }
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+impl fmt::Display for StreamConfig {
+    /// Formats a one-line summary of the stream's subjects, retention,
+    /// storage and limits, using `∞` for limits that are not set.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn limit<T: Default + PartialOrd>(
+            limit: T,
+            format: fn(T) -> String,
+        ) -> String {
+            if limit > T::default() {
+                format(limit)
+            } else {
+                "\u{221e}".to_string()
+            }
+        }
+
+        write!(
+            f,
+            "{}: subjects=[{}] retention={} storage={} replicas={} \
+            max_msgs={} max_bytes={} max_age={} max_msgs_per_subject={}",
+            self.name,
+            self.effective_subjects().join(", "),
+            serde_name(&self.retention),
+            serde_name(&self.storage),
+            self.num_replicas.max(1),
+            limit(self.max_msgs, |n| n.to_string()),
+            limit(self.max_bytes, format_bytes),
+            limit(self.max_age, |max_age| max_age.to_string()),
+            limit(self.max_msgs_per_subject, |n| n.to_string()),
+        )
+    }
+}
+
+/// Returns the name that a policy enum is serialized as.
+fn serde_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => "unknown".to_string(),
+    }
+}
+
+/// The `metadata` key that `StreamConfig::with_client_metadata` and
+/// `ConsumerConfig::with_client_metadata` store the client tag under.
+pub const CLIENT_METADATA_KEY: &str = "_nats.client";
+
+/// The client tag stored by `with_client_metadata`, the name and version
+/// of this crate, e.g. `nats/0.15.1`.
+pub const CLIENT_METADATA_VALUE: &str =
+    concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+fn insert_client_metadata(metadata: &mut BTreeMap<String, String>) {
+    metadata
+        .entry(CLIENT_METADATA_KEY.to_string())
+        .or_insert_with(|| CLIENT_METADATA_VALUE.to_string());
+}
+
+/// Serializes a configuration as compact JSON with the keys of every
+/// object sorted, regardless of the order in which they are declared.
+fn canonical_json<T: Serialize>(value: &T) -> io::Result<String> {
+    fn sorted(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => map
+                .into_iter()
+                .map(|(key, value)| (key, sorted(value)))
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .collect(),
+            serde_json::Value::Array(values) => {
+                values.into_iter().map(sorted).collect()
+            }
+            value => value,
+        }
+    }
+
+    let value = serde_json::to_value(value)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    Ok(sorted(value).to_string())
+}
+
+/// Counts the bytes of the compact JSON that a configuration serializes
+/// to, as sent in requests, without buffering it.
+fn serialized_len<T: Serialize>(value: &T) -> io::Result<usize> {
+    struct ByteCounter(usize);
+
+    impl io::Write for ByteCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = ByteCounter(0);
+    serde_json::to_writer(&mut counter, value)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    Ok(counter.0)
+}
+
+/// Formats a size in bytes with the largest binary unit that it is at
+/// least one of, keeping one decimal place, e.g. `"1.5KiB"` or `"10GiB"`.
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [(&str, i128); 5] = [
+        ("TiB", 1 << 40),
+        ("GiB", 1 << 30),
+        ("MiB", 1 << 20),
+        ("KiB", 1 << 10),
+        ("B", 1),
+    ];
+
+    let bytes = i128::from(bytes);
+    let (unit, size) = UNITS
+        .iter()
+        .find(|(_, size)| bytes >= *size)
+        .copied()
+        .unwrap_or(("B", 1));
+    let tenths = bytes * 10 / size;
+    if tenths % 10 == 0 {
+        format!("{}{}", tenths / 10, unit)
+    } else {
+        format!("{}.{}{}", tenths / 10, tenths % 10, unit)
+    }
+}
+
+impl From<&StreamConfig> for StreamConfig {
+    fn from(sc: &StreamConfig) -> StreamConfig {
+        sc.clone()
+    }
+}
+
+impl From<&str> for StreamConfig {
+    fn from(s: &str) -> StreamConfig {
+        StreamConfig {
+            name: s.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&StreamInfo> for StreamConfig {
+    /// Returns the configuration of an existing stream in a form that can
+    /// be used to create it again. The `template_owner` is cleared, as it
+    /// is managed by the server for streams created from a template.
+    fn from(info: &StreamInfo) -> StreamConfig {
+        StreamConfig {
+            template_owner: String::new(),
+            ..info.config.clone()
+        }
+    }
+}
+
+/// Shows config and current state for this stream.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    /// The configuration associated with this stream
+    pub config: StreamConfig,
+    /// The time that this stream was created, or `None` if the server
+    /// did not report it
+    #[serde(
+        default,
+        deserialize_with = "zero_time_as_none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<DateTime>,
+    /// Various metrics associated with this stream
+    pub state: StreamState,
+    /// The time at which the server took this snapshot of the stream's
+    /// state, reported by servers since 2.10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<DateTime>,
+    /// The state of replicating the stream's mirror, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<StreamSourceInfo>,
+    /// The state of replicating each of the stream's sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<StreamSourceInfo>>,
+}
+
+impl StreamInfo {
+    /// Returns how long ago the server took this snapshot, according to
+    /// the `ts` field and the local clock. Returns `None` if the server
+    /// did not report `ts`, or if clock skew places it in the future.
+    pub fn age(&self) -> Option<time::Duration> {
+        self.ts.as_ref().and_then(DateTime::elapsed)
+    }
+
+    /// Returns how long ago the stream was created, according to `created`
+    /// and the local clock. As the time was taken by the server, the
+    /// result is off by the skew between the clocks of the server and the
+    /// client, and is `None` if that skew places it in the future. Also
+    /// returns `None` if `created` is not set.
+    pub fn created_age(&self) -> Option<time::Duration> {
+        self.created.as_ref().and_then(DateTime::elapsed)
+    }
+
+    /// Returns `true` if `state.consumer_count` equals the number of
+    /// consumers that were `listed` separately, e.g. by
+    /// `Connection::list_consumers`. The two are not taken at the same
+    /// time, so they may briefly disagree while consumers are being
+    /// created or deleted, and a health check should only report a
+    /// mismatch that persists.
+    pub fn consumer_count_matches(&self, listed: usize) -> bool {
+        self.state.consumer_count == listed
+    }
+
+    /// Returns how full the stream is relative to the `max_bytes`,
+    /// `max_msgs` and `max_age` limits of its configuration, as
+    /// percentages. The age of the oldest message is measured at `ts`, or
+    /// now if the server did not report it.
+    #[allow(clippy::cast_precision_loss, clippy::float_arithmetic)]
+    pub fn utilization(&self) -> Utilization {
+        let percent = |used: f64, limit: f64| {
+            if limit > 0.0 {
+                Some(used * 100.0 / limit)
+            } else {
+                None
+            }
+        };
+
+        let age = match self.state.first_ts {
+            Some(first_ts) if !self.state.is_empty() => {
+                let now = self.ts.map_or_else(Utc::now, |ts| ts.0);
+                now.signed_duration_since(first_ts.0)
+                    .to_std()
+                    .unwrap_or_default()
+            }
+            _ => time::Duration::default(),
+        };
+
+        Utilization {
+            bytes_pct: percent(
+                self.state.bytes as f64,
+                self.config.max_bytes as f64,
+            ),
+            msgs_pct: percent(
+                self.state.messages as f64,
+                self.config.max_msgs as f64,
+            ),
+            age_pct: percent(
+                age.as_nanos() as f64,
+                self.config.max_age.as_nanos() as f64,
+            ),
+        }
+    }
+}
+
+/// How full a stream is relative to the limits of its configuration,
+/// see `StreamInfo::utilization`. Each value is a percentage, which
+/// may exceed 100 briefly before the server enforces the limit, and is
+/// `None` if the corresponding limit is unlimited.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Utilization {
+    /// The size of the stream relative to `max_bytes`
+    pub bytes_pct: Option<f64>,
+    /// The number of messages relative to `max_msgs`
+    pub msgs_pct: Option<f64>,
+    /// The age of the oldest message relative to `max_age`
+    pub age_pct: Option<f64>,
+}
+
+/// A page of the number of messages per subject in a stream, as returned
+/// in response to a request for the information of the stream with a
+/// subjects filter, see `Connection::stream_subjects`. The server reports
+/// the counts as `state.subjects`, but they are kept out of
+/// `StreamState`, which would otherwise no longer be `Copy`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SubjectsPage {
+    /// The number of messages per subject on this page
+    #[serde(
+        default,
+        rename = "state",
+        deserialize_with = "subjects_from_state",
+        serialize_with = "subjects_into_state"
+    )]
+    pub subjects: HashMap<String, u64>,
+    /// The total number of subjects matching the requested subjects
+    /// filter, which may be more than the server returned in `subjects`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub total: usize,
+    /// The offset of the first subject returned in `subjects`
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub offset: usize,
+    /// The maximum number of subjects the server returns at once
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub limit: usize,
+}
+
+impl SubjectsPage {
+    /// Returns the offset to request the next page of subjects from, or
+    /// `None` if this page completes the set of matching subjects.
+    pub fn next_offset(&self) -> Option<usize> {
+        let received = self.subjects.len();
+        let next = self.offset + received;
+        if received > 0 && next < self.total {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Merges the subjects of the next page into this one, so that
+    /// `next_offset` continues after the merged page. The `total` and
+    /// `limit` are taken from the merged page since it is more recent.
+    pub fn extend(&mut self, page: SubjectsPage) {
+        self.subjects.extend(page.subjects);
+        self.total = page.total;
+        self.limit = page.limit;
+    }
+}
+
+/// The part of the state of a stream that holds its subjects.
+#[derive(Default, Serialize, Deserialize)]
+struct StateSubjects<T> {
+    #[serde(default)]
+    subjects: Option<T>,
+}
+
+fn subjects_from_state<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let state = StateSubjects::deserialize(deserializer)?;
+    Ok(state.subjects.unwrap_or_default())
+}
+
+fn subjects_into_state<S>(
+    subjects: &HashMap<String, u64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    StateSubjects {
+        subjects: Some(subjects),
+    }
+    .serialize(serializer)
+}
+
+/// The request used to query information about a stream.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub(crate) struct StreamInfoRequest {
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub subjects_filter: String,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub offset: usize,
+}
+
+/// A message read directly from a stream, e.g. as part of a
+/// `DirectGetBatch`, along with the metadata the server attaches to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamMsg {
+    /// The subject the message was originally published to
+    pub subject: String,
+    /// The sequence of the message in the stream
+    pub sequence: u64,
+    /// The time the message was stored in the stream
+    pub time: DateTime,
+    /// The headers of the message, including those added by the server
+    pub headers: Option<Headers>,
+    /// The payload of the message
+    pub data: Vec<u8>,
+}
+
+impl StreamMsg {
+    /// Decodes a message from a direct get reply, which describes the
+    /// stored message in its `Nats-Subject`, `Nats-Sequence` and
+    /// `Nats-Time-Stamp` headers.
+    pub fn from_message(msg: &Message) -> io::Result<StreamMsg> {
+        let header = |name: &str| {
+            msg.headers
+                .as_ref()
+                .and_then(|headers| headers.get_first(name))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("the {} header is missing", name),
+                    )
+                })
+        };
+        let invalid = |name: &str, value: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the {} header {:?} is invalid", name, value),
+            )
+        };
+
+        let sequence = header(STREAM_MSG_SEQUENCE)?;
+        let sequence = sequence
+            .parse()
+            .map_err(|_| invalid(STREAM_MSG_SEQUENCE, sequence))?;
+        let time = header(STREAM_MSG_TIME_STAMP)?;
+        let time = time
+            .parse()
+            .map_err(|_| invalid(STREAM_MSG_TIME_STAMP, time))?;
+
+        Ok(StreamMsg {
+            subject: header(STREAM_MSG_SUBJECT)?.to_string(),
+            sequence,
+            time,
+            headers: msg.headers.clone(),
+            data: msg.data.clone(),
+        })
+    }
+}
+
+/// Where a message that a stream received from a mirror or source
+/// originally came from, as described by its `Nats-Stream-Source` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceInfo {
+    /// The name of the stream the message was sourced from
+    pub stream: String,
+    /// The sequence of the message in the stream it was sourced from
+    pub sequence: u64,
+    /// The subject the message was originally published to, which
+    /// servers since 2.10 report
+    pub subject: Option<String>,
+}
+
+impl SourceInfo {
+    /// The header that mirrors and sources add to the messages they copy.
+    pub const HEADER: &'static str = "Nats-Stream-Source";
+
+    /// Parses the `Nats-Stream-Source` header of a message, returning
+    /// `None` if the message was not copied from another stream or the
+    /// header can not be parsed.
+    ///
+    /// The header holds the name of the origin stream, which is suffixed
+    /// with `:<hash>` for streams in other domains or accounts, followed
+    /// by the origin sequence and, since 2.10, the filter and transform of
+    /// the source and the original subject, all separated by spaces.
+    pub fn from_headers(headers: &Headers) -> Option<SourceInfo> {
+        let header = headers.get_first(SourceInfo::HEADER)?;
+        let tokens = header.split_whitespace().collect::<Vec<_>>();
+        let (stream, sequence) = match tokens[..] {
+            [stream, sequence, ..] => (stream, sequence.parse().ok()?),
+            _ => return None,
+        };
+        let stream = stream.split(':').next().unwrap_or(stream);
+
+        Some(SourceInfo {
+            stream: stream.to_string(),
+            sequence,
+            subject: tokens.get(4).map(|subject| (*subject).to_string()),
+        })
+    }
 }
 
+const STREAM_MSG_SUBJECT: &str = "Nats-Subject";
+const STREAM_MSG_SEQUENCE: &str = "Nats-Sequence";
+const STREAM_MSG_TIME_STAMP: &str = "Nats-Time-Stamp";
+
 /// Information about a received message
 #[derive(Debug, Clone)]
 pub struct JetStreamMessageInfo<'a> {
@@ -238,27 +3275,132 @@ pub struct JetStreamMessageInfo<'a> {
     pub token: Option<&'a str>,
 }
 
+/// The position of an ordered consumer, which delivers the messages of a
+/// stream strictly in order without acknowledgements. The client has to
+/// recreate such a consumer, starting after the last message it received,
+/// whenever a message is skipped or the server stops sending heartbeats.
+///
+/// The default state expects the first message of a new consumer, and
+/// counts its creation as the last heartbeat, so that a heartbeat is only
+/// missed once the consumer stays silent for too long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderedState {
+    /// The stream sequence of the last message received
+    pub stream_seq: u64,
+    /// The consumer sequence of the last message received, which the
+    /// next message has to follow directly
+    pub consumer_seq: u64,
+    /// The time that the last message or heartbeat was received
+    pub last_heartbeat: DateTime,
+}
+
+impl Default for OrderedState {
+    fn default() -> OrderedState {
+        OrderedState {
+            stream_seq: 0,
+            consumer_seq: 0,
+            last_heartbeat: DateTime(Utc::now()),
+        }
+    }
+}
+
+impl OrderedState {
+    /// Returns `true` if the message described by `info` does not directly
+    /// follow the last message received, so that the consumer has to be
+    /// recreated with `OrderedState::reset`.
+    pub fn needs_reset(&self, info: &JetStreamMessageInfo<'_>) -> bool {
+        info.consumer_seq != self.consumer_seq + 1
+    }
+
+    /// Records the message described by `info` as received, which also
+    /// counts as a heartbeat.
+    pub fn advance(&mut self, info: &JetStreamMessageInfo<'_>) {
+        self.stream_seq = info.stream_seq;
+        self.consumer_seq = info.consumer_seq;
+        self.heartbeat();
+    }
+
+    /// Records that a heartbeat was received now.
+    pub fn heartbeat(&mut self) {
+        self.last_heartbeat = DateTime(Utc::now());
+    }
+
+    /// Returns `true` if neither a message nor a heartbeat was received for
+    /// twice the consumer's `idle_heartbeat`, which also requires the
+    /// consumer to be recreated.
+    pub fn heartbeat_missed(&self, idle_heartbeat: time::Duration) -> bool {
+        self.last_heartbeat
+            .elapsed()
+            .map_or(false, |elapsed| elapsed > idle_heartbeat * 2)
+    }
+
+    /// Prepares the state for a recreated consumer, which starts over with
+    /// consumer sequence 1, and returns the stream sequence that it has
+    /// to start at, i.e. its `opt_start_seq`.
+    pub fn reset(&mut self) -> u64 {
+        self.consumer_seq = 0;
+        self.heartbeat();
+        self.stream_seq + 1
+    }
+}
+
 /// information about the given stream.
-#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq,
+)]
 pub struct StreamState {
     /// The number of messages contained in this stream
+    #[serde(deserialize_with = "number_or_string")]
     pub messages: u64,
     /// The number of bytes of all messages contained in this stream
+    #[serde(deserialize_with = "number_or_string")]
     pub bytes: u64,
     /// The lowest sequence number still present in this stream
+    #[serde(deserialize_with = "number_or_string")]
     pub first_seq: u64,
-    /// The time associated with the oldest message still present in this stream
-    pub first_ts: DateTime,
+    /// The time associated with the oldest message still present in this
+    /// stream, or `None` if the stream is empty
+    #[serde(
+        default,
+        deserialize_with = "zero_time_as_none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub first_ts: Option<DateTime>,
     /// The last sequence number assigned to a message in this stream
+    #[serde(deserialize_with = "number_or_string")]
     pub last_seq: u64,
-    /// The time that the last message was received by this stream
-    pub last_ts: DateTime,
+    /// The time that the last message was received by this stream, or
+    /// `None` if it never received one
+    #[serde(
+        default,
+        deserialize_with = "zero_time_as_none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_ts: Option<DateTime>,
     /// The number of consumers configured to consume this stream
     pub consumer_count: usize,
 }
 
+impl StreamState {
+    /// Returns `true` if the stream contains no messages. `first_ts` is
+    /// `None` in that case.
+    pub fn is_empty(&self) -> bool {
+        self.messages == 0
+    }
+
+    /// Returns the time between the oldest and the newest message in the
+    /// stream, or `None` if the stream is empty.
+    pub fn time_span(&self) -> Option<time::Duration> {
+        if self.is_empty() {
+            return None;
+        }
+        let (first, last) = (self.first_ts?, self.last_ts?);
+        last.0.signed_duration_since(first.0).to_std().ok()
+    }
+}
+
 /// `DeliverPolicy` determines how the consumer should select the first message to deliver.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum DeliverPolicy {
     /// All causes the consumer to receive the oldest messages still present in the system.
@@ -280,6 +3422,14 @@ pub enum DeliverPolicy {
     /// configured `opt_start_time` parameter.
     #[serde(rename = "by_start_time")]
     ByStartTime = 4,
+    /// `LastPerSubject` will start the consumer with the last message of
+    /// each subject, supported by servers since 2.3.
+    #[serde(rename = "last_per_subject")]
+    LastPerSubject = 5,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
 }
 
 impl Default for DeliverPolicy {
@@ -290,7 +3440,7 @@ impl Default for DeliverPolicy {
 
 /// Determines whether messages will be acknowledged individually,
 /// in batches, or never.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum AckPolicy {
     /// All messages will be individually acknowledged. This is the default.
@@ -303,6 +3453,10 @@ pub enum AckPolicy {
     /// message is acknowledged. Useful for "batching" acknowledgement.
     #[serde(rename = "all")]
     All = 1,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
 }
 
 impl Default for AckPolicy {
@@ -313,7 +3467,7 @@ impl Default for AckPolicy {
 
 /// `ReplayPolicy` controls whether messages are sent to a consumer
 /// as quickly as possible or at the rate that they were originally received at.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum ReplayPolicy {
     /// Sends all messages in a stream to the consumer as quickly as possible. This is the default.
@@ -324,6 +3478,10 @@ pub enum ReplayPolicy {
     /// traffic patterns.
     #[serde(rename = "original")]
     Original = 1,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
 }
 
 impl Default for ReplayPolicy {
@@ -332,17 +3490,113 @@ impl Default for ReplayPolicy {
     }
 }
 
+/// A request to purge some or all of the messages in a stream.
+///
+/// The default request purges every message in the stream.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PurgeRequest {
+    /// Only purges messages with a subject matching this filter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+    /// Purges all messages with a lower sequence than this one, which also
+    /// makes an empty stream continue at this sequence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// Purges all but this many of the newest messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep: Option<u64>,
+}
+
+impl PurgeRequest {
+    /// Creates a request that purges every message before sequence `seq`,
+    /// which is a fast way of deleting a range of messages at the start of
+    /// a stream.
+    ///
+    /// Note that the message at `seq` itself is kept, along with all later
+    /// ones. To delete messages up to and including sequence `n`, use
+    /// `up_to_seq(n + 1)`. Because the server treats a `seq` of 0 as not
+    /// set and would purge the whole stream, `up_to_seq(0)` is sent as
+    /// `up_to_seq(1)`, which purges nothing.
+    pub fn up_to_seq(seq: u64) -> PurgeRequest {
+        PurgeRequest {
+            seq: Some(seq.max(1)),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a request that purges all but the newest `keep` messages,
+    /// e.g. purging a stream of 20 messages with `keep_newest(5)` removes
+    /// the oldest 15 and reports `purged: 15`. Combined with a `filter`,
+    /// the newest `keep` messages of every matching subject are kept.
+    ///
+    /// The server treats a `keep` of 0 as not set, so `keep_newest(0)`
+    /// purges every message, which keeps none of them as well.
+    pub fn keep_newest(keep: u64) -> PurgeRequest {
+        PurgeRequest {
+            keep: Some(keep),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a request that purges every message of a stream in the
+    /// given `state`. With a non-zero `first_seq` the emptied stream
+    /// continues at `first_seq`, or at the sequence after its last
+    /// message if that is higher, since a `seq` at or below `last_seq`
+    /// would keep the messages from there on.
+    pub fn emptying(state: &StreamState, first_seq: u64) -> PurgeRequest {
+        if first_seq == 0 {
+            return PurgeRequest::default();
+        }
+        PurgeRequest::up_to_seq(first_seq.max(state.last_seq.saturating_add(1)))
+    }
+
+    /// Checks this request for values that the server rejects, returning
+    /// an `ErrorKind::InvalidInput` error if both `seq` and `keep` are set.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.seq.is_some() && self.keep.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a purge request can not have both a seq and keep",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of messages that purging a stream in the given
+    /// `state` removes, as reported in `PurgeResponse::purged`, if it can
+    /// be told from the state. This is the case for requests without a
+    /// `filter` or `seq`, which purge all messages or all but the newest
+    /// `keep` of them.
+    pub fn expected_purged(&self, state: &StreamState) -> Option<u64> {
+        if self.filter.is_some() || self.seq.is_some() {
+            return None;
+        }
+        let keep = self.keep.unwrap_or(0);
+        Some(state.messages.saturating_sub(keep))
+    }
+}
+
 /// The response generated by trying ot purge a stream.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub struct PurgeResponse {
     /// Whether the purge request was successful.
     pub success: bool,
-    /// The number of purged messages in a stream.
+    /// The number of messages that were removed from the stream, rather
+    /// than the number of messages left in it, see
+    /// `PurgeResponse::remaining`.
     pub purged: u64,
 }
 
+impl PurgeResponse {
+    /// Returns the number of messages left in a stream that was purged in
+    /// the given `state`, assuming that no messages were added meanwhile.
+    pub fn remaining(&self, before: &StreamState) -> u64 {
+        before.messages.saturating_sub(self.purged)
+    }
+}
+
 /// `RetentionPolicy` determines how messages in a set are retained.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum RetentionPolicy {
     /// `Limits` (default) means that messages are retained until any given limit is reached.
@@ -355,6 +3609,10 @@ pub enum RetentionPolicy {
     /// `WorkQueue` specifies that when the first worker or subscriber acknowledges the message it can be removed.
     #[serde(rename = "workqueue")]
     WorkQueue = 2,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
 }
 
 impl Default for RetentionPolicy {
@@ -363,9 +3621,28 @@ impl Default for RetentionPolicy {
     }
 }
 
+impl RetentionPolicy {
+    /// Returns `true` if acknowledgements of consumers remove messages from
+    /// the stream, which is the case for `Interest` and `WorkQueue`. Under
+    /// `Limits`, messages are only removed by the limits of the stream.
+    pub fn ack_driven(&self) -> bool {
+        match self {
+            RetentionPolicy::Interest | RetentionPolicy::WorkQueue => true,
+            RetentionPolicy::Limits | RetentionPolicy::Unknown => false,
+        }
+    }
+
+    /// Returns `true` if every subject may only be consumed by a single
+    /// consumer, which is the case for `WorkQueue`. See
+    /// `validate_consumer_for_stream`.
+    pub fn single_consumer_per_subject(&self) -> bool {
+        *self == RetentionPolicy::WorkQueue
+    }
+}
+
 /// `DiscardPolicy` determines how we proceed when limits of messages or bytes are hit. The default, `Old` will
 /// remove older messages. `New` will fail to store the new message.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum DiscardPolicy {
     /// will remove older messages when limits are hit.
@@ -374,6 +3651,10 @@ pub enum DiscardPolicy {
     /// will error on a StoreMsg call when limits are hit
     #[serde(rename = "new")]
     New = 1,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
 }
 
 impl Default for DiscardPolicy {
@@ -383,7 +3664,7 @@ impl Default for DiscardPolicy {
 }
 
 /// determines how messages are stored for retention.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum StorageType {
     /// Stream data is kept in files. This is the default.
@@ -392,6 +3673,10 @@ pub enum StorageType {
     /// Stream data is kept only in memory.
     #[serde(rename = "memory")]
     Memory = 1,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
 }
 
 impl Default for StorageType {
@@ -400,18 +3685,44 @@ impl Default for StorageType {
     }
 }
 
+/// determines how the messages of a stream are compressed when stored.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Compression {
+    /// Messages are stored as is. This is the default.
+    #[serde(rename = "none")]
+    None = 0,
+    /// Messages are compressed with S2.
+    #[serde(rename = "s2")]
+    S2 = 1,
+    /// A value reported by a newer server that this client does not know
+    /// about. Configurations containing it can not be sent to the server.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::None
+    }
+}
+
 /// Various limits imposed on a particular account.
 #[derive(
     Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq,
 )]
 pub struct AccountLimits {
     /// Maximum memory for this account (-1 if no limit)
+    #[serde(deserialize_with = "number_or_string")]
     pub max_memory: i64,
     /// Maximum storage for this account (-1 if no limit)
+    #[serde(deserialize_with = "number_or_string")]
     pub max_storage: i64,
     /// Maximum streams for this account (-1 if no limit)
+    #[serde(deserialize_with = "number_or_string")]
     pub max_streams: i64,
     /// Maximum consumers for this account (-1 if no limit)
+    #[serde(deserialize_with = "number_or_string")]
     pub max_consumers: i64,
 }
 
@@ -426,10 +3737,14 @@ pub(crate) struct AccountStats {
     pub limits: AccountLimits,
 }
 
+/// The acknowledgement of a message published to a stream.
 #[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub(crate) struct PubAck {
+pub struct PubAck {
+    /// The stream the message was stored in
     pub stream: String,
+    /// The sequence of the message in the stream
     pub seq: u64,
+    /// Whether the message was recognized as a duplicate and dropped
     #[serde(default, skip_serializing_if = "is_default")]
     pub duplicate: bool,
 }
@@ -476,8 +3791,14 @@ pub struct ConsumerInfo {
     pub stream_name: String,
     /// The consumer's unique name
     pub name: String,
-    /// The time the consumer was created
-    pub created: DateTime,
+    /// The time the consumer was created, or `None` if the server did
+    /// not report it
+    #[serde(
+        default,
+        deserialize_with = "zero_time_as_none",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<DateTime>,
     /// The consumer's configuration
     pub config: ConsumerConfig,
     /// Statistics for delivered messages
@@ -492,9 +3813,64 @@ pub struct ConsumerInfo {
     /// The number of waiting
     pub num_waiting: usize,
     /// The number of pending
+    #[serde(deserialize_with = "number_or_string")]
     pub num_pending: u64,
     /// Information about the consumer's cluster
     pub cluster: ClusterInfo,
+    /// The time at which the server took this snapshot of the consumer's
+    /// state, reported by servers since 2.10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<DateTime>,
+}
+
+impl ConsumerInfo {
+    /// Returns how long ago the server took this snapshot, according to
+    /// the `ts` field and the local clock. Returns `None` if the server
+    /// did not report `ts`, or if clock skew places it in the future.
+    pub fn age(&self) -> Option<time::Duration> {
+        self.ts.as_ref().and_then(DateTime::elapsed)
+    }
+
+    /// Returns how long ago the consumer was created, according to `created`
+    /// and the local clock. As the time was taken by the server, the
+    /// result is off by the skew between the clocks of the server and the
+    /// client, and is `None` if that skew places it in the future. Also
+    /// returns `None` if `created` is not set.
+    pub fn created_age(&self) -> Option<time::Duration> {
+        self.created.as_ref().and_then(DateTime::elapsed)
+    }
+
+    /// Estimates how many messages per second the consumer worked off
+    /// between an `earlier` snapshot of it and this one, based on the
+    /// change of `num_pending` and the `ts` of both snapshots. The rate is
+    /// negative if messages arrived faster than they were consumed.
+    ///
+    /// Returns `None` if either snapshot lacks `ts`, or if `earlier` was
+    /// not taken before this snapshot.
+    #[allow(clippy::cast_precision_loss, clippy::float_arithmetic)]
+    pub fn drain_rate(&self, earlier: &ConsumerInfo) -> Option<f64> {
+        let (now, then) = (self.ts?, earlier.ts?);
+        let elapsed = now.0.signed_duration_since(then.0).to_std().ok()?;
+        if elapsed == time::Duration::default() {
+            return None;
+        }
+
+        let drained = earlier.num_pending as f64 - self.num_pending as f64;
+        Some(drained / elapsed.as_secs_f64())
+    }
+
+    /// Returns `true` if the consumer has as many unacknowledged messages
+    /// in flight as its `max_ack_pending` allows, in which case the server
+    /// stops delivering until some of them are acknowledged. A
+    /// `max_ack_pending` of 0 or less is treated as unlimited.
+    pub fn is_ack_pending_saturated(&self) -> bool {
+        match u64::try_from(self.config.max_ack_pending) {
+            Ok(max_ack_pending) if max_ack_pending > 0 => {
+                self.num_ack_pending as u64 >= max_ack_pending
+            }
+            _ => false,
+        }
+    }
 }
 
 /// Information about the consumer's associated `JetStream` cluster
@@ -510,15 +3886,19 @@ pub struct ClusterInfo {
 )]
 pub struct SequencePair {
     /// How far along the consumer has progressed
+    #[serde(deserialize_with = "number_or_string")]
     pub consumer_seq: u64,
     /// The aggregate for all stream consumers
+    #[serde(deserialize_with = "number_or_string")]
     pub stream_seq: u64,
+    /// The last time that a message was delivered or acknowledged,
+    /// reported by servers since 2.10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_active: Option<DateTime>,
 }
 
 /// for getting next messages for pull based consumers.
-#[derive(
-    Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq,
-)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct NextRequest {
     /// The number of messages that are being requested to be delivered.
     pub batch: usize,
@@ -532,6 +3912,121 @@ pub struct NextRequest {
     /// Consumer has reached MaxAckPending limits.
     #[serde(default, skip_serializing_if = "is_default")]
     pub no_wait: bool,
+    /// The largest total size in bytes of the messages delivered for this
+    /// request, 0 for no limit. Requires a server since 2.8.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub max_bytes: usize,
+    /// How often the server sends a heartbeat while this request waits for
+    /// messages, so that a dead connection is noticed during long pulls.
+    /// Serialized as nanoseconds, and must be less than `expires`, which
+    /// therefore has to be set too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_heartbeat: Option<Duration>,
+    /// The priority group of the consumer that this request pulls for,
+    /// which is required for consumers with `priority_groups`. Requires a
+    /// server since 2.11.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Only deliver messages to this request while the consumer has more
+    /// than this many pending messages, for `PriorityPolicy::Overflow`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_pending: Option<i64>,
+    /// Only deliver messages to this request while the consumer has more
+    /// than this many unacknowledged messages, for
+    /// `PriorityPolicy::Overflow`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_ack_pending: Option<i64>,
+}
+
+impl NextRequest {
+    /// Requests up to `batch` messages with a total size of up to
+    /// `max_bytes`, whichever limit is reached first, that the consumer
+    /// has available right now, without waiting for more to arrive.
+    ///
+    /// If the first message alone is larger than `max_bytes`, the server
+    /// delivers nothing and ends the batch with a status message with
+    /// code 409 and the description "Message Size Exceeds MaxBytes".
+    pub fn fetch_bytes(batch: usize, max_bytes: usize) -> NextRequest {
+        NextRequest {
+            batch,
+            max_bytes,
+            no_wait: true,
+            ..Default::default()
+        }
+    }
+
+    /// Limits `expires` to `max`, e.g. to the `max_expires` of the
+    /// consumer, which the server enforces by rejecting longer requests
+    /// with a 409 status. Since such a consumer also rejects waiting
+    /// requests without `expires`, those are given an `expires` of `max`
+    /// as well. A `max` of zero, like a `max_expires` of 0, sets no limit,
+    /// and `no_wait` requests are left as they are because they never
+    /// wait.
+    pub fn clamp_expires(self, max: Duration) -> NextRequest {
+        let max = usize::try_from(max.as_nanos()).unwrap_or(usize::MAX);
+        if max == 0 || self.no_wait {
+            return self;
+        }
+        let expires = if self.expires == 0 {
+            max
+        } else {
+            self.expires.min(max)
+        };
+        NextRequest { expires, ..self }
+    }
+
+    /// Checks that the server accepts this request, returning an
+    /// `ErrorKind::InvalidInput` error if an `idle_heartbeat` is set that
+    /// is not less than `expires`.
+    pub fn validate(&self) -> io::Result<()> {
+        if let Some(idle_heartbeat) = self.idle_heartbeat {
+            let expires = Duration::from_nanos(self.expires as u64);
+            if idle_heartbeat >= expires {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "the idle_heartbeat of {} must be less than the \
+                         expires of {}",
+                        idle_heartbeat, expires
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks this request against the `priority_groups` of the consumer
+    /// it pulls for, returning an `ErrorKind::InvalidInput` error if its
+    /// `group` is not one of them, or if it is missing although the
+    /// consumer has priority groups. `min_pending` and `min_ack_pending`
+    /// require a `group`.
+    pub fn validate_group(&self, priority_groups: &[String]) -> io::Result<()> {
+        match self.group {
+            Some(ref group) if !priority_groups.contains(group) => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "the group {:?} is not one of the consumer's \
+                         priority groups {:?}",
+                        group, priority_groups
+                    ),
+                ))
+            }
+            None if !priority_groups.is_empty() => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pulling from a consumer with priority groups requires a group",
+            )),
+            None if self.min_pending.is_some()
+                || self.min_ack_pending.is_some() =>
+            {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "min_pending and min_ack_pending require a group",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -554,42 +4049,533 @@ pub(crate) struct SubOpts {
     pub cfg: ConsumerConfig,
 }
 
-/// Options for publishing
+/// Options for publishing to a stream with
+/// `Connection::publish_with_opts`
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub(crate) struct PubOpts {
-    pub ttl: i64,
+pub struct PubOpts {
+    /// How long the stream keeps the message before it expires, sent as
+    /// the `Nats-TTL` header. Requires `allow_msg_ttl` on the stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<Duration>,
+    /// The message id used for duplicate detection
     pub id: String,
-    // Expected last msgId
+    /// Expected last msgId
     pub lid: String,
-    // Expected stream name
+    /// Expected stream name
     pub str: String,
-    // Expected last sequence
+    /// Expected last sequence
     pub seq: u64,
 }
 
+impl PubOpts {
+    /// Returns the headers that send these options to the server, leaving
+    /// out every option that is not set.
+    pub fn headers(&self) -> Headers {
+        let mut headers = vec![];
+        if !self.id.is_empty() {
+            headers.push(("Nats-Msg-Id", self.id.clone()));
+        }
+        if !self.lid.is_empty() {
+            headers.push(("Nats-Expected-Last-Msg-Id", self.lid.clone()));
+        }
+        if !self.str.is_empty() {
+            headers.push(("Nats-Expected-Stream", self.str.clone()));
+        }
+        if self.seq != 0 {
+            headers.push(("Nats-Expected-Last-Sequence", self.seq.to_string()));
+        }
+        if let Some(ttl) = self.ttl {
+            headers.push(("Nats-TTL", ttl.to_string()));
+        }
+
+        headers
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect()
+    }
+
+    /// Checks that these options may be used to publish to a stream with
+    /// the given configuration, returning an `ErrorKind::InvalidInput` error
+    /// if a `ttl` is set but the stream does not have `allow_msg_ttl`.
+    ///
+    /// This is a best-effort check against a configuration that may be out
+    /// of date, the server still makes the final decision.
+    pub fn validate_for_stream(&self, stream: &StreamConfig) -> io::Result<()> {
+        if self.ttl.is_some() && !stream.allow_msg_ttl {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "stream {} does not have allow_msg_ttl set, so messages \
+                    published to it can not have a ttl",
+                    stream.name
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// contains info about the `JetStream` usage from the current account.
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct AccountInfo {
     pub(crate) r#type: String,
     /// How much memory is used
+    #[serde(deserialize_with = "number_or_string")]
     pub memory: i64,
     /// How much storage is used
+    #[serde(deserialize_with = "number_or_string")]
     pub storage: i64,
     /// How many streams exist
+    #[serde(deserialize_with = "number_or_string")]
     pub streams: i64,
     /// How many consumers exist
+    #[serde(deserialize_with = "number_or_string")]
     pub consumers: i64,
     /// Aggregated API statistics
     pub api: ApiStats,
     /// Limits placed on the accuont
     pub limits: AccountLimits,
+    /// The `JetStream` domain of the server that answered, reported by
+    /// servers since 2.10 when a domain is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
 }
 
 /// reports on API calls to `JetStream` for this account.
-#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+#[derive(
+    Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq,
+)]
 pub struct ApiStats {
     /// The total number of API requests
     pub total: u64,
     /// The total number of API requests resulting in errors
     pub errors: u64,
+    /// The API feature level of the server, reported by servers since
+    /// 2.10, which is raised whenever the server gains capabilities
+    /// that clients may want to check for before using them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<u64>,
+}
+
+/// The `type` of responses to `Connection::stream_info` and other
+/// requests that return a `StreamInfo`.
+pub const STREAM_INFO_RESPONSE_TYPE: &str =
+    "io.nats.jetstream.api.v1.stream_info_response";
+
+/// The `type` of responses to `Connection::consumer_info` and other
+/// requests that return a `ConsumerInfo`.
+pub const CONSUMER_INFO_RESPONSE_TYPE: &str =
+    "io.nats.jetstream.api.v1.consumer_info_response";
+
+/// The `type` of acknowledgements for published messages, which the
+/// server usually leaves out.
+pub const PUB_ACK_RESPONSE_TYPE: &str =
+    "io.nats.jetstream.api.v1.pub_ack_response";
+
+/// The `type` of responses to `Connection::account_info`.
+pub const ACCOUNT_INFO_RESPONSE_TYPE: &str =
+    "io.nats.jetstream.api.v1.account_info_response";
+
+/// The kinds of `JetStream` API responses that this client knows the
+/// `type` string of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseType {
+    /// A `StreamInfo`, see `STREAM_INFO_RESPONSE_TYPE`
+    StreamInfo,
+    /// A `ConsumerInfo`, see `CONSUMER_INFO_RESPONSE_TYPE`
+    ConsumerInfo,
+    /// A `PubAck`, see `PUB_ACK_RESPONSE_TYPE`
+    PubAck,
+    /// An `AccountInfo`, see `ACCOUNT_INFO_RESPONSE_TYPE`
+    AccountInfo,
+}
+
+impl ResponseType {
+    /// Parses the `type` string of a response, returning `None` if it is
+    /// not one of the known response types.
+    pub fn from_type_str(s: &str) -> Option<ResponseType> {
+        match s {
+            STREAM_INFO_RESPONSE_TYPE => Some(ResponseType::StreamInfo),
+            CONSUMER_INFO_RESPONSE_TYPE => Some(ResponseType::ConsumerInfo),
+            PUB_ACK_RESPONSE_TYPE => Some(ResponseType::PubAck),
+            ACCOUNT_INFO_RESPONSE_TYPE => Some(ResponseType::AccountInfo),
+            _ => None,
+        }
+    }
+
+    /// Returns the `type` string that responses of this type carry.
+    pub fn as_type_str(self) -> &'static str {
+        match self {
+            ResponseType::StreamInfo => STREAM_INFO_RESPONSE_TYPE,
+            ResponseType::ConsumerInfo => CONSUMER_INFO_RESPONSE_TYPE,
+            ResponseType::PubAck => PUB_ACK_RESPONSE_TYPE,
+            ResponseType::AccountInfo => ACCOUNT_INFO_RESPONSE_TYPE,
+        }
+    }
+}
+
+/// A `JetStream` API response with a known `type` string.
+pub trait TypedResponse: serde::de::DeserializeOwned {
+    /// The type of response this is decoded from
+    const RESPONSE_TYPE: ResponseType;
+}
+
+impl TypedResponse for StreamInfo {
+    const RESPONSE_TYPE: ResponseType = ResponseType::StreamInfo;
+}
+
+impl TypedResponse for ConsumerInfo {
+    const RESPONSE_TYPE: ResponseType = ResponseType::ConsumerInfo;
+}
+
+impl TypedResponse for PubAck {
+    const RESPONSE_TYPE: ResponseType = ResponseType::PubAck;
+}
+
+impl TypedResponse for AccountInfo {
+    const RESPONSE_TYPE: ResponseType = ResponseType::AccountInfo;
+}
+
+/// Decodes a `JetStream` API response, returning an
+/// `ErrorKind::InvalidData` error if its `type` does not match the
+/// expected type of `T`, e.g. because the request was sent to the wrong
+/// API subject. Responses without a `type` are accepted, because not all
+/// of them carry one.
+pub fn from_typed_json<T: TypedResponse>(json: &[u8]) -> io::Result<T> {
+    #[derive(Deserialize)]
+    struct Type {
+        #[serde(default)]
+        r#type: String,
+    }
+
+    let Type { r#type } = serde_json::from_slice(json)?;
+    let expected = T::RESPONSE_TYPE.as_type_str();
+    if !r#type.is_empty() && r#type != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected a response of type {}, got {}", expected, r#type),
+        ));
+    }
+
+    Ok(serde_json::from_slice(json)?)
+}
+
+/// The prefix of the subjects that `JetStream` publishes advisories on.
+/// Subscribing to `$JS.EVENT.ADVISORY.>` receives all of them.
+pub const ADVISORY_PREFIX: &str = "$JS.EVENT.ADVISORY";
+
+/// An event that `JetStream` publishes on a subject below
+/// `ADVISORY_PREFIX`, decoded according to its `type` string, see
+/// `Advisory::from_message`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum Advisory {
+    /// A message reached the `max_deliver` of a consumer and is not
+    /// delivered again
+    #[serde(rename = "io.nats.jetstream.advisory.v1.max_deliver")]
+    MaxDeliver(MaxDeliverAdvisory),
+    /// A message was terminated with `AckKind::Term` and is not delivered
+    /// again
+    #[serde(rename = "io.nats.jetstream.advisory.v1.terminated")]
+    Terminated(TerminatedAdvisory),
+    /// A consumer was created or deleted
+    #[serde(rename = "io.nats.jetstream.advisory.v1.consumer_action")]
+    ConsumerAction(ConsumerActionAdvisory),
+    /// A stream was created, updated or deleted
+    #[serde(rename = "io.nats.jetstream.advisory.v1.stream_action")]
+    StreamAction(StreamActionAdvisory),
+    /// A server ran out of storage for `JetStream`
+    #[serde(rename = "io.nats.jetstream.advisory.v1.server_out_of_space")]
+    ServerOutOfStorage(ServerOutOfStorageAdvisory),
+    /// An advisory of a type that this client does not know about
+    #[serde(other)]
+    Unknown,
+}
+
+impl Advisory {
+    /// Decodes an advisory from the payload of a message received on a
+    /// subject below `ADVISORY_PREFIX`, returning an error if it is not
+    /// valid JSON or lacks the fields of its `type`. Advisories of other
+    /// types decode as `Advisory::Unknown`.
+    pub fn from_message(msg: &Message) -> io::Result<Advisory> {
+        Ok(serde_json::from_slice(&msg.data)?)
+    }
+
+    /// Returns whether the advisory reports a message that the consumer
+    /// gave up on, either because it reached `max_deliver` or because a
+    /// client terminated it, whatever the `reason`. Such messages stay in
+    /// the stream but are not delivered to the consumer again.
+    pub fn is_poison_message(&self) -> bool {
+        matches!(self, Advisory::MaxDeliver(_) | Advisory::Terminated(_))
+    }
+
+    /// Returns the subject of `MaxDeliver` advisories for `consumer` on
+    /// `stream`, either of which may be `*` to match any.
+    pub fn max_deliver_subject(stream: &str, consumer: &str) -> String {
+        format!(
+            "{}.CONSUMER.MAX_DELIVERIES.{}.{}",
+            ADVISORY_PREFIX, stream, consumer
+        )
+    }
+
+    /// Returns the subject of `Terminated` advisories for `consumer` on
+    /// `stream`, either of which may be `*` to match any.
+    pub fn terminated_subject(stream: &str, consumer: &str) -> String {
+        format!(
+            "{}.CONSUMER.MSG_TERMINATED.{}.{}",
+            ADVISORY_PREFIX, stream, consumer
+        )
+    }
+
+    /// Returns the subject of `ConsumerAction` advisories for `consumer`
+    /// on `stream` with the given `action`, or of all actions if `action`
+    /// is `None`. The stream and consumer may be `*` to match any.
+    pub fn consumer_action_subject(
+        action: Option<AdvisoryAction>,
+        stream: &str,
+        consumer: &str,
+    ) -> String {
+        format!(
+            "{}.CONSUMER.{}.{}.{}",
+            ADVISORY_PREFIX,
+            action.map_or("*", AdvisoryAction::subject_token),
+            stream,
+            consumer
+        )
+    }
+
+    /// Returns the subject of `StreamAction` advisories for `stream` with
+    /// the given `action`, or of all actions if `action` is `None`. The
+    /// stream may be `*` to match any.
+    pub fn stream_action_subject(
+        action: Option<AdvisoryAction>,
+        stream: &str,
+    ) -> String {
+        format!(
+            "{}.STREAM.{}.{}",
+            ADVISORY_PREFIX,
+            action.map_or("*", AdvisoryAction::subject_token),
+            stream
+        )
+    }
+
+    /// Returns the subject of `ServerOutOfStorage` advisories.
+    pub fn server_out_of_storage_subject() -> String {
+        format!("{}.SERVER.OUT_OF_STORAGE", ADVISORY_PREFIX)
+    }
+}
+
+/// Reports that a message reached the `max_deliver` of a consumer, see
+/// `Advisory::MaxDeliver`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MaxDeliverAdvisory {
+    /// The unique id of the advisory
+    pub id: String,
+    /// When the advisory was created
+    pub timestamp: DateTime,
+    /// The stream of the consumer
+    pub stream: String,
+    /// The consumer that failed to deliver the message
+    pub consumer: String,
+    /// The stream sequence of the message
+    #[serde(deserialize_with = "number_or_string")]
+    pub stream_seq: u64,
+    /// How often the message was delivered
+    #[serde(deserialize_with = "number_or_string")]
+    pub deliveries: u64,
+    /// The `JetStream` domain of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// Reports that a message was terminated by a client, see
+/// `Advisory::Terminated`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TerminatedAdvisory {
+    /// The unique id of the advisory
+    pub id: String,
+    /// When the advisory was created
+    pub timestamp: DateTime,
+    /// The stream of the consumer
+    pub stream: String,
+    /// The consumer that the message was terminated on
+    pub consumer: String,
+    /// The consumer sequence of the message
+    #[serde(deserialize_with = "number_or_string")]
+    pub consumer_seq: u64,
+    /// The stream sequence of the message
+    #[serde(deserialize_with = "number_or_string")]
+    pub stream_seq: u64,
+    /// How often the message was delivered
+    #[serde(deserialize_with = "number_or_string")]
+    pub deliveries: u64,
+    /// Why the client terminated the message, if it gave a reason
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The `JetStream` domain of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// Reports that a consumer was created or deleted, see
+/// `Advisory::ConsumerAction`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ConsumerActionAdvisory {
+    /// The unique id of the advisory
+    pub id: String,
+    /// When the advisory was created
+    pub timestamp: DateTime,
+    /// The stream of the consumer
+    pub stream: String,
+    /// The name of the consumer
+    pub consumer: String,
+    /// What happened to the consumer
+    pub action: AdvisoryAction,
+    /// The `JetStream` domain of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// Reports that a stream was created, updated or deleted, see
+/// `Advisory::StreamAction`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StreamActionAdvisory {
+    /// The unique id of the advisory
+    pub id: String,
+    /// When the advisory was created
+    pub timestamp: DateTime,
+    /// The name of the stream
+    pub stream: String,
+    /// What happened to the stream
+    pub action: AdvisoryAction,
+    /// The `JetStream` domain of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// Reports that a server ran out of storage, see
+/// `Advisory::ServerOutOfStorage`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ServerOutOfStorageAdvisory {
+    /// The unique id of the advisory
+    pub id: String,
+    /// When the advisory was created
+    pub timestamp: DateTime,
+    /// The name of the server
+    pub server: String,
+    /// The id of the server
+    pub server_id: String,
+    /// The stream that could not store a message, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    /// The cluster of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// The `JetStream` domain of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+/// What happened to the stream or consumer of a `StreamAction` or
+/// `ConsumerAction` advisory.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum AdvisoryAction {
+    /// The stream or consumer was created
+    #[serde(rename = "create")]
+    Create = 0,
+    /// The stream was updated
+    #[serde(rename = "modify")]
+    Modify = 1,
+    /// The stream or consumer was deleted
+    #[serde(rename = "delete")]
+    Delete = 2,
+    /// A value reported by a newer server that this client does not know
+    /// about.
+    #[serde(other, rename = "unknown")]
+    Unknown = 255,
+}
+
+impl Default for AdvisoryAction {
+    fn default() -> AdvisoryAction {
+        AdvisoryAction::Create
+    }
+}
+
+impl AdvisoryAction {
+    /// Returns the token of the advisory subject for this action, or `*`
+    /// for `Unknown`.
+    fn subject_token(self) -> &'static str {
+        match self {
+            AdvisoryAction::Create => "CREATED",
+            AdvisoryAction::Modify => "UPDATED",
+            AdvisoryAction::Delete => "DELETED",
+            AdvisoryAction::Unknown => "*",
+        }
+    }
+}
+
+/// The prefix of the subjects that `JetStream` publishes metrics on, such
+/// as `AckMetric`s.
+pub const METRIC_PREFIX: &str = "$JS.EVENT.METRIC";
+
+/// The `type` of `AckMetric`s.
+pub const ACK_METRIC_TYPE: &str = "io.nats.jetstream.metric.v1.consumer_ack";
+
+/// A sample of an acknowledgement, which the server publishes for the
+/// share of acknowledged messages given by the consumer's
+/// `sample_frequency`, see `AckMetric::subject`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AckMetric {
+    /// The unique id of the metric
+    pub id: String,
+    /// When the metric was created
+    pub timestamp: DateTime,
+    /// The stream of the consumer
+    pub stream: String,
+    /// The consumer that the message was acknowledged on
+    pub consumer: String,
+    /// The consumer sequence of the message
+    #[serde(deserialize_with = "number_or_string")]
+    pub consumer_seq: u64,
+    /// The stream sequence of the message
+    #[serde(deserialize_with = "number_or_string")]
+    pub stream_seq: u64,
+    /// The time between the last delivery of the message and its
+    /// acknowledgement, sent as `ack_time`
+    #[serde(rename = "ack_time")]
+    pub delay: Duration,
+    /// How often the message was delivered
+    #[serde(deserialize_with = "number_or_string")]
+    pub deliveries: u64,
+    /// The `JetStream` domain of the server, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+impl AckMetric {
+    /// Decodes a metric from the payload of a message received on an
+    /// `AckMetric::subject`, returning an `ErrorKind::InvalidData` error
+    /// if its `type` is not `ACK_METRIC_TYPE`.
+    pub fn from_message(msg: &Message) -> io::Result<AckMetric> {
+        #[derive(Deserialize)]
+        struct Type {
+            r#type: String,
+        }
+
+        let Type { r#type } = serde_json::from_slice(&msg.data)?;
+        if r#type != ACK_METRIC_TYPE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected an ack metric, got {}", r#type),
+            ));
+        }
+        Ok(serde_json::from_slice(&msg.data)?)
+    }
+
+    /// Returns the subject of the `AckMetric`s of `consumer` on `stream`,
+    /// either of which may be `*` to match any.
+    pub fn subject(stream: &str, consumer: &str) -> String {
+        format!("{}.CONSUMER.ACK.{}.{}", METRIC_PREFIX, stream, consumer)
+    }
 }
@@ -0,0 +1,873 @@
+// Copyright 2021 The NATS Authors
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for the Key-Value store layered on top of `JetStream`.
+//!
+//! Each bucket is backed by a stream named `KV_<bucket>` that captures
+//! the subjects `$KV.<bucket>.>`, and each key is stored on its own
+//! subject `$KV.<bucket>.<key>`.
+//!
+//! # Examples
+//!
+//! ```
+//! # fn main() -> std::io::Result<()> {
+//! let subject = nats::kv::key_subject("config", "app.log_level")?;
+//! assert_eq!(subject, "$KV.config.app.log_level");
+//! # Ok(()) }
+//! ```
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Error, ErrorKind};
+use std::time::{self, SystemTime};
+
+use crate::jetstream::{
+    AckPolicy, ConsumerAction, ConsumerConfig, ConsumerInfo,
+    CreateConsumerRequest, DeliverPolicy, DiscardPolicy, Duration,
+    OrderedState, PurgeRequest, StorageType, StreamConfig, StreamInfo,
+    StreamName,
+};
+use crate::{Connection, Headers, Message, Subscription};
+
+/// The prefix used for all subjects of Key-Value buckets.
+pub const KV_SUBJECT_PREFIX: &str = "$KV.";
+
+/// The prefix of the names of the streams backing Key-Value buckets.
+pub const KV_STREAM_PREFIX: &str = "KV_";
+
+/// The largest number of revisions a bucket may keep per key.
+pub const MAX_HISTORY: i64 = 64;
+
+/// Checks that a name may be used for a Key-Value or object store bucket.
+///
+/// Bucket names become part of stream names and subjects, so they must be
+/// non-empty and consist only of ASCII alphanumerics, `_` and `-`. In
+/// particular they may not contain `.`, which would split the subjects
+/// derived from them into additional tokens. Invalid names are reported
+/// as an `ErrorKind::InvalidInput` error wrapping a `BucketNameError`.
+pub fn validate_bucket_name(name: &str) -> io::Result<()> {
+    if name.is_empty() {
+        return Err(BucketNameError::Empty.into());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-')))
+    {
+        return Err(BucketNameError::InvalidChar {
+            name: name.to_string(),
+            c,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// The error wrapped in the `io::Error` returned for a name that can not
+/// be used for a bucket, see `validate_bucket_name`. Like the other
+/// errors of this crate it comes as an `io::Error`, here of kind
+/// `ErrorKind::InvalidInput`, and can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BucketNameError {
+    /// The name is empty
+    Empty,
+    /// The name contains a character that bucket names may not contain,
+    /// such as `.`
+    InvalidChar {
+        /// The invalid name
+        name: String,
+        /// The first invalid character
+        c: char,
+    },
+}
+
+impl fmt::Display for BucketNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BucketNameError::Empty => {
+                write!(f, "the bucket name must not be empty")
+            }
+            BucketNameError::InvalidChar { name, c } => write!(
+                f,
+                "the bucket name {:?} contains the invalid character {:?}",
+                name, c
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BucketNameError {}
+
+impl From<BucketNameError> for io::Error {
+    fn from(error: BucketNameError) -> io::Error {
+        io::Error::new(ErrorKind::InvalidInput, error)
+    }
+}
+
+/// The configuration of a Key-Value bucket, which is converted into the
+/// configuration of its backing stream with `StreamConfig::try_from`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KeyValueConfig {
+    /// The name of the bucket, see `validate_bucket_name`
+    pub bucket: String,
+    /// How many revisions to keep per key, at most `MAX_HISTORY`. 0 keeps
+    /// only the latest revision.
+    pub history: i64,
+    /// How long to keep each revision, or `None` to keep them forever
+    pub max_age: Option<Duration>,
+    /// The largest total size of the bucket in bytes, -1 for unlimited
+    pub max_bytes: i64,
+    /// The largest size of a single value in bytes, -1 for unlimited
+    pub max_value_size: i32,
+    /// The type of storage backend, `File` (default) and `Memory`
+    pub storage: StorageType,
+    /// How many replicas to keep of each value in a clustered `JetStream`
+    pub num_replicas: usize,
+}
+
+impl TryFrom<&KeyValueConfig> for StreamConfig {
+    type Error = io::Error;
+
+    fn try_from(config: &KeyValueConfig) -> io::Result<StreamConfig> {
+        validate_bucket_name(&config.bucket)?;
+        if config.history > MAX_HISTORY {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "a bucket may keep at most {} revisions per key, got {}",
+                    MAX_HISTORY, config.history
+                ),
+            ));
+        }
+
+        Ok(StreamConfig {
+            name: format!("{}{}", KV_STREAM_PREFIX, config.bucket),
+            subjects: Some(vec![format!(
+                "{}{}.>",
+                KV_SUBJECT_PREFIX, config.bucket
+            )]),
+            max_msgs_per_subject: config.history.max(1),
+            max_bytes: config.max_bytes,
+            max_msg_size: config.max_value_size,
+            max_age: config.max_age.unwrap_or_default(),
+            storage: config.storage,
+            num_replicas: config.num_replicas.max(1),
+            discard: DiscardPolicy::New,
+            ..StreamConfig::limits()
+        })
+    }
+}
+
+impl TryFrom<&StreamInfo> for KeyValueConfig {
+    type Error = io::Error;
+
+    /// Recovers the configuration of a bucket from its backing stream,
+    /// returning an `ErrorKind::InvalidData` error wrapping a `KvError` if
+    /// the stream is not named `KV_<bucket>` after a valid bucket name, or
+    /// does not capture the subjects `$KV.<bucket>.>`.
+    fn try_from(info: &StreamInfo) -> io::Result<KeyValueConfig> {
+        let config = &info.config;
+        let bucket = config
+            .name
+            .strip_prefix(KV_STREAM_PREFIX)
+            .filter(|bucket| validate_bucket_name(bucket).is_ok())
+            .ok_or_else(|| KvError::NotABucket {
+                stream: config.name.clone(),
+            })?;
+
+        let subject = format!("{}{}.>", KV_SUBJECT_PREFIX, bucket);
+        if !config.effective_subjects().contains(&subject) {
+            return Err(KvError::MissingSubjects {
+                stream: config.name.clone(),
+                subjects: subject,
+            }
+            .into());
+        }
+
+        let max_age = Some(config.max_age)
+            .filter(|max_age| *max_age > Duration::default());
+
+        Ok(KeyValueConfig {
+            bucket: bucket.to_string(),
+            history: config.max_msgs_per_subject.max(1),
+            max_age,
+            max_bytes: config.max_bytes,
+            max_value_size: config.max_msg_size,
+            storage: config.storage,
+            num_replicas: config.num_replicas,
+        })
+    }
+}
+
+/// The error wrapped in the `io::Error` returned when a stream does not
+/// back a Key-Value bucket, see `KeyValueConfig::try_from`. Like the
+/// other errors of this crate it comes as an `io::Error`, here of kind
+/// `ErrorKind::InvalidData`, and can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvError {
+    /// The stream is not named `KV_<bucket>` after a valid bucket name
+    NotABucket {
+        /// The name of the stream
+        stream: String,
+    },
+    /// The stream does not capture the subjects of its bucket
+    MissingSubjects {
+        /// The name of the stream
+        stream: String,
+        /// The subjects `$KV.<bucket>.>` that it would have to capture
+        subjects: String,
+    },
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::NotABucket { stream } => write!(
+                f,
+                "the stream {} does not back a Key-Value bucket",
+                stream
+            ),
+            KvError::MissingSubjects { stream, subjects } => write!(
+                f,
+                "the stream {} does not capture the subjects {}",
+                stream, subjects
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KvError {}
+
+impl From<KvError> for io::Error {
+    fn from(error: KvError) -> io::Error {
+        io::Error::new(ErrorKind::InvalidData, error)
+    }
+}
+
+/// Returns `true` if the character may appear in a key.
+fn is_valid_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '/' | '_' | '=')
+}
+
+/// Checks that a key may be used as a subject suffix in a bucket.
+///
+/// Keys may contain `.` characters, which become separate subject
+/// tokens, but every token must be non-empty and consist only of
+/// ASCII alphanumerics or `-`, `/`, `_` and `=`. In particular the
+/// wildcards `*` and `>` are rejected. Invalid keys are reported as an
+/// `ErrorKind::InvalidInput` error wrapping a `KeyError`.
+pub fn validate_key(key: &str) -> io::Result<()> {
+    if key.is_empty() {
+        return Err(KeyError::Empty.into());
+    }
+
+    for token in key.split('.') {
+        if token.is_empty() {
+            return Err(KeyError::EmptyToken {
+                key: key.to_string(),
+            }
+            .into());
+        }
+        if let Some(c) = token.chars().find(|c| !is_valid_key_char(*c)) {
+            return Err(KeyError::InvalidChar {
+                key: key.to_string(),
+                c,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The error wrapped in the `io::Error` returned for a key that can not
+/// be used in a bucket, see `validate_key`. Like the other errors of this
+/// crate it comes as an `io::Error`, here of kind
+/// `ErrorKind::InvalidInput`, and can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyError {
+    /// The key is empty
+    Empty,
+    /// The key contains an empty token, e.g. `app..level`
+    EmptyToken {
+        /// The invalid key
+        key: String,
+    },
+    /// The key contains a character that keys may not contain, such as
+    /// the wildcards `*` and `>`
+    InvalidChar {
+        /// The invalid key
+        key: String,
+        /// The first invalid character
+        c: char,
+    },
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Empty => write!(f, "the key must not be empty"),
+            KeyError::EmptyToken { key } => {
+                write!(f, "the key {:?} contains an empty token", key)
+            }
+            KeyError::InvalidChar { key, c } => write!(
+                f,
+                "the key {:?} contains the invalid character {:?}",
+                key, c
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+impl From<KeyError> for io::Error {
+    fn from(error: KeyError) -> io::Error {
+        io::Error::new(ErrorKind::InvalidInput, error)
+    }
+}
+
+/// Builds the subject `$KV.<bucket>.<key>` that a key is read from and
+/// written to, validating the bucket name and the key first, see
+/// `validate_key` for the `KeyError` returned for invalid keys.
+pub fn key_subject(bucket: &str, key: &str) -> io::Result<String> {
+    validate_bucket_name(bucket)?;
+    validate_key(key)?;
+
+    Ok(format!("{}{}.{}", KV_SUBJECT_PREFIX, bucket, key))
+}
+
+/// Builds the subject `$KV.<bucket>.<key>` that a watch on the key
+/// listens to. Unlike in `key_subject`, tokens of the key may be the
+/// wildcard `*`, and its last token may be `>`, e.g. `app.*.log_level` or
+/// `>` to watch the whole bucket.
+pub fn watch_subject(bucket: &str, key: &str) -> io::Result<String> {
+    validate_bucket_name(bucket)?;
+    let tokens = key.split('.').count();
+    for (i, token) in key.split('.').enumerate() {
+        if token == "*" || (token == ">" && i + 1 == tokens) {
+            continue;
+        }
+        validate_key(token).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("the key {:?} is not a valid key or wildcard", key),
+            )
+        })?;
+    }
+
+    Ok(format!("{}{}.{}", KV_SUBJECT_PREFIX, bucket, key))
+}
+
+/// The header used to mark a message as a delete or purge operation.
+pub const KV_OPERATION_HEADER: &str = "KV-Operation";
+
+/// The header of an idle heartbeat holding the last consumer sequence
+/// that the server delivered.
+const KV_LAST_CONSUMER_HEADER: &str = "Nats-Last-Consumer";
+
+/// The header of an idle heartbeat holding the subject that a stalled
+/// consumer waits on for a flow control response.
+const KV_CONSUMER_STALLED_HEADER: &str = "Nats-Consumer-Stalled";
+
+/// How often the consumer of a `KvWatch` sends a heartbeat while there are
+/// no updates.
+const KV_WATCH_IDLE_HEARTBEAT: Duration = Duration::from_secs(5);
+
+/// The kind of operation that produced a `KeyValueEntry`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// A value was stored for the key.
+    Put,
+    /// The key was deleted, leaving a tombstone as its latest revision.
+    Delete,
+    /// The key was purged, removing all of its previous revisions.
+    Purge,
+}
+
+impl Operation {
+    fn from_message(msg: &Message) -> Operation {
+        let header = msg
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get(KV_OPERATION_HEADER))
+            .and_then(|values| values.iter().next())
+            .map(String::as_str);
+
+        match header {
+            Some("DEL") => Operation::Delete,
+            Some("PURGE") => Operation::Purge,
+            _ => Operation::Put,
+        }
+    }
+}
+
+/// A single revision of a key in a Key-Value bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValueEntry {
+    /// The bucket the key belongs to
+    pub bucket: String,
+    /// The key this revision was stored under
+    pub key: String,
+    /// The stored value, empty for `Delete` and `Purge` operations
+    pub value: Vec<u8>,
+    /// The revision of this entry, which is its sequence in the backing stream
+    pub revision: u64,
+    /// The number of messages after this one that the consumer delivering
+    /// it still had pending when it was sent
+    pub delta: u64,
+    /// The time that this revision was stored
+    pub created: std::time::SystemTime,
+    /// The operation that produced this revision
+    pub operation: Operation,
+}
+
+impl KeyValueEntry {
+    /// Decodes an entry from a message that was delivered by a
+    /// `JetStream` consumer of a bucket's backing stream.
+    pub fn from_message(msg: &Message) -> io::Result<KeyValueEntry> {
+        let info = msg.jetstream_message_info().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "the message was not delivered by a JetStream consumer",
+            )
+        })?;
+
+        let mut tokens = msg
+            .subject
+            .strip_prefix(KV_SUBJECT_PREFIX)
+            .unwrap_or_default()
+            .splitn(2, '.');
+
+        let (bucket, key) = match (tokens.next(), tokens.next()) {
+            (Some(bucket), Some(key)) if !bucket.is_empty() => (bucket, key),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "the subject {:?} is not a Key-Value key subject",
+                        msg.subject
+                    ),
+                ))
+            }
+        };
+
+        Ok(KeyValueEntry {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            value: msg.data.clone(),
+            revision: info.stream_seq,
+            delta: info.pending,
+            created: info.published,
+            operation: Operation::from_message(msg),
+        })
+    }
+}
+
+/// An iterator over all of the revisions of a key that are retained by its
+/// bucket, oldest first. Iteration stops after the latest revision, which is
+/// detected by the delivering consumer reporting no further pending messages.
+///
+/// Revisions that deleted or purged the key are yielded as entries with
+/// `Operation::Delete` or `Operation::Purge` rather than being skipped.
+pub struct KvHistory {
+    messages: Box<dyn Iterator<Item = Message> + Send>,
+    done: bool,
+}
+
+impl KvHistory {
+    /// Creates a `KvHistory` over messages delivered from a bucket's
+    /// backing stream, in stream order.
+    pub fn new<I>(messages: I) -> KvHistory
+    where
+        I: IntoIterator<Item = Message>,
+        I::IntoIter: Send + 'static,
+    {
+        KvHistory {
+            messages: Box::new(messages.into_iter()),
+            done: false,
+        }
+    }
+}
+
+impl std::iter::FusedIterator for KvHistory {}
+
+impl Iterator for KvHistory {
+    type Item = io::Result<KeyValueEntry>;
+
+    fn next(&mut self) -> Option<io::Result<KeyValueEntry>> {
+        if self.done {
+            return None;
+        }
+
+        let msg = if let Some(msg) = self.messages.next() {
+            msg
+        } else {
+            self.done = true;
+            return None;
+        };
+
+        let entry = KeyValueEntry::from_message(&msg);
+        if let Ok(ref entry) = entry {
+            self.done = entry.delta == 0;
+        }
+        Some(entry)
+    }
+}
+
+/// An event yielded by a `KvWatch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvWatchEvent {
+    /// The latest revision of a watched key, either one of the initial
+    /// values or a live update
+    Entry(KeyValueEntry),
+    /// Marks that all of the initial values were yielded, so that every
+    /// following entry is a live update
+    InitDone,
+}
+
+/// The messages delivered to a `KvWatch`.
+type WatchMessages = Box<dyn Iterator<Item = Message> + Send>;
+
+/// Recreates the consumer of a `KvWatch` at the given stream sequence,
+/// returning its messages and the number of messages it reported as
+/// pending when it was created.
+type WatchResume =
+    Box<dyn FnMut(u64) -> io::Result<(WatchMessages, u64)> + Send>;
+
+/// An iterator over the updates of the watched keys of a bucket, which
+/// first yields the latest revision of every key that has one, then a
+/// single `KvWatchEvent::InitDone`, and then every later update as it
+/// arrives. If no key has a value yet, `InitDone` is yielded right away.
+///
+/// The end of the initial values is detected by the delivering consumer
+/// reporting no further pending messages. The messages are expected from
+/// an ordered consumer: flow control requests are answered, and idle
+/// heartbeats are checked for messages sent before them that never
+/// arrived. When a message is missed, the consumer is recreated after the
+/// last revision yielded, see `KvWatch::with_resume`, or an
+/// `ErrorKind::UnexpectedEof` error ends the watch if it can not be.
+pub struct KvWatch {
+    messages: WatchMessages,
+    resume: Option<WatchResume>,
+    state: OrderedState,
+    init_pending: bool,
+    init_done: bool,
+}
+
+impl KvWatch {
+    /// Creates a `KvWatch` over messages delivered from a bucket's backing
+    /// stream, in stream order, where `initial` is the number of initial
+    /// values that the consumer reported as pending when it was created.
+    pub fn new<I>(messages: I, initial: u64) -> KvWatch
+    where
+        I: IntoIterator<Item = Message>,
+        I::IntoIter: Send + 'static,
+    {
+        KvWatch {
+            messages: Box::new(messages.into_iter()),
+            resume: None,
+            state: OrderedState::default(),
+            init_pending: initial == 0,
+            init_done: false,
+        }
+    }
+
+    /// Recreates the consumer with `resume` whenever a message is missed,
+    /// instead of ending the watch with an error. `resume` is given the
+    /// stream sequence to start at, and returns the messages of the new
+    /// consumer along with the number of messages it reported as pending.
+    pub fn with_resume<F, I>(self, mut resume: F) -> KvWatch
+    where
+        F: FnMut(u64) -> io::Result<(I, u64)> + Send + 'static,
+        I: IntoIterator<Item = Message>,
+        I::IntoIter: Send + 'static,
+    {
+        KvWatch {
+            resume: Some(Box::new(move |start_seq| {
+                let (messages, pending) = resume(start_seq)?;
+                let messages: WatchMessages = Box::new(messages.into_iter());
+                Ok((messages, pending))
+            })),
+            ..self
+        }
+    }
+
+    /// Handles a status message of the consumer, returning `true` if it
+    /// shows that a message was missed.
+    fn handle_status(&mut self, msg: &Message) -> io::Result<bool> {
+        if msg.reply.is_some() {
+            // a flow control request, which pauses the consumer until it
+            // is answered
+            msg.respond(b"")?;
+            return Ok(false);
+        }
+
+        let headers = match msg.headers {
+            Some(ref headers) => headers,
+            None => return Ok(false),
+        };
+        if let Some(stalled) = headers.get_first(KV_CONSUMER_STALLED_HEADER) {
+            msg.client.publish(stalled, None, None, b"")?;
+        }
+        self.state.heartbeat();
+        let last_consumer_seq = headers
+            .get_first(KV_LAST_CONSUMER_HEADER)
+            .and_then(|seq| seq.parse::<u64>().ok());
+        Ok(last_consumer_seq
+            .map_or(false, |seq| seq != self.state.consumer_seq))
+    }
+
+    /// Recreates the consumer after the last message received.
+    fn reset(&mut self) -> io::Result<()> {
+        let resume = match self.resume {
+            Some(ref mut resume) => resume,
+            None => {
+                self.messages = Box::new(std::iter::empty());
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "the watch missed the messages following stream \
+                         sequence {}",
+                        self.state.stream_seq
+                    ),
+                ));
+            }
+        };
+
+        let (messages, pending) = resume(self.state.reset())?;
+        self.messages = messages;
+        if !self.init_done && pending == 0 {
+            self.init_pending = true;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for KvWatch {
+    type Item = io::Result<KvWatchEvent>;
+
+    fn next(&mut self) -> Option<io::Result<KvWatchEvent>> {
+        loop {
+            if self.init_pending {
+                self.init_pending = false;
+                self.init_done = true;
+                return Some(Ok(KvWatchEvent::InitDone));
+            }
+
+            let msg = self.messages.next()?;
+            let is_status = msg.headers.as_ref().map_or(false, |headers| {
+                headers.get_first(Headers::STATUS).is_some()
+            });
+            let missed = if is_status {
+                self.handle_status(&msg)
+            } else {
+                Ok(msg.jetstream_message_info().map_or(false, |info| {
+                    let missed = self.state.needs_reset(&info);
+                    if !missed {
+                        self.state.advance(&info);
+                    }
+                    missed
+                }))
+            };
+            match missed {
+                Ok(true) => {
+                    if let Err(err) = self.reset() {
+                        return Some(Err(err));
+                    }
+                    continue;
+                }
+                Ok(false) if is_status => continue,
+                Ok(false) => {}
+                Err(err) => return Some(Err(err)),
+            }
+
+            let entry = KeyValueEntry::from_message(&msg);
+            if let Ok(ref entry) = entry {
+                self.init_pending = !self.init_done && entry.delta == 0;
+            }
+            return Some(entry.map(KvWatchEvent::Entry));
+        }
+    }
+}
+
+/// Selects the purges that remove the tombstones among the latest
+/// revisions of the keys of a bucket, as yielded by a `KvWatch` before
+/// `KvWatchEvent::InitDone`, see `Connection::kv_purge_deletes`.
+///
+/// A purge is returned for every key whose latest revision is a `Delete`
+/// or `Purge` operation stored at least `older_than` before `now`, or at
+/// any time if `older_than` is `None`. Each purge removes the revisions of
+/// its key up to and including the tombstone, so that a value put after
+/// the tombstone survives. If several revisions of a key are given, only
+/// the latest one counts, so a key that was deleted and put again is not
+/// purged.
+pub fn tombstone_purges<I>(
+    bucket: &str,
+    latest: I,
+    older_than: Option<time::Duration>,
+    now: SystemTime,
+) -> Vec<PurgeRequest>
+where
+    I: IntoIterator<Item = KeyValueEntry>,
+{
+    let mut keys = BTreeMap::<String, KeyValueEntry>::new();
+    for entry in latest {
+        match keys.get(&entry.key) {
+            Some(newer) if newer.revision > entry.revision => {}
+            _ => {
+                keys.insert(entry.key.clone(), entry);
+            }
+        }
+    }
+
+    keys.into_iter()
+        .filter(|(_, entry)| entry.operation != Operation::Put)
+        .filter(|(_, entry)| {
+            older_than.map_or(true, |older_than| {
+                now.duration_since(entry.created)
+                    .map_or(false, |age| age >= older_than)
+            })
+        })
+        .map(|(key, entry)| PurgeRequest {
+            filter: Some(format!("{}{}.{}", KV_SUBJECT_PREFIX, bucket, key)),
+            ..PurgeRequest::up_to_seq(entry.revision + 1)
+        })
+        .collect()
+}
+
+impl Connection {
+    /// Returns an iterator over the retained revisions of a key in the given
+    /// Key-Value bucket, oldest first, ending with the latest revision.
+    pub fn kv_history(&self, bucket: &str, key: &str) -> io::Result<KvHistory> {
+        let filter_subject = key_subject(bucket, key)?;
+        let deliver_subject = self.new_inbox();
+        let sub = self.subscribe(&deliver_subject)?;
+
+        let req = CreateConsumerRequest {
+            stream_name: StreamName::try_from(format!(
+                "{}{}",
+                KV_STREAM_PREFIX, bucket
+            ))?,
+            config: ConsumerConfig {
+                deliver_subject: Some(deliver_subject),
+                deliver_policy: DeliverPolicy::All,
+                ack_policy: AckPolicy::None,
+                filter_subject,
+                ..Default::default()
+            },
+            action: ConsumerAction::CreateOrUpdate,
+        };
+        let subject =
+            format!("{}CONSUMER.CREATE.{}", self.api_prefix(), req.stream_name);
+        let info: ConsumerInfo =
+            self.js_request(&subject, &serde_json::ser::to_vec(&req)?)?;
+
+        let mut history = KvHistory::new(sub);
+        history.done = info.num_pending == 0;
+        Ok(history)
+    }
+
+    /// Watches the keys of the given Key-Value bucket that match `key`,
+    /// which may contain wildcards, see `watch_subject`. The returned
+    /// iterator yields the latest revision of every matching key, then
+    /// `KvWatchEvent::InitDone`, and then every later update.
+    ///
+    /// The updates are delivered by an ordered consumer with flow control
+    /// and idle heartbeats, which is recreated after the last revision
+    /// received if a message is missed. Revisions that were replaced
+    /// while the initial values were being replayed may then be yielded
+    /// before `KvWatchEvent::InitDone` as well.
+    pub fn kv_watch(&self, bucket: &str, key: &str) -> io::Result<KvWatch> {
+        let filter_subject = watch_subject(bucket, key)?;
+        let stream_name =
+            StreamName::try_from(format!("{}{}", KV_STREAM_PREFIX, bucket))?;
+        let (sub, pending) =
+            self.kv_watch_consumer(&stream_name, &filter_subject, None)?;
+
+        let nc = self.clone();
+        Ok(KvWatch::new(sub, pending).with_resume(move |start_seq| {
+            nc.kv_watch_consumer(&stream_name, &filter_subject, Some(start_seq))
+        }))
+    }
+
+    /// Creates the ordered consumer of a `KvWatch`, starting with the latest
+    /// revision of every key, or at `start_seq` when resuming the watch.
+    fn kv_watch_consumer(
+        &self,
+        stream_name: &StreamName,
+        filter_subject: &str,
+        start_seq: Option<u64>,
+    ) -> io::Result<(Subscription, u64)> {
+        let deliver_subject = self.new_inbox();
+        let sub = self.subscribe(&deliver_subject)?;
+
+        let config = ConsumerConfig {
+            deliver_subject: Some(deliver_subject),
+            ack_policy: AckPolicy::None,
+            max_deliver: Some(1),
+            flow_control: true,
+            idle_heartbeat: KV_WATCH_IDLE_HEARTBEAT,
+            filter_subject: filter_subject.to_string(),
+            ..Default::default()
+        };
+        let req = CreateConsumerRequest {
+            stream_name: stream_name.clone(),
+            config: match start_seq {
+                Some(start_seq) => config.deliver_from_seq(start_seq),
+                None => config.deliver_last_per_subject(),
+            },
+            action: ConsumerAction::CreateOrUpdate,
+        };
+        let subject =
+            format!("{}CONSUMER.CREATE.{}", self.api_prefix(), req.stream_name);
+        let info: ConsumerInfo =
+            self.js_request(&subject, &serde_json::ser::to_vec(&req)?)?;
+
+        Ok((sub, info.num_pending))
+    }
+
+    /// Removes the keys of the given Key-Value bucket whose latest revision
+    /// deleted or purged them at least `older_than` ago, or at any time if
+    /// `older_than` is `None`, along with their earlier revisions. Returns
+    /// the number of messages purged from the backing stream.
+    ///
+    /// The latest revisions are read with `Connection::kv_watch`, and the
+    /// tombstones are purged one key at a time as selected by
+    /// `tombstone_purges`.
+    pub fn kv_purge_deletes(
+        &self,
+        bucket: &str,
+        older_than: Option<time::Duration>,
+    ) -> io::Result<u64> {
+        let mut latest = vec![];
+        for event in self.kv_watch(bucket, ">")? {
+            match event? {
+                KvWatchEvent::Entry(entry) => latest.push(entry),
+                KvWatchEvent::InitDone => break,
+            }
+        }
+
+        let stream = format!("{}{}", KV_STREAM_PREFIX, bucket);
+        let mut purged = 0;
+        for req in
+            tombstone_purges(bucket, latest, older_than, SystemTime::now())
+        {
+            purged += self.purge_stream_with(&stream, &req)?.purged;
+        }
+        Ok(purged)
+    }
+}
@@ -185,6 +185,7 @@ mod auth_utils;
 mod client;
 mod connect;
 mod connector;
+mod duration;
 mod headers;
 mod jetstream_types;
 mod message;
@@ -196,6 +197,12 @@ mod subscription;
 /// `JetStream` stream management and consumers.
 pub mod jetstream;
 
+/// Key-Value store built on top of `JetStream` streams.
+pub mod kv;
+
+/// Object store built on top of `JetStream` streams.
+pub mod object_store;
+
 #[cfg(feature = "fault_injection")]
 mod fault_injection;
 
@@ -332,6 +339,18 @@ pub fn connect(nats_url: &str) -> io::Result<Connection> {
     Options::new().connect(nats_url)
 }
 
+/// Fails with `NotFound` if `resp` is the status message that the server
+/// sends when nothing is subscribed to the subject of a request.
+fn check_responders(subject: &str, resp: Message) -> io::Result<Message> {
+    if resp.is_no_responders() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no responders for {}", subject),
+        ));
+    }
+    Ok(resp)
+}
+
 impl Connection {
     /// Connects on a URL with the given options.
     pub(crate) fn connect_with_options(
@@ -433,7 +452,8 @@ impl Connection {
     }
 
     /// Publish a message on the given subject as a request and receive the
-    /// response.
+    /// response. Fails with `NotFound` if nothing is subscribed to the
+    /// subject.
     ///
     /// # Example
     /// ```
@@ -460,12 +480,14 @@ impl Connection {
         )?;
 
         // Wait for the response.
-        sub.next().ok_or_else(|| ErrorKind::ConnectionReset.into())
+        let resp = sub.next().ok_or(ErrorKind::ConnectionReset)?;
+        check_responders(subject, resp)
     }
 
     /// Publish a message on the given subject as a request and receive the
     /// response. This call will return after the timeout duration if no
-    /// response is received.
+    /// response is received, and fails with `NotFound` if nothing is
+    /// subscribed to the subject.
     ///
     /// # Example
     /// ```
@@ -493,11 +515,13 @@ impl Connection {
         )?;
 
         // Wait for the response.
-        sub.next_timeout(timeout)
+        check_responders(subject, sub.next_timeout(timeout)?)
     }
 
     /// Publish a message on the given subject as a request and allow multiple
-    /// responses.
+    /// responses. If nothing is subscribed to the subject, the server's
+    /// status message saying so is not delivered as a response: `next`
+    /// returns `None` for it and `next_timeout` fails with `NotFound`.
     ///
     /// # Example
     /// ```
@@ -515,7 +539,13 @@ impl Connection {
     ) -> io::Result<Subscription> {
         // Publish a request.
         let reply = self.new_inbox();
-        let sub = self.subscribe(&reply)?;
+        let (sid, receiver) = self.0.client.subscribe(&reply, None)?;
+        let sub = Subscription::for_request(
+            sid,
+            reply.clone(),
+            receiver,
+            self.0.client.clone(),
+        );
         self.publish_with_reply_or_headers(
             subject,
             Some(reply.as_str()),
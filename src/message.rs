@@ -48,6 +48,39 @@ impl From<crate::asynk::Message> for Message {
 }
 
 impl Message {
+    /// Creates a new `Message` that is not associated with any connection.
+    /// This is meant for testing code that processes messages, as
+    /// responding to or acknowledging such a message fails with
+    /// `ErrorKind::NotConnected`.
+    #[doc(hidden)]
+    pub fn new(
+        subject: &str,
+        reply: Option<&str>,
+        data: impl AsRef<[u8]>,
+        headers: Option<Headers>,
+    ) -> Message {
+        Message {
+            subject: subject.to_string(),
+            reply: reply.map(String::from),
+            data: data.as_ref().to_vec(),
+            headers,
+            client: Client::closed(),
+            double_acked: Default::default(),
+        }
+    }
+
+    /// Returns `true` if this is the status message with code `503` that
+    /// the server sends in reply to a request when nothing is subscribed
+    /// to its subject.
+    pub fn is_no_responders(&self) -> bool {
+        self.data.is_empty()
+            && self
+                .headers
+                .as_ref()
+                .and_then(|headers| headers.get_first(Headers::STATUS))
+                == Some("503")
+    }
+
     /// Respond to a request message.
     pub fn respond(&self, msg: impl AsRef<[u8]>) -> io::Result<()> {
         match self.reply.as_ref() {
@@ -107,6 +140,8 @@ impl Message {
             }
             Some(original_reply) => original_reply,
         };
+        // a closed client never reconnects, so retrying would never end
+        self.client.check_shutdown()?;
         let mut retries = 0;
         loop {
             retries += 1;
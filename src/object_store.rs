@@ -0,0 +1,668 @@
+// Copyright 2021 The NATS Authors
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for the object store layered on top of `JetStream`.
+//!
+//! Each bucket is backed by a stream named `OBJ_<bucket>`. Objects are
+//! split into chunks stored on the subjects `$O.<bucket>.C.<nuid>`, and
+//! the metadata describing each object is stored on the subjects
+//! `$O.<bucket>.M.<name>`.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::time;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::jetstream::{
+    AckPolicy, ConsumerAction, ConsumerConfig, ConsumerInfo,
+    CreateConsumerRequest, DateTime, DeliverPolicy, DiscardPolicy, Duration,
+    PubAck, PurgeRequest, StorageType, StreamConfig, StreamName,
+};
+use crate::{Connection, Message, Subscription};
+
+pub use crate::kv::{validate_bucket_name, BucketNameError};
+
+/// The prefix used for all subjects of object store buckets.
+pub const OBJ_SUBJECT_PREFIX: &str = "$O.";
+
+/// The prefix of the names of the streams backing object store buckets.
+pub const OBJ_STREAM_PREFIX: &str = "OBJ_";
+
+/// The configuration of an object store bucket, which is converted into
+/// the configuration of its backing stream with `StreamConfig::try_from`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ObjectStoreConfig {
+    /// The name of the bucket, see `validate_bucket_name`
+    pub bucket: String,
+    /// How long to keep each object, or `None` to keep them forever
+    pub max_age: Option<Duration>,
+    /// The largest total size of the bucket in bytes, -1 for unlimited
+    pub max_bytes: i64,
+    /// The type of storage backend, `File` (default) and `Memory`
+    pub storage: StorageType,
+    /// How many replicas to keep of each object in a clustered `JetStream`
+    pub num_replicas: usize,
+}
+
+impl TryFrom<&ObjectStoreConfig> for StreamConfig {
+    type Error = io::Error;
+
+    fn try_from(config: &ObjectStoreConfig) -> io::Result<StreamConfig> {
+        validate_bucket_name(&config.bucket)?;
+
+        Ok(StreamConfig {
+            name: format!("{}{}", OBJ_STREAM_PREFIX, config.bucket),
+            subjects: Some(vec![
+                format!("{}{}.C.>", OBJ_SUBJECT_PREFIX, config.bucket),
+                format!("{}{}.M.>", OBJ_SUBJECT_PREFIX, config.bucket),
+            ]),
+            max_bytes: config.max_bytes,
+            max_age: config.max_age.unwrap_or_default(),
+            storage: config.storage,
+            num_replicas: config.num_replicas.max(1),
+            discard: DiscardPolicy::New,
+            ..StreamConfig::limits()
+        })
+    }
+}
+
+/// The size of the chunks that objects are split into by default.
+pub const DEFAULT_CHUNK_SIZE: usize = 128 * 1024;
+
+/// How long to wait for each message of an object before giving up.
+const READ_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// The prefix of digests computed with `ObjectDigest`.
+pub const DIGEST_PREFIX: &str = "SHA-256=";
+
+/// Returns the subject that the chunks of an object are stored on, which
+/// is named after the NUID generated when the object was stored.
+pub fn chunk_subject(bucket: &str, nuid: &str) -> String {
+    format!("{}{}.C.{}", OBJ_SUBJECT_PREFIX, bucket, nuid)
+}
+
+/// Returns the subject that the metadata of an object is stored on. The
+/// name is encoded as URL-safe base64 so that it forms a single subject
+/// token whatever characters it contains.
+pub fn meta_subject(bucket: &str, name: &str) -> String {
+    format!(
+        "{}{}.M.{}",
+        OBJ_SUBJECT_PREFIX,
+        bucket,
+        base64::encode_config(name, base64::URL_SAFE)
+    )
+}
+
+/// The metadata that describes an object, as given when it is stored.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectMeta {
+    /// The name of the object within its bucket
+    pub name: String,
+    /// A description of the object
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arbitrary metadata attached to the object
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Options for how the object is stored, or for links what they point to
+    #[serde(
+        default,
+        rename = "options",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub opts: Option<ObjectMetaOptions>,
+}
+
+impl ObjectMeta {
+    /// Returns the link of the object if it is a link to another object
+    /// or bucket rather than an object with its own data.
+    pub fn link(&self) -> Option<&ObjectLink> {
+        self.opts.as_ref().and_then(|opts| opts.link.as_ref())
+    }
+}
+
+/// Options of an object, see `ObjectMeta::opts`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectMetaOptions {
+    /// The object or bucket that this object links to. Links have no data
+    /// of their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<ObjectLink>,
+    /// The size of the chunks that the object is split into, instead of
+    /// `DEFAULT_CHUNK_SIZE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_chunk_size: Option<i32>,
+}
+
+/// A link to an object, or to a whole bucket if `name` is `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectLink {
+    /// The bucket that is linked to
+    pub bucket: String,
+    /// The object that is linked to, or `None` to link to the bucket
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Describes one chunk of an object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkMeta {
+    /// The NUID that all chunks of the object are stored under
+    pub nuid: String,
+    /// The position of this chunk within the object, starting at 0
+    pub index: usize,
+    /// The size of this chunk in bytes
+    pub size: usize,
+}
+
+impl ChunkMeta {
+    /// Splits an object of `object_size` bytes into chunks of at most
+    /// `chunk_size` bytes each, e.g. to plan or resume an upload.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn split(
+        nuid: &str,
+        object_size: usize,
+        chunk_size: usize,
+    ) -> Vec<ChunkMeta> {
+        assert!(chunk_size > 0, "the chunk size must not be 0");
+
+        (0..object_size)
+            .step_by(chunk_size)
+            .enumerate()
+            .map(|(index, offset)| ChunkMeta {
+                nuid: nuid.to_string(),
+                index,
+                size: chunk_size.min(object_size - offset),
+            })
+            .collect()
+    }
+
+    /// Returns the subject that this chunk is stored on.
+    pub fn subject(&self, bucket: &str) -> String {
+        chunk_subject(bucket, &self.nuid)
+    }
+}
+
+/// Computes the digest of an object as it is stored in the object's
+/// metadata: its SHA-256 hash, encoded as URL-safe base64 with padding
+/// and prefixed with `SHA-256=`.
+///
+/// # Example
+///
+/// ```
+/// use nats::object_store::ObjectDigest;
+///
+/// let mut digest = ObjectDigest::new();
+/// digest.update(b"hello ");
+/// digest.update(b"world");
+/// assert_eq!(
+///     digest.finish(),
+///     "SHA-256=uU0nuZNNPgilLlLX2n2r-sSE7-N6U4DukIj3rOLvzek="
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ObjectDigest(Sha256);
+
+impl ObjectDigest {
+    /// Creates the digest of an empty object.
+    pub fn new() -> ObjectDigest {
+        ObjectDigest::default()
+    }
+
+    /// Returns the digest of `data`.
+    pub fn of(data: &[u8]) -> String {
+        let mut digest = ObjectDigest::new();
+        digest.update(data);
+        digest.finish()
+    }
+
+    /// Adds the next chunk of the object to the digest.
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    /// Returns the digest of all chunks added so far.
+    pub fn finish(self) -> String {
+        let hash = self.0.finalize();
+        format!(
+            "{}{}",
+            DIGEST_PREFIX,
+            base64::encode_config(hash, base64::URL_SAFE)
+        )
+    }
+}
+
+/// The information about a stored object, which the object store keeps
+/// as the latest message on the object's `meta_subject`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectInfo {
+    /// The metadata given when the object was stored
+    #[serde(flatten)]
+    pub meta: ObjectMeta,
+    /// The bucket the object is stored in
+    pub bucket: String,
+    /// The NUID that the chunks of the object are stored under, see
+    /// `chunk_subject`
+    pub nuid: String,
+    /// The size of the object in bytes
+    #[serde(default)]
+    pub size: u64,
+    /// When the object was last modified
+    pub mtime: DateTime,
+    /// The number of chunks the object is split into
+    #[serde(default)]
+    pub chunks: usize,
+    /// The digest of the object, see `ObjectDigest`
+    #[serde(default)]
+    pub digest: String,
+    /// Whether the object was deleted, leaving only its information behind
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+}
+
+impl ObjectInfo {
+    /// Describes `data` as an object stored in `bucket` with the given
+    /// metadata under `nuid`, split into chunks of
+    /// `ObjectInfo::chunk_size` bytes, and last modified now. Data smaller
+    /// than one chunk is stored as a single chunk, and empty data as none.
+    pub fn new(
+        bucket: &str,
+        meta: ObjectMeta,
+        nuid: &str,
+        data: &[u8],
+    ) -> ObjectInfo {
+        let mut info = ObjectInfo {
+            meta,
+            bucket: bucket.to_string(),
+            nuid: nuid.to_string(),
+            size: data.len() as u64,
+            mtime: std::time::SystemTime::now().into(),
+            digest: ObjectDigest::of(data),
+            ..Default::default()
+        };
+        info.chunks = data.chunks(info.chunk_size()).count();
+        info
+    }
+
+    /// Returns the size of the chunks that the object is split into, the
+    /// `max_chunk_size` of its metadata if it is positive, and otherwise
+    /// `DEFAULT_CHUNK_SIZE`.
+    pub fn chunk_size(&self) -> usize {
+        self.meta
+            .opts
+            .as_ref()
+            .and_then(|opts| opts.max_chunk_size)
+            .and_then(|size| usize::try_from(size).ok())
+            .filter(|size| *size > 0)
+            .unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Decodes the information of an object from the latest message on
+    /// its `meta_subject`.
+    pub fn from_message(msg: &Message) -> io::Result<ObjectInfo> {
+        Ok(serde_json::from_slice(&msg.data)?)
+    }
+
+    /// Concatenates the chunks of the object, in the order they were
+    /// stored, checking them against the `chunks`, `size` and `digest`
+    /// of the information. No more than `chunks` messages are read, so an
+    /// object of size 0 reads none.
+    ///
+    /// Returns an `ErrorKind::UnexpectedEof` error if there are fewer
+    /// chunks than expected, and an `ErrorKind::InvalidData` error if the
+    /// object does not have the expected size or digest, both wrapping an
+    /// `ObjectError`.
+    pub fn assemble<I>(&self, chunks: I) -> io::Result<Vec<u8>>
+    where
+        I: IntoIterator<Item = Message>,
+    {
+        let mut data =
+            Vec::with_capacity(usize::try_from(self.size).unwrap_or_default());
+        let mut digest = ObjectDigest::new();
+        let mut chunks = chunks.into_iter();
+        for index in 0..self.chunks {
+            let chunk =
+                chunks.next().ok_or_else(|| ObjectError::MissingChunk {
+                    name: self.meta.name.clone(),
+                    index,
+                    chunks: self.chunks,
+                })?;
+            digest.update(&chunk.data);
+            data.extend_from_slice(&chunk.data);
+        }
+
+        if data.len() as u64 != self.size {
+            return Err(ObjectError::SizeMismatch {
+                name: self.meta.name.clone(),
+                size: data.len() as u64,
+                expected: self.size,
+            }
+            .into());
+        }
+        let digest = digest.finish();
+        if digest != self.digest {
+            return Err(ObjectError::DigestMismatch {
+                name: self.meta.name.clone(),
+                digest,
+                expected: self.digest.clone(),
+            }
+            .into());
+        }
+        Ok(data)
+    }
+}
+
+/// The error wrapped in the `io::Error` returned when an object can not be
+/// stored or read, see `Connection::object_get`. Like the other errors of
+/// this crate it comes as an `io::Error`, of the kind given by
+/// `ObjectError::kind`, and can be recovered with `io::Error::get_ref`
+/// and `downcast_ref`. Failures to talk to the server are returned as
+/// plain `io::Error`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectError {
+    /// The bucket does not exist
+    BucketNotFound {
+        /// The name of the bucket
+        bucket: String,
+    },
+    /// The bucket holds no object of that name, or it was deleted
+    NotFound {
+        /// The name of the bucket
+        bucket: String,
+        /// The name of the object
+        name: String,
+    },
+    /// The object is a link, which has no data of its own
+    IsLink {
+        /// The name of the object
+        name: String,
+    },
+    /// Fewer chunks were read than the object consists of
+    MissingChunk {
+        /// The name of the object
+        name: String,
+        /// The index of the first missing chunk, starting at 0
+        index: usize,
+        /// The number of chunks the object consists of
+        chunks: usize,
+    },
+    /// The chunks do not add up to the size of the object
+    SizeMismatch {
+        /// The name of the object
+        name: String,
+        /// The size of the chunks read
+        size: u64,
+        /// The size of the object
+        expected: u64,
+    },
+    /// The chunks do not have the digest of the object
+    DigestMismatch {
+        /// The name of the object
+        name: String,
+        /// The digest of the chunks read
+        digest: String,
+        /// The digest of the object
+        expected: String,
+    },
+}
+
+impl ObjectError {
+    /// Returns the kind of the `io::Error` that the error is wrapped in:
+    /// `ErrorKind::NotFound` for a missing bucket or object,
+    /// `ErrorKind::InvalidInput` for a link, `ErrorKind::UnexpectedEof`
+    /// for a missing chunk, and `ErrorKind::InvalidData` for chunks of
+    /// the wrong size or digest.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ObjectError::BucketNotFound { .. }
+            | ObjectError::NotFound { .. } => ErrorKind::NotFound,
+            ObjectError::IsLink { .. } => ErrorKind::InvalidInput,
+            ObjectError::MissingChunk { .. } => ErrorKind::UnexpectedEof,
+            ObjectError::SizeMismatch { .. }
+            | ObjectError::DigestMismatch { .. } => ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectError::BucketNotFound { bucket } => {
+                write!(f, "the object store bucket {} does not exist", bucket)
+            }
+            ObjectError::NotFound { bucket, name } => {
+                write!(f, "the bucket {} has no object {}", bucket, name)
+            }
+            ObjectError::IsLink { name } => {
+                write!(f, "the object {} is a link", name)
+            }
+            ObjectError::MissingChunk {
+                name,
+                index,
+                chunks,
+            } => write!(
+                f,
+                "chunk {} of {} of the object {} is missing",
+                index + 1,
+                chunks,
+                name
+            ),
+            ObjectError::SizeMismatch {
+                name,
+                size,
+                expected,
+            } => write!(
+                f,
+                "the object {} has {} bytes instead of {}",
+                name, size, expected
+            ),
+            ObjectError::DigestMismatch {
+                name,
+                digest,
+                expected,
+            } => write!(
+                f,
+                "the object {} has the digest {} instead of {}",
+                name, digest, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ObjectError {}
+
+impl From<ObjectError> for io::Error {
+    fn from(error: ObjectError) -> io::Error {
+        io::Error::new(error.kind(), error)
+    }
+}
+
+impl Connection {
+    /// Reads an object from the given object store bucket, returning its
+    /// information and its data once the data was checked against the
+    /// information with `ObjectInfo::assemble`.
+    ///
+    /// Returns an error wrapping an `ObjectError` if the bucket does not
+    /// exist, holds no object of that name or it was deleted, if the
+    /// object is a link, or if its data does not match its information.
+    pub fn object_get(
+        &self,
+        bucket: &str,
+        name: &str,
+    ) -> io::Result<(ObjectInfo, Vec<u8>)> {
+        validate_bucket_name(bucket)?;
+        let info = match self.object_info(bucket, name)? {
+            Some(info) if !info.deleted => info,
+            _ => {
+                return Err(ObjectError::NotFound {
+                    bucket: bucket.to_string(),
+                    name: name.to_string(),
+                }
+                .into())
+            }
+        };
+        if info.meta.link().is_some() {
+            return Err(ObjectError::IsLink {
+                name: name.to_string(),
+            }
+            .into());
+        }
+
+        if info.chunks == 0 {
+            let data = info.assemble(std::iter::empty())?;
+            return Ok((info, data));
+        }
+        let (sub, _) = self.object_subscribe(
+            bucket,
+            chunk_subject(bucket, &info.nuid),
+            DeliverPolicy::All,
+        )?;
+        let data = info.assemble(sub.timeout_iter(READ_TIMEOUT))?;
+        Ok((info, data))
+    }
+
+    /// Stores an object in the given object store bucket, replacing any
+    /// earlier object of the same name, and returns its information.
+    ///
+    /// The data is split into chunks of the `max_chunk_size` of the
+    /// metadata, or `DEFAULT_CHUNK_SIZE`, which are published under a
+    /// fresh NUID before the information of the object, see
+    /// `ObjectInfo::new`. The chunks of a replaced object are purged once
+    /// the new object is stored. If publishing a chunk fails, the chunks
+    /// published so far are purged and the earlier object is kept.
+    ///
+    /// Returns an error wrapping an `ObjectError` if the metadata describes
+    /// a link, which has no data of its own, or if the bucket does not
+    /// exist.
+    pub fn object_put(
+        &self,
+        bucket: &str,
+        meta: ObjectMeta,
+        data: &[u8],
+    ) -> io::Result<ObjectInfo> {
+        validate_bucket_name(bucket)?;
+        if meta.link().is_some() {
+            return Err(ObjectError::IsLink { name: meta.name }.into());
+        }
+
+        let stream = format!("{}{}", OBJ_STREAM_PREFIX, bucket);
+        let previous = self.object_info(bucket, &meta.name)?;
+        let info = ObjectInfo::new(bucket, meta, &nuid::next(), data);
+
+        let chunks = chunk_subject(bucket, &info.nuid);
+        for chunk in data.chunks(info.chunk_size()) {
+            if let Err(err) = self.js_request::<PubAck>(&chunks, chunk) {
+                let purge = PurgeRequest {
+                    filter: Some(chunks),
+                    ..Default::default()
+                };
+                self.purge_stream_with(&stream, &purge).ok();
+                return Err(err);
+            }
+        }
+
+        let meta = meta_subject(bucket, &info.meta.name);
+        self.js_request::<PubAck>(&meta, &serde_json::to_vec(&info)?)?;
+
+        let stale_meta = PurgeRequest {
+            filter: Some(meta),
+            ..PurgeRequest::keep_newest(1)
+        };
+        self.purge_stream_with(&stream, &stale_meta)?;
+        if let Some(previous) = previous.filter(|p| p.nuid != info.nuid) {
+            let stale_chunks = PurgeRequest {
+                filter: Some(chunk_subject(bucket, &previous.nuid)),
+                ..Default::default()
+            };
+            self.purge_stream_with(&stream, &stale_chunks)?;
+        }
+        Ok(info)
+    }
+
+    /// Returns the latest information stored for an object, which may
+    /// describe a deleted object, or `None` if there is none. A purged
+    /// object leaves an empty message behind, which counts as none.
+    fn object_info(
+        &self,
+        bucket: &str,
+        name: &str,
+    ) -> io::Result<Option<ObjectInfo>> {
+        let (sub, pending) = self.object_subscribe(
+            bucket,
+            meta_subject(bucket, name),
+            DeliverPolicy::LastPerSubject,
+        )?;
+        if pending == 0 {
+            return Ok(None);
+        }
+        let msg = sub.next_timeout(READ_TIMEOUT)?;
+        if msg.data.is_empty() {
+            return Ok(None);
+        }
+        ObjectInfo::from_message(&msg).map(Some)
+    }
+
+    /// Subscribes to the messages of a bucket's backing stream that match
+    /// `filter_subject` through an ephemeral push consumer, returning the
+    /// subscription and the number of messages pending for it.
+    ///
+    /// Returns an `ObjectError::BucketNotFound` error if the bucket does not
+    /// exist.
+    fn object_subscribe(
+        &self,
+        bucket: &str,
+        filter_subject: String,
+        deliver_policy: DeliverPolicy,
+    ) -> io::Result<(Subscription, u64)> {
+        let deliver_subject = self.new_inbox();
+        let sub = self.subscribe(&deliver_subject)?;
+
+        let req = CreateConsumerRequest {
+            stream_name: StreamName::try_from(format!(
+                "{}{}",
+                OBJ_STREAM_PREFIX, bucket
+            ))?,
+            config: ConsumerConfig {
+                deliver_subject: Some(deliver_subject),
+                deliver_policy,
+                ack_policy: AckPolicy::None,
+                filter_subject,
+                ..Default::default()
+            },
+            action: ConsumerAction::CreateOrUpdate,
+        };
+        let subject =
+            format!("{}CONSUMER.CREATE.{}", self.api_prefix(), req.stream_name);
+        let info = match self.js_api_request::<ConsumerInfo>(
+            &subject,
+            &serde_json::ser::to_vec(&req)?,
+        )? {
+            Ok(info) => info,
+            Err(error) if error.is_stream_not_found() => {
+                return Err(ObjectError::BucketNotFound {
+                    bucket: bucket.to_string(),
+                }
+                .into())
+            }
+            Err(error) => return Err(error.into()),
+        };
+        Ok((sub, info.num_pending))
+    }
+}
@@ -531,6 +531,30 @@ impl Options {
         self
     }
 
+    /// Set the `JetStream` API prefix from `JetStreamOptions`, e.g.
+    /// to reach the API of a specific `JetStream` domain.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> std::io::Result<()> {
+    /// use nats::jetstream::JetStreamOptions;
+    ///
+    /// let nc = nats::Options::new()
+    ///     .jetstream_options(&JetStreamOptions::new().domain("hub"))
+    ///     .connect("demo.nats.io")?;
+    /// nc.drain().unwrap();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn jetstream_options(
+        mut self,
+        options: &crate::jetstream::JetStreamOptions,
+    ) -> Self {
+        self.jetstream_prefix = options.prefix().to_string();
+        self
+    }
+
     /// Set a callback to be executed when the client has been
     /// closed due to exhausting reconnect retries to known servers
     /// or by completing a drain request.
@@ -21,6 +21,11 @@ struct Inner {
 
     /// Client associated with subscription.
     pub(crate) client: Client,
+
+    /// Whether the subscription receives the replies to a request, which
+    /// end at the status message that reports that nothing is subscribed
+    /// to its subject.
+    pub(crate) request: bool,
 }
 
 impl Drop for Inner {
@@ -47,9 +52,33 @@ impl Subscription {
             subject,
             messages,
             client,
+            request: false,
         }))
     }
 
+    /// Creates a subscription to the replies of a request, see
+    /// `Connection::request_multi`.
+    pub(crate) fn for_request(
+        sid: u64,
+        subject: String,
+        messages: channel::Receiver<Message>,
+        client: Client,
+    ) -> Subscription {
+        Subscription(Arc::new(Inner {
+            sid,
+            subject,
+            messages,
+            client,
+            request: true,
+        }))
+    }
+
+    /// Returns `true` if `msg` reports that there are no responders to
+    /// the request that this subscription receives the replies of.
+    fn is_no_responders(&self, msg: &Message) -> bool {
+        self.0.request && msg.is_no_responders()
+    }
+
     /// Get a crossbeam Receiver for subscription messages.
     /// Useful for `crossbeam_channel::select` macro
     ///
@@ -79,7 +108,9 @@ impl Subscription {
     }
 
     /// Get the next message, or None if the subscription
-    /// has been unsubscribed or the connection closed.
+    /// has been unsubscribed or the connection closed, or for the
+    /// replies to `Connection::request_multi`, if nothing is subscribed
+    /// to the subject of the request.
     ///
     /// # Example
     /// ```
@@ -92,7 +123,11 @@ impl Subscription {
     /// # }
     /// ```
     pub fn next(&self) -> Option<Message> {
-        self.0.messages.recv().ok()
+        self.0
+            .messages
+            .recv()
+            .ok()
+            .filter(|msg| !self.is_no_responders(msg))
     }
 
     /// Try to get the next message, or None if no messages
@@ -111,7 +146,11 @@ impl Subscription {
     /// # }
     /// ```
     pub fn try_next(&self) -> Option<Message> {
-        self.0.messages.try_recv().ok()
+        self.0
+            .messages
+            .try_recv()
+            .ok()
+            .filter(|msg| !self.is_no_responders(msg))
     }
 
     /// Get the next message, or a timeout error
@@ -128,6 +167,10 @@ impl Subscription {
     /// ```
     pub fn next_timeout(&self, timeout: Duration) -> io::Result<Message> {
         match self.0.messages.recv_timeout(timeout) {
+            Ok(msg) if self.is_no_responders(&msg) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "next_timeout: no responders",
+            )),
             Ok(msg) => Ok(msg),
             Err(channel::RecvTimeoutError::Timeout) => Err(io::Error::new(
                 io::ErrorKind::TimedOut,
@@ -70,12 +70,12 @@ fn jetstream_queue_process() -> io::Result<()> {
     let mut consumer1 = nc.create_consumer(
         "qtest1",
         ConsumerConfig {
-            max_deliver: 5,
+            max_deliver: Some(5),
             durable_name: Some("consumer1".to_string()),
             ack_policy: AckPolicy::Explicit,
             replay_policy: ReplayPolicy::Instant,
             deliver_policy: DeliverPolicy::All,
-            ack_wait: 30 * 1_000_000_000,
+            ack_wait: Duration::from_secs(30),
             deliver_subject: None,
             ..Default::default()
         },
@@ -180,6 +180,295 @@ fn jetstream_basics() -> io::Result<()> {
     Ok(())
 }
 
+#[test]
+fn jetstream_publish_with_opts() -> io::Result<()> {
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    nc.create_stream("orders")?;
+    let opts = PubOpts {
+        id: "order-1".to_string(),
+        ..Default::default()
+    };
+    let ack = nc.publish_with_opts("orders", "created", &opts)?;
+    assert_eq!(ack.stream, "orders");
+    assert_eq!(ack.seq, 1);
+    assert!(!ack.duplicate);
+
+    let again = nc.publish_with_opts("orders", "created", &opts)?;
+    assert_eq!(again.seq, 1);
+    assert!(again.duplicate);
+    assert_eq!(nc.stream_info("orders")?.state.messages, 1);
+
+    // the stream has to exist
+    assert!(nc
+        .publish_with_opts("missing", "created", &PubOpts::default())
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_republished_status_is_not_forwarded() -> io::Result<()> {
+    use std::convert::TryFrom;
+
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    let sub = nc.subscribe("copies")?;
+    let headers = nats::Headers::try_from(
+        &b"NATS/1.0 404 No Messages\r\nNats-Stream: orders\r\n\r\n"[..],
+    )?;
+    nc.publish_with_reply_or_headers("copies", None, Some(&headers), "")?;
+
+    let copy = sub.next_timeout(std::time::Duration::from_secs(1))?;
+    let copied = copy.headers.unwrap();
+    assert!(copied.contains_key("Nats-Stream"));
+    assert!(!copied.contains_key(nats::Headers::STATUS));
+    assert!(!copied.contains_key(nats::Headers::DESCRIPTION));
+    assert!(!copied.contains_key("Status"));
+    Ok(())
+}
+
+#[test]
+fn jetstream_core_request_no_responders() -> io::Result<()> {
+    use std::time::Duration;
+
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    let err = nc.request("nobody.listening", "hello").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err = nc
+        .request_timeout("nobody.listening", "hello", Duration::from_secs(5))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let replies = nc.request_multi("nobody.listening", "hello")?;
+    let err = replies.next_timeout(Duration::from_secs(5)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    // actual replies are unaffected
+    let _responder = nc
+        .subscribe("echo")?
+        .with_handler(|msg| msg.respond(&msg.data));
+    assert_eq!(nc.request("echo", "hello")?.data, b"hello");
+    Ok(())
+}
+
+#[test]
+fn jetstream_pull_opt_unsendable_heartbeat() -> io::Result<()> {
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    nc.create_stream("pulls")?;
+    nc.create_consumer("pulls", ConsumerConfig::durable_pull("worker"))?;
+    let mut consumer = Consumer::existing(nc, "pulls", "worker")?;
+
+    // longer than expires, but too long to be sent to the server
+    let req = NextRequest {
+        batch: 1,
+        expires: usize::MAX,
+        idle_heartbeat: Some(Duration::from_nanos(i64::MAX as u64 + 1)),
+        ..Default::default()
+    };
+    let err = consumer.pull_opt(req).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[test]
+fn jetstream_ensure_stream() -> io::Result<()> {
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    let desired = StreamConfig {
+        name: "orders".to_string(),
+        subjects: Some(vec!["orders.*".to_string()]),
+        ..StreamConfig::limits()
+    };
+    let (info, action) = nc.ensure_stream(&desired)?;
+    assert_eq!(action, ReconcileAction::Created);
+    assert_eq!(info.config.name, "orders");
+
+    // the server fills in its defaults, which still match
+    let (_, action) = nc.ensure_stream(&desired)?;
+    assert_eq!(action, ReconcileAction::Unchanged);
+
+    let desired = StreamConfig {
+        max_msgs: 1000,
+        ..desired
+    };
+    let (info, action) = nc.ensure_stream(&desired)?;
+    assert_eq!(action, ReconcileAction::Updated);
+    assert_eq!(info.config.max_msgs, 1000);
+    let (_, action) = nc.ensure_stream(&desired)?;
+    assert_eq!(action, ReconcileAction::Unchanged);
+
+    let err = nc
+        .ensure_stream(&StreamConfig {
+            storage: StorageType::Memory,
+            ..desired
+        })
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let conflict = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<ImmutableConflict>())
+        .unwrap();
+    assert_eq!(conflict.fields, vec!["storage"]);
+
+    // the conflicting stream was left unchanged
+    let info = nc.stream_info("orders")?;
+    assert_eq!(info.config.storage, StorageType::File);
+    assert_eq!(info.config.max_msgs, 1000);
+    Ok(())
+}
+
+#[test]
+fn jetstream_ensure_consumer() -> io::Result<()> {
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    nc.create_stream("orders")?;
+    let desired = ConsumerConfig::durable_pull("worker");
+    let (info, action) = nc.ensure_consumer("orders", &desired)?;
+    assert_eq!(action, ReconcileAction::Created);
+    assert_eq!(info.name, "worker");
+
+    // the server fills in its defaults, such as the ack_wait, which still
+    // match
+    let (_, action) = nc.ensure_consumer("orders", &desired)?;
+    assert_eq!(action, ReconcileAction::Unchanged);
+
+    let desired = ConsumerConfig {
+        max_ack_pending: 10,
+        ..desired
+    };
+    let (info, action) = nc.ensure_consumer("orders", &desired)?;
+    assert_eq!(action, ReconcileAction::Updated);
+    assert_eq!(info.config.max_ack_pending, 10);
+    let (_, action) = nc.ensure_consumer("orders", &desired)?;
+    assert_eq!(action, ReconcileAction::Unchanged);
+
+    let err = nc
+        .ensure_consumer(
+            "orders",
+            &ConsumerConfig {
+                ack_policy: AckPolicy::All,
+                ..desired
+            },
+        )
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let conflict = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<ImmutableConflict>())
+        .unwrap();
+    assert_eq!(conflict.fields, vec!["ack_policy"]);
+
+    // the conflicting consumer was left unchanged
+    let info = nc.consumer_info("orders", "worker")?;
+    assert_eq!(info.config.ack_policy, AckPolicy::Explicit);
+    assert_eq!(info.config.max_ack_pending, 10);
+    Ok(())
+}
+
+#[test]
+fn jetstream_object_store_round_trip() -> io::Result<()> {
+    use nats::object_store::*;
+    use std::convert::TryFrom;
+
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    nc.create_stream(StreamConfig::try_from(&ObjectStoreConfig {
+        bucket: "assets".to_string(),
+        ..Default::default()
+    })?)?;
+
+    // spans two full chunks and a partial one
+    let data = (0..300 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let meta = ObjectMeta {
+        name: "logo.png".to_string(),
+        ..Default::default()
+    };
+    let put = nc.object_put("assets", meta.clone(), &data)?;
+    assert_eq!(put.chunks, 3);
+    assert_eq!(put.size, data.len() as u64);
+    assert_eq!(put.digest, ObjectDigest::of(&data));
+
+    let (info, read) = nc.object_get("assets", "logo.png")?;
+    assert_eq!(info, put);
+    assert_eq!(read, data);
+
+    // replacing the object with a different chunk size
+    let meta = ObjectMeta {
+        opts: Some(ObjectMetaOptions {
+            max_chunk_size: Some(1000),
+            ..Default::default()
+        }),
+        ..meta
+    };
+    let data = &data[..4500];
+    let put = nc.object_put("assets", meta, data)?;
+    assert_eq!(put.chunks, 5);
+    let (info, read) = nc.object_get("assets", "logo.png")?;
+    assert_eq!(info.digest, ObjectDigest::of(data));
+    assert_eq!(read, data);
+    Ok(())
+}
+
+#[test]
+fn jetstream_object_store_not_found() -> io::Result<()> {
+    use nats::object_store::*;
+    use std::convert::TryFrom;
+
+    let server = server();
+
+    let nc = nats::connect(&format!("localhost:{}", server.port)).unwrap();
+
+    let err = nc.object_get("assets", "logo.png").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert!(err.to_string().contains("bucket assets"));
+    let meta = ObjectMeta {
+        name: "logo.png".to_string(),
+        ..Default::default()
+    };
+    let err = nc.object_put("assets", meta.clone(), b"data").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    nc.create_stream(StreamConfig::try_from(&ObjectStoreConfig {
+        bucket: "assets".to_string(),
+        ..Default::default()
+    })?)?;
+    let err = nc.object_get("assets", "logo.png").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    assert_eq!(
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<ObjectError>()),
+        Some(&ObjectError::NotFound {
+            bucket: "assets".to_string(),
+            name: "logo.png".to_string(),
+        })
+    );
+
+    nc.object_put("assets", meta, b"data")?;
+    assert_eq!(nc.object_get("assets", "logo.png")?.1, b"data");
+
+    // an empty message left on the meta subject is not an object either
+    nc.request(&meta_subject("assets", "logo.png"), "")?;
+    let err = nc.object_get("assets", "logo.png").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    Ok(())
+}
+
 #[test]
 fn jetstream_libdoc_test() {
     use nats::jetstream::Consumer;
@@ -0,0 +1,203 @@
+use nats::jetstream::*;
+use proptest::prelude::*;
+
+fn deliver_policy() -> impl Strategy<Value = DeliverPolicy> {
+    prop_oneof![
+        Just(DeliverPolicy::All),
+        Just(DeliverPolicy::Last),
+        Just(DeliverPolicy::New),
+        Just(DeliverPolicy::ByStartSeq),
+        Just(DeliverPolicy::ByStartTime),
+        Just(DeliverPolicy::Unknown),
+    ]
+}
+
+fn ack_policy() -> impl Strategy<Value = AckPolicy> {
+    prop_oneof![
+        Just(AckPolicy::Explicit),
+        Just(AckPolicy::None),
+        Just(AckPolicy::All),
+        Just(AckPolicy::Unknown),
+    ]
+}
+
+fn replay_policy() -> impl Strategy<Value = ReplayPolicy> {
+    prop_oneof![
+        Just(ReplayPolicy::Instant),
+        Just(ReplayPolicy::Original),
+        Just(ReplayPolicy::Unknown),
+    ]
+}
+
+fn retention_policy() -> impl Strategy<Value = RetentionPolicy> {
+    prop_oneof![
+        Just(RetentionPolicy::Limits),
+        Just(RetentionPolicy::Interest),
+        Just(RetentionPolicy::WorkQueue),
+        Just(RetentionPolicy::Unknown),
+    ]
+}
+
+fn discard_policy() -> impl Strategy<Value = DiscardPolicy> {
+    prop_oneof![
+        Just(DiscardPolicy::Old),
+        Just(DiscardPolicy::New),
+        Just(DiscardPolicy::Unknown),
+    ]
+}
+
+fn storage_type() -> impl Strategy<Value = StorageType> {
+    prop_oneof![
+        Just(StorageType::File),
+        Just(StorageType::Memory),
+        Just(StorageType::Unknown),
+    ]
+}
+
+fn consumer_config() -> impl Strategy<Value = ConsumerConfig> {
+    (
+        (
+            proptest::option::of("[a-z_.]{1,16}"),
+            proptest::option::of("[a-z_]{1,16}"),
+            deliver_policy(),
+            any::<i64>(),
+            proptest::option::of(any::<DateTime>()),
+            ack_policy(),
+            any::<Duration>(),
+            // -1 is unlimited like `None`, and deserialized as `None`
+            proptest::option::of(
+                any::<i64>().prop_filter("unlimited", |n| *n != -1),
+            ),
+        ),
+        (
+            "[a-z.*>]{0,16}",
+            replay_policy(),
+            any::<i64>(),
+            any::<u8>(),
+            any::<i64>(),
+            any::<i64>(),
+        ),
+    )
+        .prop_map(
+            |(
+                (
+                    deliver_subject,
+                    durable_name,
+                    deliver_policy,
+                    opt_start_seq,
+                    opt_start_time,
+                    ack_policy,
+                    ack_wait,
+                    max_deliver,
+                ),
+                (
+                    filter_subject,
+                    replay_policy,
+                    rate_limit,
+                    sample_frequency,
+                    max_waiting,
+                    max_ack_pending,
+                ),
+            )| ConsumerConfig {
+                deliver_subject,
+                durable_name,
+                deliver_policy,
+                opt_start_seq,
+                opt_start_time,
+                ack_policy,
+                ack_wait,
+                max_deliver,
+                filter_subject,
+                replay_policy,
+                rate_limit,
+                sample_frequency,
+                max_waiting,
+                max_ack_pending,
+                ..Default::default()
+            },
+        )
+}
+
+fn stream_config() -> impl Strategy<Value = StreamConfig> {
+    (
+        (
+            "[a-zA-Z0-9_-]{1,16}",
+            any::<i64>(),
+            any::<i64>(),
+            any::<i64>(),
+            discard_policy(),
+            // an empty list is serialized like `None`
+            proptest::option::of(proptest::collection::vec(
+                "[a-z.*>]{1,16}",
+                1..4,
+            )),
+            retention_policy(),
+            any::<i32>(),
+        ),
+        (
+            any::<Duration>(),
+            any::<i32>(),
+            storage_type(),
+            any::<usize>(),
+            any::<bool>(),
+            any::<Duration>(),
+            "[a-z]{0,16}",
+        ),
+    )
+        .prop_map(
+            |(
+                (
+                    name,
+                    max_bytes,
+                    max_msgs,
+                    max_msgs_per_subject,
+                    discard,
+                    subjects,
+                    retention,
+                    max_consumers,
+                ),
+                (
+                    max_age,
+                    max_msg_size,
+                    storage,
+                    num_replicas,
+                    no_ack,
+                    duplicate_window,
+                    template_owner,
+                ),
+            )| StreamConfig {
+                name,
+                max_bytes,
+                max_msgs,
+                max_msgs_per_subject,
+                discard,
+                subjects,
+                retention,
+                max_consumers,
+                max_age,
+                max_msg_size,
+                storage,
+                num_replicas,
+                no_ack,
+                duplicate_window,
+                template_owner,
+                ..Default::default()
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn jetstream_consumer_config_roundtrip(config in consumer_config()) {
+        let json = serde_json::to_string(&config).unwrap();
+        let decoded: ConsumerConfig = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(decoded, config);
+    }
+
+    #[test]
+    fn jetstream_stream_config_roundtrip(config in stream_config()) {
+        let json = serde_json::to_string(&config).unwrap();
+        let decoded: StreamConfig = serde_json::from_str(&json).unwrap();
+        prop_assert_eq!(decoded, config);
+    }
+}
@@ -0,0 +1,3274 @@
+use nats::jetstream::*;
+
+#[test]
+fn jetstream_durable_pull_preset() {
+    let cfg = ConsumerConfig::durable_pull("worker");
+
+    assert_eq!(cfg.durable_name.as_deref(), Some("worker"));
+    assert_eq!(cfg.deliver_subject, None);
+    assert_eq!(cfg.deliver_policy, DeliverPolicy::All);
+    assert_eq!(cfg.ack_policy, AckPolicy::Explicit);
+    assert_eq!(cfg.max_ack_pending, ConsumerConfig::DEFAULT_MAX_ACK_PENDING);
+    assert_eq!(cfg.max_waiting, ConsumerConfig::DEFAULT_MAX_WAITING);
+    assert!(cfg.validate().is_ok());
+}
+
+#[test]
+fn jetstream_max_deliver_unlimited() -> std::io::Result<()> {
+    let unset = ConsumerConfig::durable_pull("worker");
+    let minus_one = ConsumerConfig {
+        max_deliver: Some(-1),
+        ..unset.clone()
+    };
+    assert!(unset.max_deliver_is_unlimited());
+    assert!(minus_one.max_deliver_is_unlimited());
+    assert_eq!(
+        serde_json::to_value(&unset)?,
+        serde_json::to_value(&minus_one)?
+    );
+    assert!(serde_json::to_value(&minus_one)?
+        .get("max_deliver")
+        .is_none());
+    assert!(unset.semantically_eq(&minus_one));
+
+    let info: ConsumerInfo = serde_json::from_str(CONSUMER_INFO)?;
+    assert_eq!(info.config.max_deliver, None);
+
+    let limited = ConsumerConfig {
+        max_deliver: Some(5),
+        ..unset
+    };
+    assert!(!limited.max_deliver_is_unlimited());
+    assert_eq!(serde_json::to_value(&limited)?["max_deliver"], 5);
+    assert!(limited.validate().is_ok());
+    assert!(minus_one.validate().is_ok());
+
+    for max_deliver in &[0, -2] {
+        let invalid = ConsumerConfig {
+            max_deliver: Some(*max_deliver),
+            ..limited.clone()
+        };
+        let err = invalid.validate().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_info_default_policies() -> std::io::Result<()> {
+    let mut json: serde_json::Value = serde_json::from_str(CONSUMER_INFO)?;
+    let config = json["config"].as_object_mut().unwrap();
+    for field in &["ack_policy", "deliver_policy", "replay_policy"] {
+        assert!(config.remove(*field).is_some(), "{}", field);
+    }
+
+    let info: ConsumerInfo = serde_json::from_value(json)?;
+    assert_eq!(info.config.ack_policy, AckPolicy::default());
+    assert_eq!(info.config.deliver_policy, DeliverPolicy::default());
+    assert_eq!(info.config.replay_policy, ReplayPolicy::default());
+    assert_eq!(info.config.durable_name.as_deref(), Some("worker"));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_validate_max_waiting() {
+    let push = ConsumerConfig {
+        deliver_subject: Some("deliver".to_string()),
+        ..ConsumerConfig::durable_pull("worker")
+    };
+    let err = push.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("max_waiting"));
+
+    let push = ConsumerConfig {
+        max_waiting: 0,
+        ..push
+    };
+    assert!(push.validate().is_ok());
+}
+
+#[test]
+fn jetstream_validate_flow_control() {
+    let ordered = ConsumerConfig {
+        deliver_subject: Some("deliver".to_string()),
+        ack_policy: AckPolicy::None,
+        flow_control: true,
+        idle_heartbeat: Duration::from_secs(5),
+        ..Default::default()
+    };
+    assert!(ordered.validate().is_ok());
+
+    let json = serde_json::to_value(&ordered).unwrap();
+    assert_eq!(json["flow_control"], true);
+    assert_eq!(json["idle_heartbeat"], 5_000_000_000u64);
+
+    let no_heartbeat = ConsumerConfig {
+        idle_heartbeat: Duration::default(),
+        ..ordered.clone()
+    };
+    let err = no_heartbeat.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("idle_heartbeat"));
+
+    let pull = ConsumerConfig {
+        deliver_subject: None,
+        ..ordered
+    };
+    let err = pull.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("flow_control"));
+}
+
+#[test]
+fn jetstream_cli_json() -> std::io::Result<()> {
+    let stream = StreamConfig {
+        name: "ORDERS".to_string(),
+        subjects: Some(vec!["ORDERS.*".to_string()]),
+        max_consumers: -1,
+        max_msgs: -1,
+        max_msgs_per_subject: -1,
+        max_bytes: 10 * 1024 * 1024 * 1024,
+        max_age: Duration::from_secs(7 * 24 * 60 * 60),
+        num_replicas: 3,
+        duplicate_window: Duration::from_secs(2 * 60),
+        ..Default::default()
+    };
+    let expected: serde_json::Value =
+        serde_json::from_str(include_str!("configs/cli/stream.json"))?;
+    let actual: serde_json::Value =
+        serde_json::from_str(&stream.to_cli_json()?)?;
+    assert_eq!(actual, expected);
+
+    let consumer = ConsumerConfig {
+        ack_wait: Duration::from_secs(90),
+        max_deliver: Some(5),
+        filter_subject: "ORDERS.received".to_string(),
+        ..ConsumerConfig::durable_pull("processor")
+    };
+    let expected: serde_json::Value =
+        serde_json::from_str(include_str!("configs/cli/consumer.json"))?;
+    let actual: serde_json::Value =
+        serde_json::from_str(&consumer.to_cli_json()?)?;
+    assert_eq!(actual, expected);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_config_equality() {
+    let a = StreamConfig {
+        name: "stream".to_string(),
+        subjects: Some(vec!["stream.>".to_string()]),
+        storage: StorageType::Memory,
+        ..Default::default()
+    };
+    let b = a.clone();
+    assert_eq!(a, b);
+
+    let c = StreamConfig {
+        storage: StorageType::File,
+        ..a.clone()
+    };
+    assert_ne!(a, c);
+
+    let info = StreamInfo {
+        config: a.clone(),
+        ..Default::default()
+    };
+    assert_eq!(info.clone(), info);
+    assert_ne!(info, StreamInfo::default());
+
+    let d = ConsumerConfig::durable_pull("worker");
+    let e = ConsumerConfig {
+        opt_start_time: Some(DateTime::default()),
+        ..d.clone()
+    };
+    assert_eq!(d, d.clone());
+    assert_ne!(d, e);
+}
+
+#[test]
+fn jetstream_config_hash() {
+    use std::collections::HashSet;
+
+    let mut streams = HashSet::new();
+    streams.insert(StreamConfig::from("stream"));
+    streams.insert(StreamConfig::from("stream"));
+    streams.insert(StreamConfig::from("other"));
+    assert_eq!(streams.len(), 2);
+
+    let consumer = ConsumerConfig {
+        deliver_policy: DeliverPolicy::ByStartTime,
+        opt_start_time: Some(DateTime::default()),
+        ..ConsumerConfig::durable_pull("worker")
+    };
+    let mut consumers = HashSet::new();
+    consumers.insert(consumer.clone());
+    consumers.insert(consumer);
+    assert_eq!(consumers.len(), 1);
+}
+
+#[test]
+fn jetstream_no_ack() -> std::io::Result<()> {
+    let mut stream = StreamConfig::from("stream");
+    let json = serde_json::to_value(&stream)?;
+    assert!(json.get("no_ack").is_none());
+
+    stream.no_ack = true;
+    let json = serde_json::to_value(&stream)?;
+    assert_eq!(json["no_ack"], true);
+
+    let explicit = ConsumerConfig::durable_pull("explicit");
+    let none = ConsumerConfig {
+        ack_policy: AckPolicy::None,
+        ..ConsumerConfig::durable_pull("none")
+    };
+
+    let lints = stream.validate_against_consumers(&[explicit.clone(), none]);
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].field, "no_ack");
+    assert!(lints[0].message.contains("explicit"));
+
+    stream.no_ack = false;
+    assert!(stream.validate_against_consumers(&[explicit]).is_empty());
+
+    Ok(())
+}
+
+const CONSUMER_INFO: &str = r#"{
+    "stream_name": "stream",
+    "name": "worker",
+    "created": "2021-07-01T12:00:00.000000001Z",
+    "config": {
+        "durable_name": "worker",
+        "deliver_policy": "all",
+        "ack_policy": "explicit",
+        "ack_wait": 30000000000,
+        "max_deliver": -1,
+        "replay_policy": "instant",
+        "max_waiting": 512,
+        "max_ack_pending": 1000
+    },
+    "delivered": { "consumer_seq": 10, "stream_seq": 12 },
+    "ack_floor": { "consumer_seq": 8, "stream_seq": 10 },
+    "num_ack_pending": 2,
+    "num_redelivered": 0,
+    "num_waiting": 1,
+    "num_pending": 90,
+    "cluster": { "leader": "server-1" },
+    "ts": "2021-07-01T12:30:00Z"
+}"#;
+
+#[test]
+fn jetstream_consumer_info_ts() -> std::io::Result<()> {
+    let info: ConsumerInfo = serde_json::from_str(CONSUMER_INFO)?;
+    let ts = info.ts.unwrap();
+    assert_eq!(ts.0.to_rfc3339(), "2021-07-01T12:30:00+00:00");
+    assert!(info.age().unwrap() > std::time::Duration::from_secs(3600));
+
+    let mut value: serde_json::Value = serde_json::from_str(CONSUMER_INFO)?;
+    value.as_object_mut().unwrap().remove("ts");
+    let info: ConsumerInfo = serde_json::from_value(value)?;
+    assert_eq!(info.ts, None);
+    assert_eq!(info.age(), None);
+
+    Ok(())
+}
+
+const STREAM_INFO: &str = r#"{
+    "config": {
+        "name": "stream",
+        "subjects": ["stream.>"],
+        "retention": "limits",
+        "max_consumers": -1,
+        "max_msgs": -1,
+        "max_bytes": -1,
+        "max_age": 0,
+        "max_msgs_per_subject": -1,
+        "max_msg_size": -1,
+        "discard": "old",
+        "storage": "file",
+        "num_replicas": 1,
+        "duplicate_window": 120000000000
+    },
+    "created": "2021-07-01T12:00:00Z",
+    "state": {
+        "messages": 3,
+        "bytes": 120,
+        "first_seq": 1,
+        "first_ts": "2021-07-01T12:01:00Z",
+        "last_seq": 3,
+        "last_ts": "2021-07-01T12:03:00Z",
+        "consumer_count": 1
+    },
+    "ts": "2021-07-01T12:30:00Z"
+}"#;
+
+#[test]
+fn jetstream_stream_info_ts() -> std::io::Result<()> {
+    let info: StreamInfo = serde_json::from_str(STREAM_INFO)?;
+    assert_eq!(info.ts.unwrap().0.to_rfc3339(), "2021-07-01T12:30:00+00:00");
+    assert!(info.age().unwrap() > std::time::Duration::from_secs(3600));
+
+    let mut value: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+    value.as_object_mut().unwrap().remove("ts");
+    let info: StreamInfo = serde_json::from_value(value)?;
+    assert_eq!(info.ts, None);
+    assert_eq!(info.age(), None);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_sequence_pair_last_active() -> std::io::Result<()> {
+    let pair: SequencePair = serde_json::from_str(
+        r#"{"consumer_seq":10,"stream_seq":12,"last_active":"2021-07-01T12:00:00Z"}"#,
+    )?;
+    assert_eq!(pair.consumer_seq, 10);
+    assert_eq!(pair.stream_seq, 12);
+    assert_eq!(
+        pair.last_active.unwrap().0.to_rfc3339(),
+        "2021-07-01T12:00:00+00:00"
+    );
+
+    let pair: SequencePair =
+        serde_json::from_str(r#"{"consumer_seq":10,"stream_seq":12}"#)?;
+    assert_eq!(pair.last_active, None);
+    assert!(serde_json::to_value(pair)?.get("last_active").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_unknown_policies() -> std::io::Result<()> {
+    let mut value: serde_json::Value = serde_json::from_str(CONSUMER_INFO)?;
+    value["config"]["deliver_policy"] = "by_future_policy".into();
+    value["config"]["replay_policy"] = "rewind".into();
+
+    let info: ConsumerInfo = serde_json::from_value(value)?;
+    assert_eq!(info.config.deliver_policy, DeliverPolicy::Unknown);
+    assert_eq!(info.config.replay_policy, ReplayPolicy::Unknown);
+    assert_eq!(info.config.ack_policy, AckPolicy::Explicit);
+    assert_eq!(info.num_pending, 90);
+
+    let mut value: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+    value["config"]["storage"] = "tape".into();
+    let info: StreamInfo = serde_json::from_value(value)?;
+    assert_eq!(info.config.storage, StorageType::Unknown);
+    assert_eq!(info.config.retention, RetentionPolicy::Limits);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_create_consumer_request_from_info() -> std::io::Result<()> {
+    let info: ConsumerInfo = serde_json::from_str(CONSUMER_INFO)?;
+
+    let req = CreateConsumerRequest::from(&info);
+    assert_eq!(req.stream_name, "stream");
+    assert_eq!(req.config, info.config);
+
+    let json = serde_json::to_value(&req)?;
+    assert_eq!(json["stream_name"], "stream");
+    assert_eq!(json["config"]["durable_name"], "worker");
+    assert_eq!(json["config"]["max_waiting"], 512);
+    assert!(json.get("created").is_none());
+    assert!(json.get("delivered").is_none());
+
+    let push = ConsumerInfo {
+        config: ConsumerConfig {
+            deliver_subject: Some("deliver".to_string()),
+            ..info.config.clone()
+        },
+        ..info
+    };
+    let json = serde_json::to_value(CreateConsumerRequest::from(&push))?;
+    assert!(json["config"].get("max_waiting").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_config_from_info() -> std::io::Result<()> {
+    let info: StreamInfo = serde_json::from_str(STREAM_INFO)?;
+
+    let config = StreamConfig::from(&info);
+    assert_eq!(config, info.config);
+
+    let copy = config.cloned_as("copy");
+    assert_eq!(copy.name, "copy");
+    assert_eq!(config.name, "stream");
+    assert_eq!(copy.duplicate_window, config.duplicate_window);
+
+    let templated = StreamInfo {
+        config: StreamConfig {
+            template_owner: "template".to_string(),
+            ..info.config.clone()
+        },
+        ..info
+    };
+    let json = serde_json::to_value(StreamConfig::from(&templated))?;
+    assert!(json.get("template_owner").is_none());
+    let roundtrip: StreamConfig = serde_json::from_value(json)?;
+    assert_eq!(roundtrip, config);
+
+    Ok(())
+}
+
+fn subjects_page(
+    subjects: &[(&str, u64)],
+    total: usize,
+    offset: usize,
+) -> std::io::Result<serde_json::Value> {
+    let mut value: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+    value["state"]["subjects"] = subjects
+        .iter()
+        .map(|(subject, count)| (subject.to_string(), (*count).into()))
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+    value["total"] = total.into();
+    value["offset"] = offset.into();
+    value["limit"] = 2.into();
+    Ok(value)
+}
+
+#[test]
+fn jetstream_stream_subjects_paging() -> std::io::Result<()> {
+    let first = subjects_page(&[("a", 1), ("b", 2)], 3, 0)?;
+    let mut page: SubjectsPage = serde_json::from_value(first.clone())?;
+    assert_eq!(page.next_offset(), Some(2));
+
+    page.extend(serde_json::from_value(subjects_page(&[("c", 3)], 3, 2)?)?);
+    assert_eq!(page.next_offset(), None);
+
+    assert_eq!(page.subjects.len(), 3);
+    assert_eq!(page.subjects["a"], 1);
+    assert_eq!(page.subjects["c"], 3);
+
+    // the counts are kept out of the stream's state, which stays `Copy`
+    let info: StreamInfo = serde_json::from_value(first)?;
+    let state = info.state;
+    assert_eq!(state, info.state);
+    let json = serde_json::to_value(&page)?;
+    assert_eq!(json["state"]["subjects"]["b"], 2);
+
+    // a set of subjects that exactly fills a single page
+    let page = subjects_page(&[("a", 1), ("b", 2)], 2, 0)?;
+    let page: SubjectsPage = serde_json::from_value(page)?;
+    assert_eq!(page.next_offset(), None);
+
+    // responses without a subjects filter
+    let page: SubjectsPage = serde_json::from_str(STREAM_INFO)?;
+    assert!(page.subjects.is_empty());
+    assert_eq!(page.next_offset(), None);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_state_time_span() -> std::io::Result<()> {
+    let info: StreamInfo = serde_json::from_str(STREAM_INFO)?;
+    assert!(!info.state.is_empty());
+    assert_eq!(
+        info.state.time_span(),
+        Some(std::time::Duration::from_secs(120))
+    );
+
+    let empty: StreamState = serde_json::from_str(
+        r#"{
+            "messages": 0,
+            "bytes": 0,
+            "first_seq": 0,
+            "first_ts": "0001-01-01T00:00:00Z",
+            "last_seq": 0,
+            "last_ts": "0001-01-01T00:00:00Z",
+            "consumer_count": 0
+        }"#,
+    )?;
+    assert!(empty.is_empty());
+    assert_eq!(empty.first_ts, None);
+    assert_eq!(empty.last_ts, None);
+    assert_eq!(empty.time_span(), None);
+    assert!(StreamState::default().is_empty());
+
+    // unset timestamps are omitted rather than sent as the zero time
+    let json = serde_json::to_value(empty)?;
+    assert!(json.get("first_ts").is_none());
+    assert!(json.get("last_ts").is_none());
+    let json = serde_json::to_value(ConsumerConfig::durable_pull("worker"))?;
+    assert!(json.get("opt_start_time").is_none());
+    assert!(json.get("pause_until").is_none());
+
+    let state = StreamState {
+        first_ts: info.state.first_ts,
+        ..empty
+    };
+    let json = serde_json::to_value(state)?;
+    assert!(json["first_ts"].as_str().unwrap().ends_with('Z'));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_date_time_utc() -> std::io::Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    let time = DateTime::from(
+        SystemTime::UNIX_EPOCH + Duration::from_millis(1_625_140_800_500),
+    );
+    assert_eq!(
+        serde_json::to_string(&time)?,
+        r#""2021-07-01T12:00:00.500Z""#
+    );
+
+    let offset: DateTime =
+        serde_json::from_str(r#""2021-07-01T14:00:00.5+02:00""#)?;
+    assert_eq!(offset, time);
+    assert!(serde_json::to_string(&offset)?.ends_with(r#"Z""#));
+    assert_eq!("2021-07-01T12:00:00.5Z".parse::<DateTime>(), Ok(time));
+
+    assert!(serde_json::from_str::<DateTime>(r#""yesterday""#).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_work_queue_consumer_filters() {
+    let mut stream = StreamConfig {
+        name: "jobs".to_string(),
+        subjects: Some(vec!["jobs.>".to_string()]),
+        retention: RetentionPolicy::WorkQueue,
+        ..Default::default()
+    };
+    let consumer = |name: &str, filter: &str| ConsumerConfig {
+        filter_subject: filter.to_string(),
+        ..ConsumerConfig::durable_pull(name)
+    };
+    let existing = vec![consumer("emails", "jobs.email.*")];
+
+    for filter in &["jobs.sms.*", "jobs.email", "jobs.email.send.now"] {
+        let new = consumer("other", filter);
+        assert!(
+            validate_consumer_for_stream(&stream, &existing, &new).is_ok(),
+            "{}",
+            filter
+        );
+    }
+
+    for filter in &["", "jobs.>", "jobs.*.send", "jobs.email.send"] {
+        let new = consumer("other", filter);
+        let err =
+            validate_consumer_for_stream(&stream, &existing, &new).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", filter);
+    }
+
+    // updating the existing consumer does not conflict with itself
+    let update = consumer("emails", "jobs.email.>");
+    assert!(validate_consumer_for_stream(&stream, &existing, &update).is_ok());
+
+    stream.retention = RetentionPolicy::Limits;
+    let new = consumer("other", "jobs.>");
+    assert!(validate_consumer_for_stream(&stream, &existing, &new).is_ok());
+}
+
+#[test]
+fn jetstream_validate_replicas() {
+    for replicas in 1..=6 {
+        let stream = StreamConfig {
+            name: "replicated".to_string(),
+            num_replicas: replicas,
+            ..Default::default()
+        };
+        let consumer = ConsumerConfig {
+            num_replicas: replicas,
+            ..ConsumerConfig::durable_pull("replicated")
+        };
+
+        for result in &[stream.validate(), consumer.validate()] {
+            match replicas {
+                1 | 3 | 5 => assert_eq!(result.as_ref().unwrap(), &vec![]),
+                2 | 4 => {
+                    let lints = result.as_ref().unwrap();
+                    assert_eq!(lints.len(), 1);
+                    assert_eq!(lints[0].field, "num_replicas");
+                }
+                _ => assert_eq!(
+                    result.as_ref().unwrap_err().kind(),
+                    std::io::ErrorKind::InvalidInput
+                ),
+            }
+        }
+    }
+
+    // consumers inherit the replication of their stream by default
+    assert_eq!(ConsumerConfig::default().validate().unwrap(), vec![]);
+}
+
+fn direct_get_reply(seq: u64, data: &str) -> nats::Message {
+    let sequence = seq.to_string();
+    let headers = vec![
+        ("Nats-Stream", "events"),
+        ("Nats-Subject", "events.created"),
+        ("Nats-Sequence", sequence.as_str()),
+        ("Nats-Time-Stamp", "2021-07-01T12:00:00.5Z"),
+    ]
+    .into_iter()
+    .collect::<nats::Headers>();
+    nats::Message::new("_INBOX.batch", None, data, Some(headers))
+}
+
+fn status_reply(status: &str) -> std::io::Result<nats::Message> {
+    use std::convert::TryFrom;
+
+    let headers = nats::Headers::try_from(
+        format!("NATS/1.0 {}\r\n\r\n", status).as_bytes(),
+    )?;
+    Ok(nats::Message::new("_INBOX.batch", None, "", Some(headers)))
+}
+
+#[test]
+fn jetstream_pull_drain() -> std::io::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let message =
+        |data: &str| nats::Message::new("orders.received", None, data, None);
+    // a mock server that returns two batches, the first of them expiring
+    // before it was filled, and then reports that the stream is drained
+    let mut batches = vec![
+        vec![message("one"), message("two")],
+        vec![message("three"), status_reply("408 Request Timeout")?],
+        vec![status_reply("404 No Messages")?],
+    ]
+    .into_iter();
+    let requests = Arc::new(Mutex::new(vec![]));
+    let seen = requests.clone();
+
+    let next_request = NextRequest {
+        batch: 2,
+        ..Default::default()
+    };
+    let drain = PullDrain::new(next_request.clone(), move |req| {
+        seen.lock().unwrap().push(req.clone());
+        Ok(batches.next().unwrap_or_default())
+    });
+    let msgs = drain.collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(
+        msgs.iter().map(|m| m.data.clone()).collect::<Vec<_>>(),
+        vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+    );
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|req| req.no_wait && req.batch == 2));
+
+    let mut deleted = PullDrain::new(next_request, |_| {
+        Ok(vec![status_reply("409 Consumer Deleted")?])
+    });
+    let err = deleted.next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("Consumer Deleted"));
+    assert!(deleted.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_ordered_state() {
+    use std::time::Duration;
+
+    let message = |stream_seq: u64, consumer_seq: u64| {
+        let reply = format!(
+            "$JS.ACK.orders.ordered.1.{}.{}.1625140800000000000.0",
+            stream_seq, consumer_seq
+        );
+        nats::Message::new("orders.received", Some(&reply), "", None)
+    };
+
+    // a fresh state has not missed a heartbeat yet, but one that stayed
+    // silent for longer than twice the interval has
+    let mut state = OrderedState::default();
+    assert_eq!((state.stream_seq, state.consumer_seq), (0, 0));
+    assert!(!state.heartbeat_missed(Duration::from_secs(5)));
+    let silent = OrderedState {
+        last_heartbeat: (std::time::SystemTime::now()
+            - Duration::from_secs(11))
+        .into(),
+        ..state
+    };
+    assert!(silent.heartbeat_missed(Duration::from_secs(5)));
+
+    let first = message(10, 1);
+    let info = first.jetstream_message_info().unwrap();
+    assert!(!state.needs_reset(&info));
+    state.advance(&info);
+    assert_eq!((state.stream_seq, state.consumer_seq), (10, 1));
+    assert!(!state.heartbeat_missed(Duration::from_secs(5)));
+
+    // the next message in order, even if the stream skipped sequences
+    let second = message(12, 2);
+    let info = second.jetstream_message_info().unwrap();
+    assert!(!state.needs_reset(&info));
+    state.advance(&info);
+
+    // a gap in the consumer sequence
+    let gap = message(15, 4);
+    assert!(state.needs_reset(&gap.jetstream_message_info().unwrap()));
+    assert_eq!(state.reset(), 13);
+    assert!(
+        !state.needs_reset(&message(13, 1).jetstream_message_info().unwrap())
+    );
+}
+
+#[test]
+fn jetstream_direct_get_batch() -> std::io::Result<()> {
+    let replies = vec![
+        direct_get_reply(1, "one"),
+        direct_get_reply(2, "two"),
+        direct_get_reply(5, "three"),
+        status_reply("204 EOB")?,
+        // anything after the terminator belongs to another request
+        direct_get_reply(6, "four"),
+    ];
+
+    let msgs =
+        DirectGetBatch::new(replies).collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(
+        msgs.iter().map(|m| m.sequence).collect::<Vec<_>>(),
+        vec![1, 2, 5]
+    );
+    assert!(msgs.iter().all(|m| m.subject == "events.created"));
+    assert_eq!(msgs[2].data, b"three");
+    assert_eq!(
+        msgs[0].time,
+        DateTime("2021-07-01T12:00:00.5Z".parse().unwrap())
+    );
+
+    let mut empty = DirectGetBatch::new(vec![status_reply("204 EOB")?]);
+    assert!(empty.next().is_none());
+
+    // a regular header named like the status is not taken for one
+    let headers = <nats::Headers as std::convert::TryFrom<_>>::try_from(
+        &b"NATS/1.0 204 EOB\r\nStatus: 503\r\n\r\n"[..],
+    )?;
+    assert!(headers.contains_key("Status"));
+    assert_eq!(
+        headers.get(nats::Headers::STATUS),
+        Some(&vec!["204".to_string()].into_iter().collect())
+    );
+
+    let mut broken = direct_get_reply(3, "broken");
+    broken
+        .headers
+        .as_mut()
+        .unwrap()
+        .inner
+        .remove("Nats-Sequence");
+    let replies = vec![broken, direct_get_reply(4, "ok"), status_reply("204")?];
+    let results = DirectGetBatch::new(replies).collect::<Vec<_>>();
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[0].as_ref().unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+    assert_eq!(results[1].as_ref().unwrap().sequence, 4);
+
+    let mut missing =
+        DirectGetBatch::new(vec![status_reply("404 Message Not Found")?]);
+    let err = missing.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    assert!(missing.next().is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_pub_opts_ttl() {
+    let opts = PubOpts {
+        ttl: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+    let headers = opts.headers();
+    assert_eq!(headers.len(), 1);
+    assert!(headers.get("Nats-TTL").unwrap().contains("30s"));
+
+    let opts = PubOpts {
+        ttl: Some(Duration::from_secs(90 * 60)),
+        id: "order-1".to_string(),
+        ..Default::default()
+    };
+    let headers = opts.headers();
+    assert!(headers.get("Nats-TTL").unwrap().contains("1h30m"));
+    assert!(headers.get("Nats-Msg-Id").unwrap().contains("order-1"));
+
+    // unset options are skipped entirely
+    assert!(PubOpts::default().headers().is_empty());
+
+    let mut stream = StreamConfig::from("events");
+    let err = opts.validate_for_stream(&stream).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(PubOpts::default().validate_for_stream(&stream).is_ok());
+
+    stream.allow_msg_ttl = true;
+    assert!(opts.validate_for_stream(&stream).is_ok());
+}
+
+#[test]
+fn jetstream_stream_lints() {
+    let fields = |config: &StreamConfig| {
+        config.lint().iter().map(|l| l.field).collect::<Vec<_>>()
+    };
+
+    let stream = StreamConfig {
+        name: "orders".to_string(),
+        num_replicas: 3,
+        ..Default::default()
+    };
+    assert!(fields(&stream).is_empty());
+
+    let templated = StreamConfig {
+        template_owner: "orders_template".to_string(),
+        ..stream.clone()
+    };
+    assert_eq!(fields(&templated), vec!["template_owner"]);
+
+    let unbounded = StreamConfig {
+        discard: DiscardPolicy::New,
+        ..stream.clone()
+    };
+    assert_eq!(fields(&unbounded), vec!["discard"]);
+
+    let bounded = StreamConfig {
+        max_msgs: 10_000,
+        ..unbounded.clone()
+    };
+    assert!(fields(&bounded).is_empty());
+
+    // a mirror is never published to, so there is nothing to deduplicate
+    let mirror = StreamConfig {
+        mirror: Some(StreamSource {
+            name: "orders_eu".to_string(),
+            ..Default::default()
+        }),
+        ..stream.clone()
+    };
+    assert!(fields(&mirror).is_empty());
+    let deduplicated = StreamConfig {
+        duplicate_window: Duration::from_secs(60),
+        ..mirror
+    };
+    assert_eq!(fields(&deduplicated), vec!["duplicate_window"]);
+    assert!(deduplicated.lint()[0].message.contains("1m"));
+    let published = StreamConfig {
+        mirror: None,
+        ..deduplicated
+    };
+    assert!(fields(&published).is_empty());
+
+    let even = StreamConfig {
+        num_replicas: 2,
+        ..templated
+    };
+    assert_eq!(fields(&even), vec!["template_owner", "num_replicas"]);
+    assert_eq!(even.validate().unwrap(), even.lint());
+}
+
+#[test]
+fn jetstream_validate_republish() {
+    let stream = StreamConfig {
+        name: "orders".to_string(),
+        subjects: Some(vec!["orders.*".to_string()]),
+        num_replicas: 1,
+        republish: Some(Republish {
+            src: "orders.created".to_string(),
+            dest: "audit.orders.created".to_string(),
+            headers_only: true,
+        }),
+        ..Default::default()
+    };
+    assert_eq!(stream.validate().unwrap(), vec![]);
+
+    let invalid = |src: &str, dest: &str| {
+        let stream = StreamConfig {
+            republish: Some(Republish {
+                src: src.to_string(),
+                dest: dest.to_string(),
+                headers_only: false,
+            }),
+            ..stream.clone()
+        };
+        stream.validate().unwrap_err().kind()
+    };
+
+    // src does not match any subject of the stream
+    assert_eq!(
+        invalid("invoices.>", "audit.invoices"),
+        std::io::ErrorKind::InvalidInput
+    );
+    // dest would be captured by the stream again
+    assert_eq!(
+        invalid("orders.created", "orders.copied"),
+        std::io::ErrorKind::InvalidInput
+    );
+    // malformed subjects
+    assert_eq!(
+        invalid("orders..created", "audit"),
+        std::io::ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        invalid("orders.created", "audit.>.x"),
+        std::io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn jetstream_subjects_overlap() {
+    let s = |subjects: &[&str]| {
+        subjects.iter().map(|s| s.to_string()).collect::<Vec<_>>()
+    };
+
+    assert!(subjects_overlap(&s(&["a.*"]), &s(&["a.b"])));
+    assert!(subjects_overlap(&s(&["a.>"]), &s(&["a.b.c"])));
+    assert!(subjects_overlap(&s(&["a.b.c"]), &s(&["a.>"])));
+    assert!(subjects_overlap(&s(&["*.b"]), &s(&["a.*"])));
+    assert!(subjects_overlap(&s(&["x", "a.b"]), &s(&["y", "a.b"])));
+
+    assert!(!subjects_overlap(&s(&["a.*"]), &s(&["a.b.c"])));
+    assert!(!subjects_overlap(&s(&["a.>"]), &s(&["a"])));
+    assert!(!subjects_overlap(&s(&["a.b", "c.>"]), &s(&["a.c", "d.>"])));
+    assert!(!subjects_overlap(&s(&[]), &s(&["a.b"])));
+}
+
+#[test]
+fn jetstream_validate_subjects() {
+    let stream = |subjects: &[&str]| StreamConfig {
+        name: "events".to_string(),
+        subjects: Some(subjects.iter().map(|s| s.to_string()).collect()),
+        num_replicas: 1,
+        ..Default::default()
+    };
+
+    assert!(stream(&["events.>", "audit.*.created"])
+        .validate_subjects()
+        .is_ok());
+    assert!(StreamConfig::from("events").validate_subjects().is_ok());
+
+    for subject in &["", "events..created", ".events", "events.>.x", "a b"] {
+        let err = stream(&[subject]).validate_subjects().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", subject);
+        assert!(stream(&[subject]).validate().is_err());
+    }
+}
+
+#[test]
+fn jetstream_effective_subjects() {
+    let explicit = StreamConfig {
+        name: "events".to_string(),
+        subjects: Some(vec!["events.*".to_string(), "audit".to_string()]),
+        ..Default::default()
+    };
+    assert_eq!(explicit.effective_subjects(), vec!["events.*", "audit"]);
+
+    let defaulted = StreamConfig::from("events");
+    assert_eq!(defaulted.subjects, None);
+    assert_eq!(defaulted.effective_subjects(), vec!["events"]);
+
+    let empty = StreamConfig {
+        subjects: Some(vec![]),
+        ..defaulted
+    };
+    assert_eq!(empty.effective_subjects(), vec!["events"]);
+
+    // an empty list is left out, like `None`, since the server rejects it
+    let json = serde_json::to_value(&empty).unwrap();
+    assert!(json.get("subjects").is_none());
+    assert_eq!(
+        serde_json::to_string(&empty).unwrap(),
+        serde_json::to_string(&StreamConfig::from("events")).unwrap()
+    );
+    assert_eq!(
+        empty.fingerprint().unwrap(),
+        StreamConfig::from("events").fingerprint().unwrap()
+    );
+    let json = serde_json::to_value(&explicit).unwrap();
+    assert_eq!(json["subjects"], serde_json::json!(["events.*", "audit"]));
+}
+
+#[test]
+fn jetstream_push_or_pull() {
+    let pull = ConsumerConfig {
+        max_batch: 100,
+        max_expires: Duration::from_secs(30),
+        ..ConsumerConfig::durable_pull("workers")
+    };
+    assert!(pull.is_pull());
+    assert!(!pull.is_push());
+
+    let push = ConsumerConfig {
+        deliver_subject: Some("deliver.monitor".to_string()),
+        ..Default::default()
+    };
+    assert!(push.is_push());
+    assert!(!push.is_pull());
+
+    let queue_push = ConsumerConfig {
+        deliver_group: Some("monitors".to_string()),
+        ..push
+    };
+    assert!(queue_push.is_push());
+    assert!(!queue_push.is_pull());
+    assert!(queue_push.validate().is_ok());
+
+    // a group alone has nothing to deliver to
+    let group_only = ConsumerConfig {
+        deliver_subject: None,
+        ..queue_push
+    };
+    assert!(group_only.is_pull());
+    let err = group_only.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("deliver_group"), "{}", err);
+}
+
+#[test]
+fn jetstream_limits_as_strings() -> std::io::Result<()> {
+    let config = |max_bytes: &str| {
+        format!(
+            r#"{{
+                "name": "events",
+                "max_bytes": {},
+                "max_msgs": -1,
+                "max_msgs_per_subject": "-1",
+                "discard": "old",
+                "retention": "limits",
+                "max_consumers": -1,
+                "max_age": "0",
+                "storage": "file",
+                "num_replicas": 1
+            }}"#,
+            max_bytes
+        )
+    };
+
+    let number: StreamConfig =
+        serde_json::from_str(&config("9223372036854775807"))?;
+    let string: StreamConfig =
+        serde_json::from_str(&config(r#""9223372036854775807""#))?;
+    assert_eq!(number.max_bytes, i64::MAX);
+    assert_eq!(number, string);
+    assert_eq!(string.max_msgs_per_subject, -1);
+
+    assert!(serde_json::from_str::<StreamConfig>(&config(r#""lots""#)).is_err());
+
+    let state: StreamState = serde_json::from_str(
+        r#"{
+            "messages": "18446744073709551615",
+            "bytes": 1024,
+            "first_seq": "1",
+            "first_ts": "2021-07-01T12:00:00Z",
+            "last_seq": 10,
+            "last_ts": "2021-07-01T12:00:00Z",
+            "consumer_count": 0
+        }"#,
+    )?;
+    assert_eq!(state.messages, u64::MAX);
+    assert_eq!(state.first_seq, 1);
+
+    let limits: AccountLimits = serde_json::from_str(
+        r#"{
+            "max_memory": "-1",
+            "max_storage": "1073741824",
+            "max_streams": -1,
+            "max_consumers": "100"
+        }"#,
+    )?;
+    assert_eq!(limits.max_memory, -1);
+    assert_eq!(limits.max_storage, 1 << 30);
+    assert_eq!(limits.max_consumers, 100);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_drain_rate() -> std::io::Result<()> {
+    let snapshot = |ts: Option<&str>, num_pending: u64| {
+        let mut info: ConsumerInfo = serde_json::from_str(CONSUMER_INFO)?;
+        info.ts = ts.map(|ts| DateTime(ts.parse().unwrap()));
+        info.num_pending = num_pending;
+        Ok::<_, std::io::Error>(info)
+    };
+
+    let earlier = snapshot(Some("2021-07-01T12:30:00Z"), 1000)?;
+    let draining = snapshot(Some("2021-07-01T12:30:10Z"), 500)?;
+    let growing = snapshot(Some("2021-07-01T12:30:04Z"), 1200)?;
+
+    assert_eq!(draining.drain_rate(&earlier), Some(50.0));
+    assert_eq!(growing.drain_rate(&earlier), Some(-50.0));
+
+    // snapshots given in the wrong order, or taken at the same time
+    assert_eq!(earlier.drain_rate(&draining), None);
+    assert_eq!(earlier.drain_rate(&earlier), None);
+
+    let untimed = snapshot(None, 500)?;
+    assert_eq!(untimed.drain_rate(&earlier), None);
+    assert_eq!(earlier.drain_rate(&untimed), None);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_response_types() -> std::io::Result<()> {
+    let known = [
+        (STREAM_INFO_RESPONSE_TYPE, ResponseType::StreamInfo),
+        (CONSUMER_INFO_RESPONSE_TYPE, ResponseType::ConsumerInfo),
+        (PUB_ACK_RESPONSE_TYPE, ResponseType::PubAck),
+        (ACCOUNT_INFO_RESPONSE_TYPE, ResponseType::AccountInfo),
+    ];
+    for (type_str, response_type) in &known {
+        assert_eq!(ResponseType::from_type_str(type_str), Some(*response_type));
+        assert_eq!(response_type.as_type_str(), *type_str);
+    }
+    assert_eq!(
+        ResponseType::from_type_str("io.nats.jetstream.api.v1.stream_names"),
+        None
+    );
+
+    let with_type = |json: &str, type_str: &str| {
+        let mut value: serde_json::Value = serde_json::from_str(json).unwrap();
+        value["type"] = type_str.into();
+        serde_json::to_vec(&value).unwrap()
+    };
+
+    let consumer = with_type(CONSUMER_INFO, CONSUMER_INFO_RESPONSE_TYPE);
+    let info: ConsumerInfo = from_typed_json(&consumer)?;
+    assert_eq!(info, serde_json::from_str(CONSUMER_INFO)?);
+
+    let stream = with_type(STREAM_INFO, STREAM_INFO_RESPONSE_TYPE);
+    let info: StreamInfo = from_typed_json(&stream)?;
+    assert_eq!(info, serde_json::from_str(STREAM_INFO)?);
+
+    let mismatched = with_type(STREAM_INFO, CONSUMER_INFO_RESPONSE_TYPE);
+    let err = from_typed_json::<StreamInfo>(&mismatched).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    let ack: PubAck = from_typed_json(br#"{"stream": "events", "seq": 7}"#)?;
+    assert_eq!(ack.seq, 7);
+
+    let account: AccountInfo = from_typed_json(
+        br#"{
+            "type": "io.nats.jetstream.api.v1.account_info_response",
+            "memory": 0,
+            "storage": 1024,
+            "streams": 1,
+            "consumers": 2,
+            "api": {"total": 10, "errors": 0},
+            "limits": {
+                "max_memory": -1,
+                "max_storage": -1,
+                "max_streams": -1,
+                "max_consumers": -1
+            }
+        }"#,
+    )?;
+    assert_eq!(account.consumers, 2);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_deliver_from() {
+    let start =
+        std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_625_140_800);
+
+    let by_time =
+        ConsumerConfig::durable_pull("replay").deliver_from_time(start);
+    assert_eq!(by_time.deliver_policy, DeliverPolicy::ByStartTime);
+    assert_eq!(by_time.opt_start_time, Some(DateTime::from(start)));
+    assert_eq!(by_time.opt_start_seq, 0);
+    assert_eq!(by_time.durable_name.as_deref(), Some("replay"));
+
+    let by_seq = by_time.deliver_from_seq(42);
+    assert_eq!(by_seq.deliver_policy, DeliverPolicy::ByStartSeq);
+    assert_eq!(by_seq.opt_start_seq, 42);
+    assert_eq!(by_seq.opt_start_time, None);
+
+    let chrono_time = DateTime::default().0;
+    let by_chrono = ConsumerConfig::default().deliver_from_time(chrono_time);
+    assert_eq!(by_chrono.opt_start_time, Some(DateTime(chrono_time)));
+}
+
+#[test]
+fn jetstream_first_seq() -> std::io::Result<()> {
+    let config = StreamConfig::from("events").first_seq(1_000);
+    assert_eq!(config.first_seq, 1_000);
+
+    let json = serde_json::to_value(&config)?;
+    assert_eq!(json["first_seq"], 1_000);
+    let parsed: StreamConfig = serde_json::from_value(json)?;
+    assert_eq!(parsed, config);
+
+    let json = serde_json::to_value(StreamConfig::from("events"))?;
+    assert!(json.get("first_seq").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_purge_request() -> std::io::Result<()> {
+    assert_eq!(serde_json::to_string(&PurgeRequest::default())?, "{}");
+
+    let req = PurgeRequest {
+        filter: Some("events.created".to_string()),
+        keep: Some(5),
+        ..Default::default()
+    };
+    assert_eq!(
+        serde_json::to_value(&req)?,
+        serde_json::json!({"filter": "events.created", "keep": 5})
+    );
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_purge_keep() -> std::io::Result<()> {
+    // a mock server holding a stream of 20 messages that answers purge
+    // requests with `keep` by removing all but the newest messages
+    let mut sequences = (1..=20).collect::<Vec<u64>>();
+    let mut purge = |req: &[u8]| -> std::io::Result<Vec<u8>> {
+        let req: PurgeRequest = serde_json::from_slice(req)?;
+        let keep = req.keep.unwrap_or(0) as usize;
+        let purged = sequences.len().saturating_sub(keep);
+        sequences.drain(..purged);
+        Ok(serde_json::to_vec(&serde_json::json!({
+            "success": true,
+            "purged": purged,
+        }))?)
+    };
+
+    let state = StreamState {
+        messages: 20,
+        first_seq: 1,
+        last_seq: 20,
+        ..Default::default()
+    };
+    let req = PurgeRequest::keep_newest(5);
+    req.validate()?;
+    assert_eq!(serde_json::to_string(&req)?, r#"{"keep":5}"#);
+    assert_eq!(req.expected_purged(&state), Some(15));
+
+    let res: ApiResponse<PurgeResponse> =
+        serde_json::from_slice(&purge(&serde_json::to_vec(&req)?)?)?;
+    let res = res.into_result().map_err(std::io::Error::from)?;
+    assert!(res.success);
+    assert_eq!(res.purged, 15);
+    assert_eq!(res.remaining(&state), 5);
+    drop(purge);
+    assert_eq!(sequences, (16..=20).collect::<Vec<_>>());
+
+    // keeping more messages than the stream has purges nothing
+    assert_eq!(
+        PurgeRequest::keep_newest(50).expected_purged(&state),
+        Some(0)
+    );
+    assert_eq!(PurgeRequest::default().expected_purged(&state), Some(20));
+    assert_eq!(PurgeRequest::up_to_seq(5).expected_purged(&state), None);
+
+    let both = PurgeRequest {
+        seq: Some(10),
+        ..PurgeRequest::keep_newest(5)
+    };
+    let err = both.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_retention_policy_acks() {
+    let policies = [
+        (RetentionPolicy::Limits, false, false),
+        (RetentionPolicy::Interest, true, false),
+        (RetentionPolicy::WorkQueue, true, true),
+        (RetentionPolicy::Unknown, false, false),
+    ];
+    for (policy, ack_driven, single_consumer) in &policies {
+        assert_eq!(policy.ack_driven(), *ack_driven, "{:?}", policy);
+        assert_eq!(
+            policy.single_consumer_per_subject(),
+            *single_consumer,
+            "{:?}",
+            policy
+        );
+    }
+}
+
+#[test]
+fn jetstream_retention_presets() {
+    let week = std::time::Duration::from_secs(7 * 24 * 60 * 60);
+    let limits = StreamConfig::limits()
+        .max_age(week)
+        .max_bytes(10 * 1024 * 1024 * 1024);
+    assert_eq!(limits.retention, RetentionPolicy::Limits);
+    assert_eq!(limits.discard, DiscardPolicy::Old);
+    assert_eq!(limits.max_age, Duration::from_secs(604_800));
+    assert_eq!(limits.max_bytes, 10_737_418_240);
+    assert_eq!(limits.max_msgs, -1);
+    assert_eq!(limits.subjects, None);
+
+    let work_queue = StreamConfig::work_queue(vec!["jobs.>"]);
+    assert_eq!(work_queue.retention, RetentionPolicy::WorkQueue);
+    assert_eq!(work_queue.discard, DiscardPolicy::New);
+    assert!(!work_queue.no_ack);
+    assert_eq!(work_queue.effective_subjects(), vec!["jobs.>"]);
+
+    let interest =
+        StreamConfig::interest(vec!["events.*".to_string(), "audit".into()]);
+    assert_eq!(interest.retention, RetentionPolicy::Interest);
+    assert_eq!(interest.discard, DiscardPolicy::Old);
+    assert!(!interest.no_ack);
+    assert_eq!(interest.effective_subjects(), vec!["events.*", "audit"]);
+}
+
+#[test]
+fn jetstream_min_server_version() {
+    let version =
+        |major, minor, patch| semver::Version::new(major, minor, patch);
+
+    let plain = StreamConfig::limits().max_bytes(1024);
+    assert_eq!(plain.min_server_version(), version(2, 2, 0));
+
+    let compressed = StreamConfig {
+        compression: Some(Compression::S2),
+        ..plain.clone()
+    };
+    assert!(compressed.min_server_version() >= version(2, 10, 0));
+
+    let per_subject = StreamConfig {
+        max_msgs_per_subject: 1,
+        ..plain.clone()
+    };
+    assert_eq!(per_subject.min_server_version(), version(2, 3, 0));
+
+    let expiring = StreamConfig {
+        allow_msg_ttl: true,
+        ..compressed
+    };
+    assert_eq!(expiring.min_server_version(), version(2, 11, 0));
+}
+
+#[test]
+fn jetstream_stream_compression() -> std::io::Result<()> {
+    let s2 = StreamConfig {
+        compression: Some(Compression::S2),
+        ..StreamConfig::from("events")
+    };
+    let json = serde_json::to_value(&s2)?;
+    assert_eq!(json["compression"], "s2");
+    assert_eq!(serde_json::from_value::<StreamConfig>(json)?, s2);
+
+    // servers before 2.10 do not report the field
+    let info: StreamInfo = serde_json::from_str(STREAM_INFO)?;
+    assert!(!STREAM_INFO.contains("compression"));
+    assert_eq!(info.config.compression, None);
+
+    let unset = StreamConfig::from("events");
+    let uncompressed = StreamConfig {
+        compression: Some(Compression::None),
+        ..unset.clone()
+    };
+    assert!(serde_json::to_value(&unset)?.get("compression").is_none());
+    let json = serde_json::to_value(&uncompressed)?;
+    assert_eq!(json["compression"], "none");
+    assert_eq!(serde_json::from_value::<StreamConfig>(json)?, uncompressed);
+    assert_ne!(unset, uncompressed);
+    assert!(unset.semantically_eq(&uncompressed));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_downgrade_to() {
+    let version =
+        |major, minor, patch| semver::Version::new(major, minor, patch);
+
+    let mut config = StreamConfig {
+        name: "events".to_string(),
+        subjects: Some(vec!["events.>".to_string()]),
+        compression: Some(Compression::S2),
+        republish: Some(Republish {
+            src: "events.>".to_string(),
+            dest: "audit.events.>".to_string(),
+            headers_only: false,
+        }),
+        max_msgs_per_subject: 10,
+        ..StreamConfig::limits()
+    };
+    config
+        .metadata
+        .insert("owner".to_string(), "billing".to_string());
+    assert_eq!(config.min_server_version(), version(2, 10, 0));
+
+    assert_eq!(config.downgrade_to(version(2, 10, 0)), config);
+    assert_eq!(config.downgrade_to(version(2, 12, 1)), config);
+
+    let downgraded = config.downgrade_to(version(2, 9, 0));
+    assert_eq!(downgraded.compression, None);
+    assert!(downgraded.metadata.is_empty());
+    assert_eq!(downgraded.republish, config.republish);
+    assert_eq!(downgraded.max_msgs_per_subject, 10);
+    assert_eq!(downgraded.min_server_version(), version(2, 9, 0));
+
+    let ancient = config.downgrade_to(version(2, 2, 0));
+    assert_eq!(ancient.republish, None);
+    assert_eq!(ancient.max_msgs_per_subject, 0);
+    assert_eq!(ancient.min_server_version(), version(2, 2, 0));
+}
+
+#[test]
+fn jetstream_stream_config_summary() {
+    let config = StreamConfig {
+        name: "ORDERS".to_string(),
+        num_replicas: 3,
+        max_msgs_per_subject: 100,
+        ..StreamConfig::limits()
+            .max_age(std::time::Duration::from_secs(7 * 24 * 60 * 60))
+            .max_bytes(10 * 1024 * 1024 * 1024)
+    };
+    assert_eq!(
+        config.to_string(),
+        "ORDERS: subjects=[ORDERS] retention=limits storage=file replicas=3 \
+        max_msgs=∞ max_bytes=10GiB max_age=168h max_msgs_per_subject=100"
+    );
+
+    let config = StreamConfig {
+        name: "jobs".to_string(),
+        storage: StorageType::Memory,
+        max_bytes: 1536,
+        max_msgs: 5000,
+        ..StreamConfig::work_queue(vec!["jobs.high", "jobs.low"])
+    };
+    assert_eq!(
+        config.to_string(),
+        "jobs: subjects=[jobs.high, jobs.low] retention=workqueue \
+        storage=memory replicas=1 max_msgs=5000 max_bytes=1.5KiB max_age=∞ \
+        max_msgs_per_subject=∞"
+    );
+}
+
+#[test]
+fn jetstream_consumer_config_summary() {
+    let pull = ConsumerConfig {
+        filter_subject: "ORDERS.received".to_string(),
+        ..ConsumerConfig::durable_pull("processor")
+    };
+    assert_eq!(
+        pull.to_string(),
+        "processor: pull ack=explicit deliver=all filter=ORDERS.received"
+    );
+
+    let push = ConsumerConfig {
+        deliver_subject: Some("monitor".to_string()),
+        ack_policy: AckPolicy::None,
+        deliver_policy: DeliverPolicy::New,
+        ..Default::default()
+    };
+    assert_eq!(
+        push.to_string(),
+        "ephemeral: push ack=none deliver=new filter=>"
+    );
+}
+
+#[test]
+fn jetstream_purge_up_to_seq() -> std::io::Result<()> {
+    let req = PurgeRequest::up_to_seq(101);
+    assert_eq!(req.seq, Some(101));
+    assert_eq!(req.filter, None);
+    assert_eq!(req.keep, None);
+    assert_eq!(serde_json::to_string(&req)?, r#"{"seq":101}"#);
+
+    // nothing comes before the first sequence, and 0 must never be sent
+    assert_eq!(PurgeRequest::up_to_seq(1).seq, Some(1));
+    assert_eq!(PurgeRequest::up_to_seq(0), PurgeRequest::up_to_seq(1));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_purge_emptying() -> std::io::Result<()> {
+    // a stream created with first_seq 5 that went on to hold messages
+    // 5..=12, as found by create_empty_stream
+    let state = StreamState {
+        messages: 8,
+        first_seq: 5,
+        last_seq: 12,
+        ..Default::default()
+    };
+
+    // purging up to first_seq would keep every message
+    let req = PurgeRequest::emptying(&state, 5);
+    assert_eq!(req, PurgeRequest::up_to_seq(13));
+    let remaining = (state.first_seq..=state.last_seq)
+        .filter(|seq| *seq >= req.seq.unwrap())
+        .count();
+    assert_eq!(remaining, 0);
+
+    // a first_seq beyond the last message is where the stream continues
+    assert_eq!(
+        PurgeRequest::emptying(&state, 100),
+        PurgeRequest::up_to_seq(100)
+    );
+
+    // without a first_seq the whole stream is purged
+    let req = PurgeRequest::emptying(&state, 0);
+    assert_eq!(req, PurgeRequest::default());
+    assert_eq!(req.expected_purged(&state), Some(8));
+    assert_eq!(serde_json::to_string(&req)?, "{}");
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_rate_limit() {
+    let limited = ConsumerConfig {
+        deliver_subject: Some("deliver".to_string()),
+        ..Default::default()
+    }
+    .rate_limit(1024 * 1024);
+    assert_eq!(limited.rate_limit, 1_048_576);
+    assert!(limited.validate().is_ok());
+
+    assert_eq!(limited.clone().rate_limit(u64::MAX).rate_limit, i64::MAX);
+
+    let negative = ConsumerConfig {
+        rate_limit: -1,
+        ..limited
+    };
+    let err = negative.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn jetstream_source_info() {
+    let headers = |value: &str| {
+        vec![(SourceInfo::HEADER, value), ("Nats-Msg-Id", "abc")]
+            .into_iter()
+            .collect::<nats::Headers>()
+    };
+
+    assert_eq!(
+        SourceInfo::from_headers(&headers("ORDERS 42")),
+        Some(SourceInfo {
+            stream: "ORDERS".to_string(),
+            sequence: 42,
+            subject: None,
+        })
+    );
+
+    assert_eq!(
+        SourceInfo::from_headers(&headers(
+            "ORDERS:zNPDdVzu 7 orders.* > orders.eu.created"
+        )),
+        Some(SourceInfo {
+            stream: "ORDERS".to_string(),
+            sequence: 7,
+            subject: Some("orders.eu.created".to_string()),
+        })
+    );
+
+    assert_eq!(SourceInfo::from_headers(&headers("ORDERS")), None);
+    assert_eq!(SourceInfo::from_headers(&headers("ORDERS seven")), None);
+
+    let unsourced = vec![("Nats-Msg-Id", "abc")]
+        .into_iter()
+        .collect::<nats::Headers>();
+    assert_eq!(SourceInfo::from_headers(&unsourced), None);
+}
+
+#[test]
+fn jetstream_source_subject_transforms() -> std::io::Result<()> {
+    let source = StreamSource {
+        name: "ORDERS".to_string(),
+        subject_transforms: Some(vec![
+            SubjectTransformConfig {
+                src: "orders.eu.*".to_string(),
+                dest: "eu.{{wildcard(1)}}".to_string(),
+            },
+            SubjectTransformConfig {
+                src: "orders.us.*".to_string(),
+                dest: "us.{{wildcard(1)}}".to_string(),
+            },
+        ]),
+        ..Default::default()
+    };
+    let config = StreamConfig {
+        name: "REGIONS".to_string(),
+        sources: Some(vec![source.clone()]),
+        ..StreamConfig::limits()
+    };
+
+    let json = serde_json::to_value(&config)?;
+    assert_eq!(
+        json["sources"][0]["subject_transforms"][1]["src"],
+        "orders.us.*"
+    );
+    assert!(json["sources"][0].get("filter_subject").is_none());
+    let parsed: StreamConfig = serde_json::from_value(json)?;
+    assert_eq!(parsed, config);
+
+    assert!(config.validate().is_ok());
+    assert_eq!(config.min_server_version(), semver::Version::new(2, 10, 0));
+    let downgraded = config.downgrade_to(semver::Version::new(2, 9, 0));
+    assert_eq!(downgraded.sources.unwrap()[0].subject_transforms, None);
+
+    let filtered = StreamSource {
+        filter_subject: "orders.>".to_string(),
+        ..source
+    };
+    let err = filtered.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let mirror = StreamConfig {
+        mirror: Some(filtered),
+        ..StreamConfig::from("MIRROR")
+    };
+    assert!(mirror.validate().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_options_api_subjects() {
+    let default = JetStreamOptions::new();
+    assert_eq!(default.prefix(), "$JS.API.");
+    assert_eq!(default.domain_name(), None);
+    assert_eq!(default.api_subject("INFO"), "$JS.API.INFO");
+
+    let domain = JetStreamOptions::new().domain("leaf");
+    assert_eq!(domain.domain_name(), Some("leaf"));
+    assert_eq!(
+        domain.api_subject("STREAM.INFO.events"),
+        "$JS.leaf.API.STREAM.INFO.events"
+    );
+
+    let custom = domain.api_prefix("imported.js");
+    assert_eq!(custom.domain_name(), None);
+    assert_eq!(
+        custom.api_subject("CONSUMER.CREATE.events"),
+        "imported.js.CONSUMER.CREATE.events"
+    );
+    assert_eq!(custom, JetStreamOptions::new().api_prefix("imported.js."));
+}
+
+#[test]
+fn jetstream_stream_config_unknown_fields() -> std::io::Result<()> {
+    let mut value: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+    value["config"]["allow_atomic"] = true.into();
+    value["config"]["consumer_limits"] =
+        serde_json::json!({"inactive_threshold": 5_000_000_000_i64});
+
+    let info: StreamInfo = serde_json::from_value(value.clone())?;
+    assert_eq!(info.config.extra.len(), 2);
+    assert_eq!(info.config.extra["allow_atomic"], true);
+
+    let recreated = serde_json::to_value(StreamConfig::from(&info))?;
+    assert_eq!(recreated["allow_atomic"], true);
+    assert_eq!(
+        recreated["consumer_limits"],
+        value["config"]["consumer_limits"]
+    );
+    assert!(recreated.get("extra").is_none());
+
+    let known = serde_json::to_value(StreamConfig::from("events"))?;
+    assert!(known.get("extra").is_none());
+    let parsed: StreamConfig = serde_json::from_value(known)?;
+    assert!(parsed.extra.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_config_unknown_fields() -> std::io::Result<()> {
+    let mut value: serde_json::Value = serde_json::from_str(CONSUMER_INFO)?;
+    value["config"]["backoff"] = serde_json::json!([1_000_000_000_i64]);
+
+    let info: ConsumerInfo = serde_json::from_value(value.clone())?;
+    assert_eq!(info.config.extra.len(), 1);
+
+    let config = serde_json::to_value(&info.config)?;
+    assert_eq!(config["backoff"], value["config"]["backoff"]);
+    assert!(config.get("extra").is_none());
+
+    let known = serde_json::to_value(ConsumerConfig::default())?;
+    assert!(known.get("extra").is_none());
+    assert!(known.get("backoff").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_config_from_json_strict() -> std::io::Result<()> {
+    let mut json =
+        serde_json::to_value(StreamConfig::from("events").max_bytes(1024))?;
+    let config = StreamConfig::from_json_strict(&json.to_string())?;
+    assert_eq!(config.name, "events");
+    assert_eq!(config.max_bytes, 1024);
+
+    json["max_msg_sizes"] = 512.into();
+    let err = StreamConfig::from_json_strict(&json.to_string()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("`max_msg_sizes`"), "{}", err);
+
+    assert_eq!(
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<ConfigError>()),
+        Some(&ConfigError::UnknownField("max_msg_sizes".to_string()))
+    );
+
+    let tolerant: StreamConfig = serde_json::from_value(json)?;
+    assert_eq!(tolerant.extra["max_msg_sizes"], 512);
+
+    let err = StreamConfig::from_json_strict(r#"{"name": 5}"#).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matches!(
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<ConfigError>()),
+        Some(ConfigError::Invalid(_))
+    ));
+
+    // typos are found in nested configurations too, and the first one
+    // in the input is reported
+    let nested = r#"{
+        "name": "events",
+        "max_bytes": -1,
+        "max_msgs": -1,
+        "max_msgs_per_subject": -1,
+        "discard": "old",
+        "retention": "limits",
+        "max_consumers": -1,
+        "max_age": 0,
+        "storage": "file",
+        "num_replicas": 1,
+        "sources": [
+            {"name": "orders"},
+            {"name": "returns", "filter_subjects": "returns.>"}
+        ],
+        "no_akc": true,
+        "republish": {"src": ">", "dest": "copies.>", "header_only": true}
+    }"#;
+    let err = StreamConfig::from_json_strict(nested).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(
+        err.to_string().contains("`sources.1.filter_subjects`"),
+        "{}",
+        err
+    );
+    let nested = nested.replace("\"filter_subjects\"", "\"filter_subject\"");
+    let err = StreamConfig::from_json_strict(&nested).unwrap_err();
+    assert!(err.to_string().contains("`no_akc`"), "{}", err);
+    let nested = nested.replace("\"no_akc\": true,", "");
+    let err = StreamConfig::from_json_strict(&nested).unwrap_err();
+    assert!(
+        err.to_string().contains("`republish.header_only`"),
+        "{}",
+        err
+    );
+    let nested = nested.replace("\"header_only\"", "\"headers_only\"");
+    assert_eq!(
+        StreamConfig::from_json_strict(&nested)?
+            .sources
+            .unwrap()
+            .len(),
+        2
+    );
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_canonical_json() -> std::io::Result<()> {
+    use std::time::Duration;
+
+    let mut a = StreamConfig::from("events")
+        .max_bytes(1024)
+        .max_age(Duration::from_secs(60))
+        .first_seq(10);
+    a.extra.insert("alpha".to_string(), true.into());
+    a.extra
+        .insert("zeta".to_string(), serde_json::json!({"b": 1, "a": 2}));
+    let mut b = StreamConfig::from("events")
+        .first_seq(10)
+        .max_age(Duration::from_secs(60))
+        .max_bytes(1024);
+    b.extra
+        .insert("zeta".to_string(), serde_json::json!({"a": 2, "b": 1}));
+    b.extra.insert("alpha".to_string(), true.into());
+
+    let json = a.to_canonical_json()?;
+    assert_eq!(json, b.to_canonical_json()?);
+    assert!(json.starts_with(r#"{"alpha":true,"#), "{}", json);
+    assert!(json.ends_with(r#""zeta":{"a":2,"b":1}}"#), "{}", json);
+    assert_eq!(serde_json::from_str::<StreamConfig>(&json)?, a);
+
+    let json = ConsumerConfig::default()
+        .rate_limit(100)
+        .deliver_from_seq(5)
+        .to_canonical_json()?;
+    assert_eq!(
+        json,
+        ConsumerConfig::default()
+            .deliver_from_seq(5)
+            .rate_limit(100)
+            .to_canonical_json()?
+    );
+    let position = |key: &str| json.find(&format!("\"{}\"", key));
+    assert!(position("ack_policy") < position("deliver_policy"));
+    assert!(position("deliver_policy") < position("opt_start_seq"));
+    assert!(position("opt_start_seq") < position("rate_limit"));
+
+    let too_long = StreamConfig::from("events")
+        .max_age(Duration::from_secs(u64::MAX))
+        .to_canonical_json()
+        .unwrap_err();
+    assert_eq!(too_long.kind(), std::io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_config_fingerprint() -> std::io::Result<()> {
+    let config = StreamConfig::from("events").max_bytes(1024).first_seq(10);
+    let fingerprint = config.fingerprint()?;
+    assert_eq!(fingerprint.len(), 64);
+    assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(
+        fingerprint,
+        StreamConfig::from("events")
+            .first_seq(10)
+            .max_bytes(1024)
+            .fingerprint()?
+    );
+
+    // the digest of the canonical JSON is stable across runs
+    assert_eq!(
+        StreamConfig::from("events").fingerprint()?,
+        "b725bf7082306daab147457328934103043d0bb9903b16fec75a9dfb1396999e"
+    );
+    assert_ne!(fingerprint, config.clone().max_bytes(2048).fingerprint()?);
+    assert_ne!(fingerprint, config.cloned_as("other").fingerprint()?);
+
+    let too_long = StreamConfig::from("events")
+        .max_age(std::time::Duration::MAX)
+        .fingerprint()
+        .unwrap_err();
+    assert_eq!(too_long.kind(), std::io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[test]
+fn jetstream_next_request_idle_heartbeat() -> std::io::Result<()> {
+    let req = NextRequest {
+        batch: 10,
+        expires: 120_000_000_000,
+        idle_heartbeat: Some(Duration::from_secs(5)),
+        ..Default::default()
+    };
+    req.validate()?;
+    let json = serde_json::to_value(&req)?;
+    assert_eq!(json["idle_heartbeat"], 5_000_000_000_u64);
+    assert_eq!(serde_json::from_value::<NextRequest>(json)?, req);
+
+    let plain = serde_json::to_value(NextRequest {
+        batch: 1,
+        ..Default::default()
+    })?;
+    assert!(plain.get("idle_heartbeat").is_none());
+
+    for expires in &[5_000_000_000, 1_000_000_000, 0] {
+        let err = NextRequest {
+            expires: *expires,
+            ..req.clone()
+        }
+        .validate()
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_info_ack_pending_saturation() {
+    let info = |num_ack_pending, max_ack_pending| ConsumerInfo {
+        num_ack_pending,
+        config: ConsumerConfig {
+            max_ack_pending,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(info(1000, 1000).is_ack_pending_saturated());
+    assert!(info(1001, 1000).is_ack_pending_saturated());
+    assert!(!info(999, 1000).is_ack_pending_saturated());
+    assert!(!info(0, 1).is_ack_pending_saturated());
+
+    assert!(!info(1_000_000, -1).is_ack_pending_saturated());
+    assert!(!info(1_000_000, 0).is_ack_pending_saturated());
+}
+
+#[test]
+fn jetstream_stream_info_utilization() {
+    use std::time::{Duration, SystemTime};
+
+    let now = SystemTime::now();
+    let mut info = StreamInfo {
+        config: StreamConfig {
+            max_bytes: 1000,
+            max_msgs: 40,
+            ..StreamConfig::from("events")
+        }
+        .max_age(Duration::from_secs(3600)),
+        state: StreamState {
+            messages: 10,
+            bytes: 250,
+            first_ts: Some((now - Duration::from_secs(900)).into()),
+            last_ts: Some(now.into()),
+            ..Default::default()
+        },
+        ts: Some(now.into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        info.utilization(),
+        Utilization {
+            bytes_pct: Some(25.0),
+            msgs_pct: Some(25.0),
+            age_pct: Some(25.0),
+        }
+    );
+
+    info.state.messages = 0;
+    info.state.bytes = 0;
+    assert_eq!(info.utilization().age_pct, Some(0.0));
+    assert_eq!(info.utilization().bytes_pct, Some(0.0));
+
+    info.config = StreamConfig::limits();
+    assert_eq!(info.utilization(), Utilization::default());
+    info.config.max_bytes = 0;
+    assert_eq!(info.utilization().bytes_pct, None);
+}
+
+#[test]
+fn jetstream_success_response() -> std::io::Result<()> {
+    let ok: ApiResponse<SuccessResponse> = serde_json::from_str(
+        r#"{"type":"io.nats.jetstream.api.v1.stream_delete_response","success":true}"#,
+    )?;
+    assert_eq!(ok, ApiResponse::Ok(SuccessResponse { success: true }));
+
+    let err: ApiResponse<SuccessResponse> = serde_json::from_str(
+        r#"{
+            "type": "io.nats.jetstream.api.v1.consumer_delete_response",
+            "error": {"code": 404, "err_code": 10014, "description": "consumer not found"}
+        }"#,
+    )?;
+    match err {
+        ApiResponse::Err { r#type, error } => {
+            assert_eq!(
+                r#type,
+                "io.nats.jetstream.api.v1.consumer_delete_response"
+            );
+            assert_eq!(error.code, 404);
+            assert_eq!(
+                error.description.as_deref(),
+                Some("consumer not found")
+            );
+        }
+        ApiResponse::Ok(res) => panic!("expected an error, got {:?}", res),
+    }
+
+    let purged: ApiResponse<SuccessResponse> = serde_json::from_str(
+        r#"{"type":"io.nats.jetstream.api.v1.stream_purge_response","success":true,"purged":3}"#,
+    )?;
+    assert_eq!(purged, ApiResponse::Ok(SuccessResponse { success: true }));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_field_aliases() -> std::io::Result<()> {
+    let mut stream: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+    let config = stream["config"].as_object_mut().unwrap();
+    config.remove("num_replicas");
+    config.insert("replicas".to_string(), 3.into());
+    let info: StreamInfo = serde_json::from_value(stream)?;
+    assert_eq!(info.config.num_replicas, 3);
+    assert!(info.config.extra.is_empty());
+
+    let mut consumer: serde_json::Value = serde_json::from_str(CONSUMER_INFO)?;
+    consumer["config"]["rate_limit_bps"] = 1_048_576.into();
+    let info: ConsumerInfo = serde_json::from_value(consumer)?;
+    assert_eq!(info.config.rate_limit, 1_048_576);
+    assert!(info.config.extra.is_empty());
+
+    let json = serde_json::to_value(&info.config)?;
+    assert_eq!(json["rate_limit"], 1_048_576);
+    assert!(json.get("rate_limit_bps").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_account_info_domain_and_level() -> std::io::Result<()> {
+    let account: AccountInfo = serde_json::from_str(
+        r#"{
+            "type": "io.nats.jetstream.api.v1.account_info_response",
+            "memory": 0,
+            "storage": 1024,
+            "streams": 1,
+            "consumers": 2,
+            "domain": "hub",
+            "api": {"level": 1, "total": 10, "errors": 0},
+            "limits": {
+                "max_memory": -1,
+                "max_storage": -1,
+                "max_streams": -1,
+                "max_consumers": -1
+            }
+        }"#,
+    )?;
+    assert_eq!(account.domain.as_deref(), Some("hub"));
+    assert_eq!(account.api.level, Some(1));
+    assert_eq!(account.api.total, 10);
+
+    let older: ApiStats =
+        serde_json::from_str(r#"{"total": 10, "errors": 0}"#)?;
+    assert_eq!(older.level, None);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_action() -> std::io::Result<()> {
+    use std::convert::TryFrom;
+
+    let durable = ConsumerConfig {
+        durable_name: Some("worker".to_string()),
+        ..Default::default()
+    };
+    let create = CreateConsumerRequest {
+        stream_name: StreamName::try_from("events")?,
+        config: durable.clone(),
+        action: ConsumerAction::Create,
+    };
+    create.validate()?;
+    create.validate_action(None)?;
+    let json = serde_json::to_value(&create)?;
+    assert_eq!(json["action"], "create");
+
+    let existing = ConsumerInfo {
+        name: "worker".to_string(),
+        config: durable.clone(),
+        ..Default::default()
+    };
+    create.validate_action(Some(&existing))?;
+    // the server reports its defaults for the fields that were left out
+    let mut defaulted = ConsumerInfo {
+        config: ConsumerConfig {
+            ack_wait: Duration::from_secs(30),
+            max_deliver: Some(-1),
+            max_ack_pending: ConsumerConfig::DEFAULT_MAX_ACK_PENDING,
+            max_waiting: ConsumerConfig::DEFAULT_MAX_WAITING,
+            ..durable.clone()
+        },
+        ..existing.clone()
+    };
+    defaulted
+        .config
+        .metadata
+        .insert("_nats.req.level".to_string(), "0".to_string());
+    defaulted.config.extra.insert(
+        "inactive_threshold".to_string(),
+        serde_json::json!(5_000_000_000u64),
+    );
+    assert_ne!(defaulted.config, durable);
+    create.validate_action(Some(&defaulted))?;
+    let changed = ConsumerInfo {
+        config: durable.clone().rate_limit(1024),
+        ..existing.clone()
+    };
+    let err = create.validate_action(Some(&changed)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+    let update = CreateConsumerRequest {
+        action: ConsumerAction::Update,
+        ..create.clone()
+    };
+    update.validate()?;
+    update.validate_action(Some(&changed))?;
+    let err = update.validate_action(None).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    let unnamed = CreateConsumerRequest {
+        config: ConsumerConfig::default(),
+        ..update
+    };
+    let err = unnamed.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let default = serde_json::to_value(CreateConsumerRequest::default())?;
+    assert!(default.get("action").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_pause_until() -> std::io::Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    let until = SystemTime::now() + Duration::from_secs(3600);
+    let paused = ConsumerConfig {
+        durable_name: Some("worker".to_string()),
+        pause_until: Some(until.into()),
+        ..Default::default()
+    };
+    assert!(paused.is_paused());
+
+    let json = serde_json::to_value(&paused)?;
+    assert!(json["pause_until"].is_string());
+    assert_eq!(serde_json::from_value::<ConsumerConfig>(json)?, paused);
+
+    let past = ConsumerConfig {
+        pause_until: Some((SystemTime::now() - Duration::from_secs(60)).into()),
+        ..paused.clone()
+    };
+    assert!(!past.is_paused());
+
+    let running = serde_json::to_value(ConsumerConfig::default())?;
+    assert!(running.get("pause_until").is_none());
+    assert!(!ConsumerConfig::default().is_paused());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_priority_groups() -> std::io::Result<()> {
+    let config = ConsumerConfig {
+        durable_name: Some("worker".to_string()),
+        priority_groups: Some(vec!["jobs".to_string()]),
+        priority_policy: Some(PriorityPolicy::PinnedClient),
+        ..Default::default()
+    };
+    config.validate()?;
+
+    let json = serde_json::to_value(&config)?;
+    assert_eq!(json["priority_groups"], serde_json::json!(["jobs"]));
+    assert_eq!(json["priority_policy"], "pinned_client");
+    assert_eq!(serde_json::from_value::<ConsumerConfig>(json)?, config);
+
+    let overflow: ConsumerConfig = serde_json::from_str(
+        r#"{
+            "deliver_policy": "all",
+            "ack_policy": "explicit",
+            "replay_policy": "instant",
+            "priority_groups": ["jobs"],
+            "priority_policy": "overflow"
+        }"#,
+    )?;
+    assert_eq!(overflow.priority_policy, Some(PriorityPolicy::Overflow));
+
+    let without_policy = ConsumerConfig {
+        priority_policy: None,
+        ..config.clone()
+    };
+    let err = without_policy.validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let none_policy = ConsumerConfig {
+        priority_policy: Some(PriorityPolicy::None),
+        ..config.clone()
+    };
+    assert!(none_policy.validate().is_err());
+    let without_groups = ConsumerConfig {
+        priority_groups: Some(vec![]),
+        ..config
+    };
+    assert!(without_groups.validate().is_err());
+
+    let plain = serde_json::to_value(ConsumerConfig::default())?;
+    assert!(plain.get("priority_groups").is_none());
+    assert!(plain.get("priority_policy").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_next_request_priority_group() -> std::io::Result<()> {
+    let groups = vec!["jobs".to_string(), "reports".to_string()];
+    let req = NextRequest {
+        batch: 10,
+        group: Some("jobs".to_string()),
+        min_pending: Some(100),
+        ..Default::default()
+    };
+    req.validate_group(&groups)?;
+
+    let json = serde_json::to_value(&req)?;
+    assert_eq!(json["group"], "jobs");
+    assert_eq!(json["min_pending"], 100);
+    assert!(json.get("min_ack_pending").is_none());
+    assert_eq!(serde_json::from_value::<NextRequest>(json)?, req);
+
+    let plain = serde_json::to_value(NextRequest {
+        batch: 1,
+        ..Default::default()
+    })?;
+    assert_eq!(plain, serde_json::json!({"batch": 1}));
+
+    let other = NextRequest {
+        group: Some("other".to_string()),
+        ..req.clone()
+    };
+    let err = other.validate_group(&groups).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let ungrouped = NextRequest { group: None, ..req };
+    assert!(ungrouped.validate_group(&groups).is_err());
+    assert!(ungrouped.validate_group(&[]).is_err());
+    NextRequest::default().validate_group(&[])?;
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_client_metadata() {
+    let mut stream = StreamConfig::from("events");
+    stream
+        .metadata
+        .insert("team".to_string(), "billing".to_string());
+    let tagged = stream.clone().with_client_metadata();
+    assert_eq!(tagged.metadata["team"], "billing");
+    assert_eq!(tagged.metadata[CLIENT_METADATA_KEY], CLIENT_METADATA_VALUE);
+    assert!(CLIENT_METADATA_VALUE.starts_with("nats/"));
+    assert_eq!(tagged.metadata.len(), 2);
+
+    let mut consumer = ConsumerConfig::default();
+    consumer
+        .metadata
+        .insert(CLIENT_METADATA_KEY.to_string(), "my-app/1.0".to_string());
+    let tagged = consumer.with_client_metadata();
+    assert_eq!(tagged.metadata[CLIENT_METADATA_KEY], "my-app/1.0");
+    assert_eq!(tagged.metadata.len(), 1);
+
+    let tagged = ConsumerConfig::default().with_client_metadata();
+    assert_eq!(tagged.metadata[CLIENT_METADATA_KEY], CLIENT_METADATA_VALUE);
+}
+
+#[test]
+fn jetstream_consumer_config_overrides() {
+    let base = ConsumerConfig {
+        durable_name: Some("template".to_string()),
+        deliver_subject: Some("deliver.template".to_string()),
+        filter_subject: "orders.>".to_string(),
+        ack_policy: AckPolicy::Explicit,
+        ..Default::default()
+    };
+    let original = base.clone();
+
+    let eu = base.with_filter_subject("orders.eu.>");
+    assert_eq!(eu.filter_subject, "orders.eu.>");
+    assert_eq!(eu.durable_name, None);
+    assert_eq!(eu.deliver_subject, base.deliver_subject);
+    assert_eq!(eu.ack_policy, AckPolicy::Explicit);
+
+    let worker = base.with_deliver_subject("deliver.worker.1");
+    assert_eq!(worker.deliver_subject.as_deref(), Some("deliver.worker.1"));
+    assert_eq!(worker.durable_name, None);
+    assert_eq!(worker.filter_subject, "orders.>");
+
+    assert_eq!(base, original);
+}
+
+#[test]
+fn jetstream_stream_info_consumer_count_matches() -> std::io::Result<()> {
+    let info: StreamInfo = serde_json::from_str(STREAM_INFO)?;
+    let count = info.state.consumer_count;
+    assert!(info.consumer_count_matches(count));
+    assert!(!info.consumer_count_matches(count + 1));
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_and_consumer_names() -> std::io::Result<()> {
+    use std::convert::TryFrom;
+
+    let stream = StreamName::try_from("ORDERS_v2-eu")?;
+    assert_eq!(stream, "ORDERS_v2-eu");
+    assert_eq!(stream.to_string(), "ORDERS_v2-eu");
+    assert_eq!(serde_json::to_value(&stream)?, "ORDERS_v2-eu");
+    let consumer: ConsumerName = "worker-1".parse()?;
+    assert_eq!(consumer.as_str(), "worker-1");
+
+    for name in &[
+        "",
+        "orders.eu",
+        "orders*",
+        "orders>",
+        "my orders",
+        "a\tb",
+        "a/b",
+    ] {
+        let err = StreamName::try_from(*name).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{:?}", name);
+        assert!(ConsumerName::try_from(*name).is_err(), "{:?}", name);
+    }
+
+    let err = StreamConfig::from("orders.eu").validate().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let durable = ConsumerConfig {
+        durable_name: Some("worker 1".to_string()),
+        ..Default::default()
+    };
+    assert!(durable.validate().is_err());
+
+    let req: CreateConsumerRequest = serde_json::from_str(
+        r#"{"stream_name": "orders", "config": {
+            "deliver_policy": "all",
+            "ack_policy": "explicit",
+            "replay_policy": "instant"
+        }}"#,
+    )?;
+    assert_eq!(req.stream_name, "orders");
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_duplicate_window_within_max_age() -> std::io::Result<()> {
+    let config = StreamConfig {
+        duplicate_window: Duration::from_secs(120),
+        ..StreamConfig::from("events")
+    };
+    config.validate()?;
+    config
+        .clone()
+        .max_age(Duration::from_secs(120))
+        .validate()?;
+    config
+        .clone()
+        .max_age(Duration::from_secs(3600))
+        .validate()?;
+
+    let err = config
+        .max_age(Duration::from_secs(60))
+        .validate()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(
+        err.to_string().contains("duplicate_window of 2m"),
+        "{}",
+        err
+    );
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_info_sources() -> std::io::Result<()> {
+    let mut value: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+    value["sources"] = serde_json::json!([
+        {"name": "ORDERS_EU", "lag": 0, "active": 250_000_000_i64},
+        {
+            "name": "ORDERS_US",
+            "lag": 42,
+            "active": -1,
+            "external": {"api": "$JS.us.API"},
+            "error": {"code": 503, "description": "stream is offline"}
+        }
+    ]);
+
+    let info: StreamInfo = serde_json::from_value(value)?;
+    assert!(info.mirror.is_none());
+    let sources = info.sources.expect("sources");
+    assert_eq!(sources.len(), 2);
+    assert_eq!(sources[0].name, "ORDERS_EU");
+    assert_eq!(sources[0].active, Some(Duration::from_millis(250)));
+    assert!(sources[0].error.is_none());
+
+    assert_eq!(sources[1].lag, 42);
+    assert_eq!(sources[1].active, None);
+    assert_eq!(
+        sources[1]
+            .external
+            .as_ref()
+            .map(|external| external.api.as_str()),
+        Some("$JS.us.API")
+    );
+    assert_eq!(sources[1].error.as_ref().map(|error| error.code), Some(503));
+
+    let mirrored: StreamInfo = serde_json::from_value({
+        let mut value: serde_json::Value = serde_json::from_str(STREAM_INFO)?;
+        value["mirror"] =
+            serde_json::json!({"name": "ORDERS", "lag": 3, "active": 0});
+        value
+    })?;
+    assert_eq!(mirrored.mirror.map(|mirror| mirror.lag), Some(3));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_api_response_into_result() -> std::io::Result<()> {
+    let ok: ApiResponse<PubAck> =
+        serde_json::from_str(r#"{"stream": "events", "seq": 7}"#)?;
+    let ack = ok.into_result().map_err(std::io::Error::from)?;
+    assert_eq!(ack.seq, 7);
+
+    let err: ApiResponse<StreamInfo> = serde_json::from_str(
+        r#"{
+            "type": "io.nats.jetstream.api.v1.stream_info_response",
+            "error": {"code": 404, "description": "stream not found"}
+        }"#,
+    )?;
+    let result: Result<StreamInfo, ApiError> = err.clone().into();
+    let error = result.unwrap_err();
+    assert_eq!(error.code, 404);
+    assert_eq!(error.to_string(), "stream not found");
+
+    let io_error = std::io::Error::from(err.into_result().unwrap_err());
+    assert_eq!(io_error.kind(), std::io::ErrorKind::Other);
+    assert_eq!(io_error.to_string(), "stream not found");
+
+    assert_eq!(ApiError::default().to_string(), "unknown");
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_create_consumers() -> std::io::Result<()> {
+    use std::convert::TryFrom;
+
+    let stream = StreamName::try_from("orders")?;
+    let configs = vec![
+        ConsumerConfig::durable_pull("first"),
+        ConsumerConfig::durable_pull("second"),
+        ConsumerConfig::durable_pull("third"),
+    ];
+
+    // a mock server that fails to create the second consumer
+    let mut requests = vec![];
+    let results = create_consumers(&stream, configs.clone(), |req| {
+        requests.push(req.clone());
+        let name = req.config.durable_name.clone().unwrap();
+        if name == "second" {
+            return Err(ApiError {
+                code: 400,
+                err_code: Some(10013),
+                description: Some("consumer name already in use".to_string()),
+            });
+        }
+        Ok(ConsumerInfo {
+            stream_name: req.stream_name.to_string(),
+            name,
+            config: req.config.clone(),
+            ..Default::default()
+        })
+    });
+
+    assert_eq!(requests.len(), 3);
+    assert!(requests.iter().all(|req| req.stream_name == "orders"));
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().name, "first");
+    assert_eq!(results[1].as_ref().unwrap_err().err_code, Some(10013));
+    assert_eq!(results[2].as_ref().unwrap().name, "third");
+
+    // a missing stream fails every consumer after a single request
+    let not_found = ApiError {
+        code: 404,
+        err_code: Some(ApiError::STREAM_NOT_FOUND),
+        description: Some("stream not found".to_string()),
+    };
+    assert!(not_found.is_stream_not_found());
+    let mut calls = 0;
+    let results = create_consumers(&stream, configs, |_| {
+        calls += 1;
+        Err(not_found.clone())
+    });
+    assert_eq!(calls, 1);
+    assert_eq!(results, vec![Err(not_found.clone()); 3]);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_reconcile() -> std::io::Result<()> {
+    let desired = StreamConfig {
+        subjects: Some(vec!["orders.>".to_string()]),
+        ..StreamConfig::from("orders")
+    };
+    assert_eq!(desired.reconcile(None)?, ReconcileAction::Created);
+
+    // the configuration the server reports, with its defaults filled in
+    let reported = StreamConfig {
+        max_msg_size: -1,
+        num_replicas: 1,
+        duplicate_window: Duration::from_secs(120),
+        ..desired.clone().with_client_metadata()
+    };
+    assert!(desired.semantically_eq(&reported));
+    assert_eq!(
+        desired.reconcile(Some(&reported))?,
+        ReconcileAction::Unchanged
+    );
+
+    let smaller = StreamConfig {
+        max_bytes: 1024,
+        ..reported.clone()
+    };
+    assert!(!desired.semantically_eq(&smaller));
+    assert!(desired.diff(&reported).is_empty());
+    assert_eq!(desired.diff(&smaller), vec![("max_bytes", false)]);
+    assert_eq!(desired.reconcile(Some(&smaller))?, ReconcileAction::Updated);
+
+    let memory = StreamConfig {
+        storage: StorageType::Memory,
+        retention: RetentionPolicy::WorkQueue,
+        ..reported.clone()
+    };
+    let err = desired.reconcile(Some(&memory)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let conflict = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<ImmutableConflict>())
+        .unwrap();
+    assert_eq!(conflict.kind, "stream");
+    assert_eq!(conflict.name, "orders");
+    assert_eq!(conflict.fields, vec!["storage", "retention"]);
+    assert_eq!(
+        desired.diff(&memory),
+        vec![("retention", true), ("storage", true)]
+    );
+    let interest = StreamConfig {
+        retention: RetentionPolicy::Interest,
+        num_replicas: 3,
+        ..reported.clone()
+    };
+    assert_eq!(
+        desired.diff(&interest),
+        vec![("retention", false), ("num_replicas", false)]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_reconcile() -> std::io::Result<()> {
+    let desired = ConsumerConfig {
+        filter_subject: "orders.received".to_string(),
+        ..ConsumerConfig::durable_pull("processor")
+    };
+    assert_eq!(desired.reconcile(None)?, ReconcileAction::Created);
+
+    // the configuration the server reports, with its defaults filled in
+    let reported = ConsumerConfig {
+        ack_wait: Duration::from_secs(30),
+        max_deliver: Some(-1),
+        num_replicas: 3,
+        ..desired.clone().with_client_metadata()
+    };
+    assert!(desired.semantically_eq(&reported));
+    assert!(desired.diff(&reported).is_empty());
+    assert!(desired.diff(&desired).is_empty());
+    assert_eq!(
+        desired.reconcile(Some(&reported))?,
+        ReconcileAction::Unchanged
+    );
+
+    let slower = ConsumerConfig {
+        ack_wait: Duration::from_secs(60),
+        ..reported.clone()
+    };
+    assert_eq!(desired.diff(&slower), vec!["ack_wait"]);
+    assert_eq!(desired.reconcile(Some(&slower))?, ReconcileAction::Updated);
+
+    let other_filter = ConsumerConfig {
+        filter_subject: "orders.shipped".to_string(),
+        deliver_policy: DeliverPolicy::New,
+        ..reported
+    };
+    let err = desired.reconcile(Some(&other_filter)).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let conflict = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<ImmutableConflict>())
+        .unwrap();
+    assert_eq!(conflict.kind, "consumer");
+    assert_eq!(conflict.name, "processor");
+    assert_eq!(conflict.fields, vec!["deliver_policy", "filter_subject"]);
+    assert_eq!(
+        desired.diff(&other_filter),
+        vec!["deliver_policy", "filter_subject"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_deliver_policy_builders() -> std::io::Result<()> {
+    use std::time::SystemTime;
+
+    let by_seq = ConsumerConfig::default().deliver_from_seq(42);
+    let by_time =
+        ConsumerConfig::default().deliver_from_time(SystemTime::now());
+    assert_eq!(by_seq.deliver_policy, DeliverPolicy::ByStartSeq);
+    assert_eq!(by_seq.opt_start_seq, 42);
+    assert_eq!(by_time.deliver_policy, DeliverPolicy::ByStartTime);
+    assert!(by_time.opt_start_time.is_some());
+
+    for (config, policy) in &[
+        (by_time.clone().deliver_all(), DeliverPolicy::All),
+        (by_seq.clone().deliver_last(), DeliverPolicy::Last),
+        (by_time.clone().deliver_new(), DeliverPolicy::New),
+        (
+            by_seq.clone().deliver_last_per_subject(),
+            DeliverPolicy::LastPerSubject,
+        ),
+    ] {
+        assert_eq!(config.deliver_policy, *policy);
+        assert_eq!(config.opt_start_seq, 0);
+        assert_eq!(config.opt_start_time, None);
+    }
+
+    let switched = by_time.deliver_from_seq(7);
+    assert_eq!(switched.opt_start_time, None);
+    assert_eq!(by_seq.deliver_from_time(SystemTime::now()).opt_start_seq, 0);
+
+    // every builder leaves only the start position of its own policy, even
+    // when starting from a configuration that has both
+    let start = DateTime::from(SystemTime::now());
+    let both = ConsumerConfig {
+        opt_start_seq: 42,
+        opt_start_time: Some(start),
+        ..ConsumerConfig::durable_pull("replay")
+    };
+    type Builder = Box<dyn Fn(ConsumerConfig) -> ConsumerConfig>;
+    let builders: Vec<(Builder, _, _, _)> = vec![
+        (
+            Box::new(ConsumerConfig::deliver_all),
+            DeliverPolicy::All,
+            0,
+            None,
+        ),
+        (
+            Box::new(ConsumerConfig::deliver_last),
+            DeliverPolicy::Last,
+            0,
+            None,
+        ),
+        (
+            Box::new(ConsumerConfig::deliver_new),
+            DeliverPolicy::New,
+            0,
+            None,
+        ),
+        (
+            Box::new(ConsumerConfig::deliver_last_per_subject),
+            DeliverPolicy::LastPerSubject,
+            0,
+            None,
+        ),
+        (
+            Box::new(|config: ConsumerConfig| config.deliver_by_start_seq(7)),
+            DeliverPolicy::ByStartSeq,
+            7,
+            None,
+        ),
+        (
+            Box::new(move |config: ConsumerConfig| {
+                config.deliver_by_start_time(start)
+            }),
+            DeliverPolicy::ByStartTime,
+            0,
+            Some(start),
+        ),
+    ];
+    for (build, policy, opt_start_seq, opt_start_time) in builders {
+        let config = build(both.clone());
+        assert_eq!(config.deliver_policy, policy);
+        assert_eq!(config.opt_start_seq, opt_start_seq, "{:?}", policy);
+        assert_eq!(config.opt_start_time, opt_start_time, "{:?}", policy);
+        assert_eq!(config.durable_name.as_deref(), Some("replay"));
+    }
+
+    let json = serde_json::to_value(
+        ConsumerConfig::default().deliver_last_per_subject(),
+    )?;
+    assert_eq!(json["deliver_policy"], "last_per_subject");
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_next_request_fetch_bytes() -> std::io::Result<()> {
+    let req = NextRequest::fetch_bytes(100, 1 << 20);
+    req.validate()?;
+    assert_eq!(
+        serde_json::to_value(&req)?,
+        serde_json::json!({"batch": 100, "no_wait": true, "max_bytes": 1048576})
+    );
+    assert_eq!(
+        serde_json::from_str::<NextRequest>(
+            r#"{"batch": 100, "no_wait": true, "max_bytes": 1048576}"#
+        )?,
+        req
+    );
+    Ok(())
+}
+
+#[test]
+fn jetstream_duration_conversions() -> std::io::Result<()> {
+    let std_duration = std::time::Duration::new(30, 500);
+    let duration = Duration::from(std_duration);
+    assert_eq!(std::time::Duration::from(duration), std_duration);
+    assert_eq!(duration.as_nanos(), std_duration.as_nanos());
+    assert_eq!(Duration::from_secs(2), Duration::from_millis(2000));
+    assert_eq!(
+        Duration::from_secs(1) + Duration::from_millis(500),
+        Duration::from_millis(1500)
+    );
+    assert_eq!(
+        Duration::from_secs(1) - Duration::from_millis(250),
+        Duration::from_millis(750)
+    );
+    assert_eq!(
+        Duration::from_secs(1).checked_sub(Duration::from_secs(2)),
+        None
+    );
+
+    assert_eq!(serde_json::to_string(&duration)?, "30000000500");
+    assert_eq!(serde_json::from_str::<Duration>("30000000500")?, duration);
+    assert!(serde_json::from_str::<Duration>("-1").is_err());
+
+    let max = Duration::from_nanos(i64::MAX as u64);
+    assert_eq!(serde_json::to_string(&max)?, i64::MAX.to_string());
+    let too_long =
+        Duration::from(std::time::Duration::from_secs(300 * 365 * 24 * 3600));
+    assert!(serde_json::to_string(&too_long).is_err());
+    assert!(serde_json::to_string(&(max + Duration::from_nanos(1))).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_discard_new_per_subject() -> std::io::Result<()> {
+    let config = StreamConfig {
+        discard: DiscardPolicy::New,
+        max_msgs_per_subject: 10,
+        discard_new_per_subject: Some(true),
+        ..StreamConfig::from("events")
+    };
+    config.validate()?;
+    let json = serde_json::to_value(&config)?;
+    assert_eq!(json["discard_new_per_subject"], true);
+    assert_eq!(serde_json::from_value::<StreamConfig>(json)?, config);
+    assert_eq!(config.min_server_version().to_string(), "2.10.0");
+    let old = config.downgrade_to("2.9.0".parse().unwrap());
+    assert_eq!(old.discard_new_per_subject, None);
+
+    let unset = serde_json::to_value(StreamConfig::from("events"))?;
+    assert!(unset.get("discard_new_per_subject").is_none());
+
+    for invalid in &[
+        StreamConfig {
+            discard: DiscardPolicy::Old,
+            ..config.clone()
+        },
+        StreamConfig {
+            max_msgs_per_subject: -1,
+            ..config.clone()
+        },
+    ] {
+        let err = invalid.validate().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+    StreamConfig {
+        discard_new_per_subject: Some(false),
+        ..StreamConfig::from("events")
+    }
+    .validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_advisories() -> std::io::Result<()> {
+    let max_deliver = nats::Message::new(
+        "$JS.EVENT.ADVISORY.CONSUMER.MAX_DELIVERIES.orders.processor",
+        None,
+        r#"{
+            "type": "io.nats.jetstream.advisory.v1.max_deliver",
+            "id": "bdVEMvexHkUT6ZOffLqAUR",
+            "timestamp": "2021-07-01T12:00:00.5Z",
+            "stream": "orders",
+            "consumer": "processor",
+            "stream_seq": 42,
+            "deliveries": 5
+        }"#,
+        None,
+    );
+    match Advisory::from_message(&max_deliver)? {
+        Advisory::MaxDeliver(advisory) => {
+            assert_eq!(advisory.stream, "orders");
+            assert_eq!(advisory.consumer, "processor");
+            assert_eq!(advisory.stream_seq, 42);
+            assert_eq!(advisory.deliveries, 5);
+            assert_eq!(advisory.domain, None);
+        }
+        other => panic!("unexpected advisory {:?}", other),
+    }
+    assert!(Advisory::from_message(&max_deliver)?.is_poison_message());
+
+    let terminated: Advisory = serde_json::from_str(
+        r#"{
+            "type": "io.nats.jetstream.advisory.v1.terminated",
+            "id": "bdVEMvexHkUT6ZOffLqAUS",
+            "timestamp": "2021-07-01T12:00:01Z",
+            "stream": "orders",
+            "consumer": "processor",
+            "consumer_seq": 7,
+            "stream_seq": 43,
+            "deliveries": 1,
+            "domain": "hub"
+        }"#,
+    )?;
+    match &terminated {
+        Advisory::Terminated(advisory) => {
+            assert_eq!(advisory.consumer_seq, 7);
+            assert_eq!(advisory.stream_seq, 43);
+            assert_eq!(advisory.deliveries, 1);
+            assert_eq!(advisory.reason, None);
+            assert_eq!(advisory.domain.as_deref(), Some("hub"));
+        }
+        other => panic!("unexpected advisory {:?}", other),
+    }
+    assert!(terminated.is_poison_message());
+
+    let with_reason: Advisory = serde_json::from_str(
+        r#"{
+            "type": "io.nats.jetstream.advisory.v1.terminated",
+            "id": "bdVEMvexHkUT6ZOffLqAUU",
+            "timestamp": "2021-07-01T12:00:01Z",
+            "stream": "orders",
+            "consumer": "processor",
+            "consumer_seq": 8,
+            "stream_seq": 44,
+            "deliveries": 3,
+            "reason": "malformed order"
+        }"#,
+    )?;
+    assert!(with_reason.is_poison_message());
+    match &with_reason {
+        Advisory::Terminated(advisory) => {
+            assert_eq!(advisory.stream, "orders");
+            assert_eq!(advisory.consumer, "processor");
+            assert_eq!(advisory.stream_seq, 44);
+            assert_eq!(advisory.deliveries, 3);
+            assert_eq!(advisory.reason.as_deref(), Some("malformed order"));
+        }
+        other => panic!("unexpected advisory {:?}", other),
+    }
+    assert_eq!(
+        serde_json::to_value(&with_reason)?["reason"],
+        "malformed order"
+    );
+
+    let created: Advisory = serde_json::from_str(
+        r#"{
+            "type": "io.nats.jetstream.advisory.v1.stream_action",
+            "id": "bdVEMvexHkUT6ZOffLqAUT",
+            "timestamp": "2021-07-01T12:00:02Z",
+            "stream": "orders",
+            "action": "create"
+        }"#,
+    )?;
+    assert!(!created.is_poison_message());
+    assert!(matches!(
+        created,
+        Advisory::StreamAction(StreamActionAdvisory {
+            action: AdvisoryAction::Create,
+            ..
+        })
+    ));
+
+    let unknown: Advisory = serde_json::from_str(
+        r#"{"type": "io.nats.jetstream.advisory.v1.api_audit", "id": "x"}"#,
+    )?;
+    assert_eq!(unknown, Advisory::Unknown);
+    let invalid = nats::Message::new("$JS.EVENT.ADVISORY.API", None, "{", None);
+    assert!(Advisory::from_message(&invalid).is_err());
+
+    assert_eq!(
+        Advisory::max_deliver_subject("orders", "*"),
+        "$JS.EVENT.ADVISORY.CONSUMER.MAX_DELIVERIES.orders.*"
+    );
+    assert_eq!(
+        Advisory::terminated_subject("orders", "processor"),
+        "$JS.EVENT.ADVISORY.CONSUMER.MSG_TERMINATED.orders.processor"
+    );
+    assert_eq!(
+        Advisory::consumer_action_subject(
+            Some(AdvisoryAction::Delete),
+            "orders",
+            "*"
+        ),
+        "$JS.EVENT.ADVISORY.CONSUMER.DELETED.orders.*"
+    );
+    assert_eq!(
+        Advisory::stream_action_subject(None, "*"),
+        "$JS.EVENT.ADVISORY.STREAM.*.*"
+    );
+    assert_eq!(
+        Advisory::server_out_of_storage_subject(),
+        "$JS.EVENT.ADVISORY.SERVER.OUT_OF_STORAGE"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_ack_metric() -> std::io::Result<()> {
+    let msg = nats::Message::new(
+        &AckMetric::subject("ORDERS", "NEW"),
+        None,
+        r#"{
+            "type": "io.nats.jetstream.metric.v1.consumer_ack",
+            "id": "k9dHC1jyC7Yjvjm3Ag5e3K",
+            "timestamp": "2021-07-01T12:00:00.123456789Z",
+            "stream": "ORDERS",
+            "consumer": "NEW",
+            "consumer_seq": 12,
+            "stream_seq": 130,
+            "ack_time": 1548441,
+            "deliveries": 2
+        }"#,
+        None,
+    );
+    assert_eq!(msg.subject, "$JS.EVENT.METRIC.CONSUMER.ACK.ORDERS.NEW");
+
+    let metric = AckMetric::from_message(&msg)?;
+    assert_eq!(metric.stream, "ORDERS");
+    assert_eq!(metric.consumer, "NEW");
+    assert_eq!(metric.consumer_seq, 12);
+    assert_eq!(metric.stream_seq, 130);
+    assert_eq!(metric.delay, Duration::from_nanos(1_548_441));
+    assert_eq!(metric.deliveries, 2);
+
+    let json = serde_json::to_value(&metric)?;
+    assert_eq!(json["ack_time"], 1_548_441);
+
+    // a negative delay is invalid rather than silently zero
+    let mut negative = json;
+    negative["ack_time"] = (-1).into();
+    assert!(serde_json::from_value::<AckMetric>(negative).is_err());
+
+    let advisory = nats::Message::new(
+        "$JS.EVENT.ADVISORY.CONSUMER.MAX_DELIVERIES.ORDERS.NEW",
+        None,
+        r#"{"type": "io.nats.jetstream.advisory.v1.max_deliver"}"#,
+        None,
+    );
+    let err = AckMetric::from_message(&advisory).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_no_responders() -> std::io::Result<()> {
+    let subject = "$JS.API.STREAM.INFO.orders";
+
+    let err =
+        ApiResponse::<StreamInfo>::from_reply(subject, &status_reply("503")?)
+            .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    let no_responders = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<NoResponders>())
+        .unwrap();
+    assert_eq!(no_responders.subject, subject);
+    assert!(status_reply("503")?.is_no_responders());
+    assert!(!status_reply("404 No Messages")?.is_no_responders());
+
+    // an error reported by JetStream itself is not a missing responder
+    let not_found = nats::Message::new(
+        "_INBOX.reply",
+        None,
+        r#"{
+            "type": "io.nats.jetstream.api.v1.stream_info_response",
+            "error": {"code": 404, "err_code": 10059, "description": "stream not found"}
+        }"#,
+        None,
+    );
+    match ApiResponse::<StreamInfo>::from_reply(subject, &not_found)? {
+        ApiResponse::Err { error, .. } => assert!(error.is_stream_not_found()),
+        ApiResponse::Ok(info) => panic!("unexpected stream info {:?}", info),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_consumer_start_description() -> std::io::Result<()> {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let config = ConsumerConfig::durable_pull("processor");
+    assert_eq!(config.start_description(), "start from the first message");
+    assert_eq!(
+        config.clone().deliver_from_seq(1500).start_description(),
+        "start from sequence 1500"
+    );
+    // 2024-01-01T00:00:00Z
+    let new_year = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+    assert_eq!(
+        config
+            .clone()
+            .deliver_from_time(new_year)
+            .start_description(),
+        "start from 2024-01-01T00:00:00Z"
+    );
+    assert_eq!(
+        config
+            .clone()
+            .deliver_last_per_subject()
+            .start_description(),
+        "start from the last message of each subject"
+    );
+    let unset = ConsumerConfig {
+        deliver_policy: DeliverPolicy::ByStartTime,
+        ..config
+    };
+    assert_eq!(unset.start_description(), "start from an unset time");
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_duration_strings() -> std::io::Result<()> {
+    let from_string: Duration = serde_json::from_str(r#""30s""#)?;
+    let from_nanos: Duration = serde_json::from_str("30000000000")?;
+    assert_eq!(from_string, from_nanos);
+    assert_eq!(from_string, Duration::from_secs(30));
+    assert_eq!(serde_json::to_string(&from_string)?, "30000000000");
+
+    for (s, nanos) in &[
+        ("0", 0),
+        ("1m30s", 90_000_000_000),
+        ("1.5s", 1_500_000_000),
+        ("2h", 7_200_000_000_000),
+        ("250ms", 250_000_000),
+        ("10us", 10_000),
+        ("10\u{b5}s", 10_000),
+        ("7ns", 7),
+    ] {
+        let duration: Duration = s.parse()?;
+        assert_eq!(duration.as_nanos(), *nanos, "{}", s);
+    }
+    for s in &["", "30", "s", "-1s", "1d", "1.2.3s"] {
+        let err = s.parse::<Duration>().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", s);
+    }
+    assert_eq!(Duration::from_millis(90_500).to_string(), "1m30s500ms");
+
+    // configurations exported by the CLI give durations as strings
+    let consumer: ConsumerConfig = serde_json::from_str(
+        r#"{"durable_name": "processor", "ack_wait": "30s", "max_expires": 5000000000}"#,
+    )?;
+    assert_eq!(consumer.ack_wait, Duration::from_secs(30));
+    assert_eq!(consumer.max_expires, Duration::from_secs(5));
+    let exported = ConsumerConfig {
+        ack_wait: Duration::from_secs(90),
+        ..ConsumerConfig::durable_pull("processor")
+    };
+    let reloaded: ConsumerConfig =
+        serde_json::from_str(&exported.to_cli_json()?)?;
+    assert_eq!(reloaded, exported);
+
+    let stream =
+        StreamConfig::from("events").max_age(Duration::from_secs(3600));
+    let reloaded: StreamConfig = serde_json::from_str(&stream.to_cli_json()?)?;
+    assert_eq!(reloaded.max_age, stream.max_age);
+    let mut large = serde_json::to_value(StreamConfig::from("events"))?;
+    large["max_age"] = "9223372036854775807".into();
+    let large: StreamConfig = serde_json::from_value(large)?;
+    assert_eq!(large.max_age, Duration::from_nanos(i64::MAX as u64));
+
+    // every duration goes through the same parser
+    let request: NextRequest = serde_json::from_str(
+        r#"{"batch": 1, "expires": 60000000000, "idle_heartbeat": "5s"}"#,
+    )?;
+    assert_eq!(request.idle_heartbeat, Some(Duration::from_secs(5)));
+    let opts: PubOpts = serde_json::from_str(
+        r#"{"ttl": "1h", "id": "", "lid": "", "str": "", "seq": 0}"#,
+    )?;
+    assert_eq!(opts.ttl, Some(Duration::from_secs(3600)));
+    let mut metric = serde_json::to_value(AckMetric::default())?;
+    metric["ack_time"] = "1.5ms".into();
+    let metric: AckMetric = serde_json::from_value(metric)?;
+    assert_eq!(metric.delay, Duration::from_nanos(1_500_000));
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_stream_serialized_len() -> std::io::Result<()> {
+    let mut large = StreamConfig {
+        subjects: Some((0..100).map(|i| format!("events.{}.>", i)).collect()),
+        ..StreamConfig::from("events")
+    }
+    .with_client_metadata();
+    large.metadata.insert(
+        "owner".to_string(),
+        "an \"escaped\" team \u{1f980}".to_string(),
+    );
+
+    for config in &[
+        StreamConfig::default(),
+        StreamConfig::from("events"),
+        StreamConfig::from("events")
+            .max_age(std::time::Duration::from_secs(60)),
+        large,
+    ] {
+        let json = serde_json::to_vec(config).unwrap();
+        assert_eq!(config.serialized_len()?, json.len(), "{}", config.name);
+    }
+
+    let too_long = StreamConfig::from("events")
+        .max_age(std::time::Duration::MAX)
+        .serialized_len()
+        .unwrap_err();
+    assert_eq!(too_long.kind(), std::io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[test]
+fn jetstream_next_request_clamp_expires() {
+    let max = Duration::from_secs(30);
+    let request = |expires| NextRequest {
+        batch: 10,
+        expires,
+        ..Default::default()
+    };
+
+    let over = request(60_000_000_000).clamp_expires(max);
+    assert_eq!(over.expires, 30_000_000_000);
+    assert_eq!(over.batch, 10);
+    let under = request(5_000_000_000).clamp_expires(max);
+    assert_eq!(under.expires, 5_000_000_000);
+    // the server rejects waiting requests without expires as well
+    assert_eq!(request(0).clamp_expires(max).expires, 30_000_000_000);
+
+    assert_eq!(
+        request(60_000_000_000).clamp_expires(Duration::default()),
+        request(60_000_000_000)
+    );
+    let no_wait = NextRequest::fetch_bytes(10, 1024);
+    assert_eq!(no_wait.clone().clamp_expires(max), no_wait);
+}
+
+#[test]
+fn jetstream_created_age() -> std::io::Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    let recently = (SystemTime::now() - Duration::from_secs(90)).into();
+    let stream = StreamInfo {
+        created: Some(recently),
+        ..Default::default()
+    };
+    let age = stream.created_age().unwrap();
+    assert!(age >= Duration::from_secs(90) && age < Duration::from_secs(120));
+    let consumer = ConsumerInfo {
+        created: Some(recently),
+        ..serde_json::from_str(CONSUMER_INFO)?
+    };
+    let age = consumer.created_age().unwrap();
+    assert!(age >= Duration::from_secs(90) && age < Duration::from_secs(120));
+
+    // the zero time reported for resources without a creation time
+    let mut value: serde_json::Value = serde_json::from_str(CONSUMER_INFO)?;
+    value["created"] = "0001-01-01T00:00:00Z".into();
+    let zero: ConsumerInfo = serde_json::from_value(value.clone())?;
+    assert_eq!(zero.created, None);
+    assert_eq!(zero.created_age(), None);
+    assert!(serde_json::to_value(&zero)?.get("created").is_none());
+    value.as_object_mut().unwrap().remove("created");
+    let unset: ConsumerInfo = serde_json::from_value(value)?;
+    assert_eq!(unset.created, None);
+    assert_eq!(StreamInfo::default().created_age(), None);
+
+    // clock skew can place the creation in the future
+    let future = StreamInfo {
+        created: Some((SystemTime::now() + Duration::from_secs(60)).into()),
+        ..Default::default()
+    };
+    assert_eq!(future.created_age(), None);
+
+    Ok(())
+}
+
+#[test]
+fn jetstream_compact_stream_config_postcard() -> std::io::Result<()> {
+    use std::convert::TryFrom;
+
+    let mut full = StreamConfig {
+        discard_new_per_subject: Some(true),
+        subjects: Some(vec!["events.>".to_string()]),
+        duplicate_window: Duration::from_millis(1500),
+        republish: Some(Republish {
+            src: "events.>".to_string(),
+            dest: "copies.>".to_string(),
+            headers_only: true,
+        }),
+        compression: Some(Compression::S2),
+        subject_transform: Some(SubjectTransformConfig {
+            src: "events.>".to_string(),
+            dest: "stored.>".to_string(),
+        }),
+        sources: Some(vec![
+            StreamSource {
+                name: "orders".to_string(),
+                opt_start_time: Some("2021-07-01T12:00:00.5Z".parse().unwrap()),
+                external: Some(ExternalStream {
+                    api: "$JS.hub.API".to_string(),
+                    deliver: String::new(),
+                }),
+                ..Default::default()
+            },
+            StreamSource {
+                name: "payments".to_string(),
+                subject_transforms: Some(vec![SubjectTransformConfig {
+                    src: "pay.*".to_string(),
+                    dest: "payments.{{wildcard(1)}}".to_string(),
+                }]),
+                ..Default::default()
+            },
+        ]),
+        ..StreamConfig::from("events").max_age(Duration::from_secs(3600))
+    }
+    .with_client_metadata();
+    full.extra.insert(
+        "future_limits".to_string(),
+        serde_json::json!({"max": [1, 2.5, null], "on": true}),
+    );
+    full.extra
+        .insert("future_name".to_string(), serde_json::json!("x"));
+
+    for config in &[StreamConfig::default(), full] {
+        let bytes =
+            postcard::to_allocvec(&CompactStreamConfig::from(config)).unwrap();
+        let compact: CompactStreamConfig =
+            postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(&StreamConfig::try_from(compact)?, config);
+    }
+
+    // the flattened `extra` of the plain configuration is a map of unknown
+    // length, which postcard can not write
+    assert!(postcard::to_allocvec(&StreamConfig::default()).is_err());
+    Ok(())
+}
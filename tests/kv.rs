@@ -0,0 +1,440 @@
+use std::io;
+
+use nats::kv::*;
+
+#[test]
+fn kv_key_subject() -> io::Result<()> {
+    assert_eq!(key_subject("bucket", "key")?, "$KV.bucket.key");
+    assert_eq!(
+        key_subject("bucket", "app.settings.log_level")?,
+        "$KV.bucket.app.settings.log_level"
+    );
+
+    for key in &["", "foo.*", "foo.>", "*", "foo..bar", ".foo", "foo bar"] {
+        let err = key_subject("bucket", key).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", key);
+    }
+
+    let key_error = |key| {
+        let err = key_subject("bucket", key).unwrap_err();
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<KeyError>())
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(key_error(""), KeyError::Empty);
+    assert_eq!(
+        key_error("foo..bar"),
+        KeyError::EmptyToken {
+            key: "foo..bar".to_string()
+        }
+    );
+    let err = key_error("foo.*");
+    assert_eq!(
+        err,
+        KeyError::InvalidChar {
+            key: "foo.*".to_string(),
+            c: '*'
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "the key \"foo.*\" contains the invalid character '*'"
+    );
+
+    Ok(())
+}
+
+fn kv_message(
+    seq: u64,
+    pending: u64,
+    op: Option<&str>,
+    data: &str,
+) -> nats::Message {
+    kv_delivery(seq, seq, pending, op, data)
+}
+
+fn kv_delivery(
+    seq: u64,
+    consumer_seq: u64,
+    pending: u64,
+    op: Option<&str>,
+    data: &str,
+) -> nats::Message {
+    let reply = format!(
+        "$JS.ACK.KV_bucket.history.1.{}.{}.0.{}",
+        seq, consumer_seq, pending
+    );
+    let headers = op.map(|op| {
+        vec![(KV_OPERATION_HEADER, op)]
+            .into_iter()
+            .collect::<nats::Headers>()
+    });
+    nats::Message::new("$KV.bucket.app.key", Some(&reply), data, headers)
+}
+
+#[test]
+fn kv_history() -> io::Result<()> {
+    let messages = vec![
+        kv_message(1, 2, None, "one"),
+        kv_message(4, 1, Some("DEL"), ""),
+        kv_message(7, 0, None, "two"),
+        // a live update arriving after the history was replayed
+        kv_message(9, 0, None, "three"),
+    ];
+
+    let entries = KvHistory::new(messages).collect::<io::Result<Vec<_>>>()?;
+
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().all(|e| e.bucket == "bucket"));
+    assert!(entries.iter().all(|e| e.key == "app.key"));
+    assert_eq!(
+        entries.iter().map(|e| e.revision).collect::<Vec<_>>(),
+        vec![1, 4, 7]
+    );
+    assert_eq!(
+        entries.iter().map(|e| e.operation).collect::<Vec<_>>(),
+        vec![Operation::Put, Operation::Delete, Operation::Put]
+    );
+    assert_eq!(entries[2].value, b"two");
+
+    let tombstones = vec![
+        kv_message(2, 1, Some("DEL"), ""),
+        kv_message(3, 0, Some("PURGE"), ""),
+    ];
+    let entries = KvHistory::new(tombstones).collect::<io::Result<Vec<_>>>()?;
+    assert_eq!(
+        entries.iter().map(|e| e.operation).collect::<Vec<_>>(),
+        vec![Operation::Delete, Operation::Purge]
+    );
+
+    // mocked messages have no connection to acknowledge them on
+    let message = kv_message(1, 0, None, "one");
+    assert_eq!(
+        message.ack().unwrap_err().kind(),
+        io::ErrorKind::NotConnected
+    );
+    let err = message
+        .double_ack(nats::jetstream::AckKind::Ack)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+
+    Ok(())
+}
+
+#[test]
+fn kv_bucket_names() -> io::Result<()> {
+    use std::convert::TryFrom;
+
+    use nats::jetstream::{DiscardPolicy, StreamConfig};
+    use nats::object_store::ObjectStoreConfig;
+
+    validate_bucket_name("profiles")?;
+    validate_bucket_name("user_profiles-v2")?;
+
+    for name in &["", "user.profiles", "profiles*", "user profiles"] {
+        let err = validate_bucket_name(name).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+    }
+    let name_error = |name| {
+        let err = validate_bucket_name(name).unwrap_err();
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<BucketNameError>())
+            .cloned()
+            .unwrap()
+    };
+    assert_eq!(name_error(""), BucketNameError::Empty);
+    assert_eq!(
+        name_error("user.profiles"),
+        BucketNameError::InvalidChar {
+            name: "user.profiles".to_string(),
+            c: '.'
+        }
+    );
+    assert!(key_subject("user.profiles", "key").is_err());
+
+    let kv = KeyValueConfig {
+        bucket: "profiles".to_string(),
+        history: 5,
+        ..Default::default()
+    };
+    let stream = StreamConfig::try_from(&kv)?;
+    assert_eq!(stream.name, "KV_profiles");
+    assert_eq!(stream.effective_subjects(), vec!["$KV.profiles.>"]);
+    assert_eq!(stream.max_msgs_per_subject, 5);
+    assert_eq!(stream.discard, DiscardPolicy::New);
+
+    let dotted = KeyValueConfig {
+        bucket: "user.profiles".to_string(),
+        ..kv.clone()
+    };
+    assert!(StreamConfig::try_from(&dotted).is_err());
+    let too_long = KeyValueConfig {
+        history: MAX_HISTORY + 1,
+        ..kv
+    };
+    assert!(StreamConfig::try_from(&too_long).is_err());
+
+    let objects = ObjectStoreConfig {
+        bucket: "assets".to_string(),
+        ..Default::default()
+    };
+    let stream = StreamConfig::try_from(&objects)?;
+    assert_eq!(stream.name, "OBJ_assets");
+    assert_eq!(
+        stream.effective_subjects(),
+        vec!["$O.assets.C.>", "$O.assets.M.>"]
+    );
+    let empty = ObjectStoreConfig::default();
+    assert!(StreamConfig::try_from(&empty).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn kv_config_from_stream_info() -> io::Result<()> {
+    use std::convert::TryFrom;
+
+    use nats::jetstream::{Duration, StreamConfig, StreamInfo};
+
+    let kv = KeyValueConfig {
+        bucket: "profiles".to_string(),
+        history: 5,
+        max_age: Some(Duration::from_secs(3600)),
+        max_bytes: 1 << 20,
+        max_value_size: 1024,
+        num_replicas: 3,
+        ..Default::default()
+    };
+    let info = StreamInfo {
+        config: StreamConfig::try_from(&kv)?,
+        ..Default::default()
+    };
+    assert_eq!(KeyValueConfig::try_from(&info)?, kv);
+
+    let unlimited = StreamInfo {
+        config: StreamConfig {
+            max_age: Default::default(),
+            ..info.config.clone()
+        },
+        ..Default::default()
+    };
+    assert_eq!(KeyValueConfig::try_from(&unlimited)?.max_age, None);
+
+    for config in &[
+        StreamConfig::from("events"),
+        info.config.cloned_as("KV_"),
+        info.config.cloned_as("KV_other"),
+        StreamConfig {
+            subjects: Some(vec!["$KV.profiles.>".to_string()]),
+            ..StreamConfig::from("KV_user.profiles")
+        },
+    ] {
+        let info = StreamInfo {
+            config: config.clone(),
+            ..Default::default()
+        };
+        let err = KeyValueConfig::try_from(&info).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", config.name);
+        let expected = if config.name == "KV_other" {
+            KvError::MissingSubjects {
+                stream: "KV_other".to_string(),
+                subjects: "$KV.other.>".to_string(),
+            }
+        } else {
+            KvError::NotABucket {
+                stream: config.name.clone(),
+            }
+        };
+        assert_eq!(
+            err.get_ref()
+                .and_then(|inner| inner.downcast_ref::<KvError>()),
+            Some(&expected)
+        );
+    }
+
+    Ok(())
+}
+
+fn heartbeat(last_consumer_seq: u64) -> io::Result<nats::Message> {
+    use std::convert::TryFrom;
+
+    let headers = format!(
+        "NATS/1.0 100 Idle Heartbeat\r\nNats-Last-Consumer: {}\r\n\r\n",
+        last_consumer_seq
+    );
+    Ok(nats::Message::new(
+        "_INBOX.watch",
+        None,
+        "",
+        Some(nats::Headers::try_from(headers.as_bytes())?),
+    ))
+}
+
+#[test]
+fn kv_watch() -> io::Result<()> {
+    assert_eq!(watch_subject("bucket", ">")?, "$KV.bucket.>");
+    assert_eq!(
+        watch_subject("bucket", "app.*.level")?,
+        "$KV.bucket.app.*.level"
+    );
+    for key in &["", "app.>.level", "app..level", "app level"] {
+        let err = watch_subject("bucket", key).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", key);
+    }
+
+    let messages = vec![
+        kv_delivery(3, 1, 1, None, "one"),
+        heartbeat(1)?,
+        kv_delivery(5, 2, 0, Some("DEL"), ""),
+        // live updates arriving after the initial values were replayed
+        kv_delivery(8, 3, 0, None, "two"),
+        kv_delivery(9, 4, 0, None, "three"),
+    ];
+    let events = KvWatch::new(messages, 2).collect::<io::Result<Vec<_>>>()?;
+
+    let revisions = events
+        .iter()
+        .map(|event| match event {
+            KvWatchEvent::Entry(entry) => Some(entry.revision),
+            KvWatchEvent::InitDone => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(revisions, vec![Some(3), Some(5), None, Some(8), Some(9)]);
+    match &events[1] {
+        KvWatchEvent::Entry(entry) => {
+            assert_eq!(entry.operation, Operation::Delete)
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+
+    // an empty bucket has no initial values to wait for
+    let mut empty = KvWatch::new(vec![kv_message(1, 0, None, "first")], 0);
+    assert_eq!(empty.next().transpose()?, Some(KvWatchEvent::InitDone));
+    assert!(matches!(
+        empty.next().transpose()?,
+        Some(KvWatchEvent::Entry(ref entry)) if entry.value == b"first"
+    ));
+    assert_eq!(empty.next().transpose()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn kv_watch_missed_message() -> io::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let revision = |event: Option<io::Result<KvWatchEvent>>| match event {
+        Some(Ok(KvWatchEvent::Entry(entry))) => Ok(Some(entry.revision)),
+        Some(Ok(KvWatchEvent::InitDone)) => Ok(None),
+        Some(Err(err)) => Err(err),
+        None => panic!("the watch ended"),
+    };
+
+    // the consumer skipped its second message, and the watch can not
+    // recreate it
+    let mut watch = KvWatch::new(
+        vec![
+            kv_delivery(3, 1, 0, None, "one"),
+            kv_delivery(8, 3, 0, None, "two"),
+        ],
+        1,
+    );
+    assert_eq!(revision(watch.next())?, Some(3));
+    assert_eq!(revision(watch.next())?, None);
+    let err = revision(watch.next()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(watch.next().is_none());
+
+    // a heartbeat reports a message that never arrived, so the consumer is
+    // recreated after the last revision received, and once more when the
+    // recreated consumer skips a live update
+    let starts = Arc::new(Mutex::new(vec![]));
+    let mut redeliveries = vec![
+        vec![kv_delivery(9, 1, 0, None, "five")],
+        vec![
+            kv_delivery(4, 1, 1, None, "two"),
+            kv_delivery(5, 2, 0, None, "three"),
+            kv_delivery(8, 4, 0, None, "four"),
+        ],
+    ];
+    let mut watch =
+        KvWatch::new(vec![kv_delivery(3, 1, 1, None, "one"), heartbeat(2)?], 2)
+            .with_resume({
+                let starts = starts.clone();
+                move |start_seq| {
+                    starts.lock().unwrap().push(start_seq);
+                    let messages = redeliveries.pop().unwrap();
+                    let pending =
+                        messages[0].jetstream_message_info().unwrap().pending;
+                    Ok((messages, pending + 1))
+                }
+            });
+    let revisions = (0..3)
+        .map(|_| revision(watch.next()))
+        .collect::<io::Result<Vec<_>>>()?;
+    assert_eq!(revisions, vec![Some(3), Some(4), Some(5)]);
+    assert_eq!(revision(watch.next())?, None);
+    assert_eq!(*starts.lock().unwrap(), vec![4]);
+
+    assert_eq!(revision(watch.next())?, Some(9));
+    assert!(watch.next().is_none());
+    assert_eq!(*starts.lock().unwrap(), vec![4, 6]);
+
+    Ok(())
+}
+
+#[test]
+fn kv_tombstone_purges() {
+    use std::time::{Duration, SystemTime};
+
+    let now = SystemTime::now();
+    let entry = |key: &str, revision, age, operation| KeyValueEntry {
+        bucket: "bucket".to_string(),
+        key: key.to_string(),
+        value: vec![],
+        revision,
+        delta: 0,
+        created: now - Duration::from_secs(age),
+        operation,
+    };
+    let latest = vec![
+        entry("deleted", 3, 3600, Operation::Delete),
+        entry("purged", 4, 3600, Operation::Purge),
+        entry("recent", 5, 10, Operation::Delete),
+        entry("live", 6, 3600, Operation::Put),
+        // deleted and then put again, in either order
+        entry("revived", 1, 3600, Operation::Delete),
+        entry("revived", 7, 60, Operation::Put),
+        entry("restored", 9, 60, Operation::Put),
+        entry("restored", 2, 3600, Operation::Delete),
+    ];
+
+    let purges = tombstone_purges(
+        "bucket",
+        latest.clone(),
+        Some(Duration::from_secs(1800)),
+        now,
+    );
+    let selected = purges
+        .iter()
+        .map(|req| (req.filter.as_deref().unwrap(), req.seq.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        selected,
+        vec![("$KV.bucket.deleted", 4), ("$KV.bucket.purged", 5)]
+    );
+    assert!(purges.iter().all(|req| req.keep.is_none()));
+
+    let all = tombstone_purges("bucket", latest, None, now);
+    assert_eq!(
+        all.iter()
+            .map(|req| req.filter.as_deref().unwrap())
+            .collect::<Vec<_>>(),
+        vec![
+            "$KV.bucket.deleted",
+            "$KV.bucket.purged",
+            "$KV.bucket.recent"
+        ]
+    );
+}
@@ -0,0 +1,233 @@
+use nats::object_store::*;
+
+#[test]
+fn object_store_chunks() {
+    assert_eq!(chunk_subject("assets", "A1B2C3"), "$O.assets.C.A1B2C3");
+
+    let chunks = ChunkMeta::split("A1B2C3", 300 * 1024, DEFAULT_CHUNK_SIZE);
+    assert_eq!(
+        chunks.iter().map(|c| c.size).collect::<Vec<_>>(),
+        vec![128 * 1024, 128 * 1024, 44 * 1024]
+    );
+    assert_eq!(
+        chunks.iter().map(|c| c.index).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+    assert!(chunks
+        .iter()
+        .all(|c| c.subject("assets") == "$O.assets.C.A1B2C3"));
+
+    assert_eq!(ChunkMeta::split("A1B2C3", 256, 128).len(), 2);
+    assert!(ChunkMeta::split("A1B2C3", 0, 128).is_empty());
+}
+
+#[test]
+fn object_store_digest() {
+    assert_eq!(
+        ObjectDigest::of(b""),
+        "SHA-256=47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU="
+    );
+
+    let data = vec![0xAB_u8; 1000];
+    let mut digest = ObjectDigest::new();
+    for chunk in data.chunks(300) {
+        digest.update(chunk);
+    }
+    let digest = digest.finish();
+    assert_eq!(digest, ObjectDigest::of(&data));
+    assert!(digest.starts_with(DIGEST_PREFIX));
+    assert!(!digest.contains('+') && !digest.contains('/'));
+}
+
+#[test]
+fn object_store_links() -> std::io::Result<()> {
+    assert_eq!(
+        meta_subject("assets", "logo.png"),
+        "$O.assets.M.bG9nby5wbmc="
+    );
+
+    let link = ObjectMeta {
+        name: "latest.png".to_string(),
+        opts: Some(ObjectMetaOptions {
+            link: Some(ObjectLink {
+                bucket: "assets".to_string(),
+                name: Some("logo.png".to_string()),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let json = serde_json::to_value(&link)?;
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "name": "latest.png",
+            "options": {"link": {"bucket": "assets", "name": "logo.png"}}
+        })
+    );
+    assert_eq!(serde_json::from_value::<ObjectMeta>(json)?, link);
+    assert_eq!(
+        link.link().and_then(|link| link.name.as_deref()),
+        Some("logo.png")
+    );
+
+    let bucket_link: ObjectMeta = serde_json::from_str(
+        r#"{"name": "archive", "options": {"link": {"bucket": "old"}}}"#,
+    )?;
+    assert_eq!(bucket_link.link().map(|link| link.name.clone()), Some(None));
+
+    let plain: ObjectMeta = serde_json::from_str(r#"{"name": "logo.png"}"#)?;
+    assert!(plain.link().is_none());
+    assert_eq!(serde_json::to_string(&plain)?, r#"{"name":"logo.png"}"#);
+
+    Ok(())
+}
+
+#[test]
+fn object_store_assemble() -> std::io::Result<()> {
+    let data = (0..1000).map(|i| (i % 256) as u8).collect::<Vec<_>>();
+    let info: ObjectInfo = serde_json::from_value(serde_json::json!({
+        "name": "logo.png",
+        "description": "the logo",
+        "bucket": "assets",
+        "nuid": "A1B2C3",
+        "size": 1000,
+        "mtime": "2021-07-01T12:00:00Z",
+        "chunks": 3,
+        "digest": ObjectDigest::of(&data),
+    }))?;
+    assert_eq!(info.meta.name, "logo.png");
+    assert_eq!(info.meta.description.as_deref(), Some("the logo"));
+    assert!(!info.deleted);
+
+    let chunk = |data: &[u8]| {
+        nats::Message::new(&chunk_subject("assets", "A1B2C3"), None, data, None)
+    };
+    let chunks = data.chunks(400).map(chunk).collect::<Vec<_>>();
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(info.assemble(chunks.clone())?, data);
+
+    let object_error = |err: std::io::Error| {
+        let inner = err.get_ref().and_then(|e| e.downcast_ref::<ObjectError>());
+        assert_eq!(inner.map(ObjectError::kind), Some(err.kind()));
+        inner.cloned().unwrap()
+    };
+
+    let mut corrupted = chunks.clone();
+    corrupted[1].data[0] ^= 0xFF;
+    let err = info.assemble(corrupted).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matches!(
+        object_error(err),
+        ObjectError::DigestMismatch { ref expected, .. }
+            if *expected == info.digest
+    ));
+
+    let err = info.assemble(chunks[..2].to_vec()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(
+        object_error(err),
+        ObjectError::MissingChunk {
+            name: "logo.png".to_string(),
+            index: 2,
+            chunks: 3,
+        }
+    );
+
+    let mut truncated = chunks.clone();
+    truncated[2].data.pop();
+    let err = info.assemble(truncated).unwrap_err();
+    assert_eq!(
+        object_error(err),
+        ObjectError::SizeMismatch {
+            name: "logo.png".to_string(),
+            size: 999,
+            expected: 1000,
+        }
+    );
+
+    // an empty object has no chunks to read
+    let empty = ObjectInfo {
+        size: 0,
+        chunks: 0,
+        digest: ObjectDigest::of(b""),
+        ..info
+    };
+    assert_eq!(empty.assemble(chunks)?, b"");
+
+    Ok(())
+}
+
+#[test]
+fn object_store_put_chunks() -> std::io::Result<()> {
+    let meta = |max_chunk_size: Option<i32>| ObjectMeta {
+        name: "logo.png".to_string(),
+        opts: max_chunk_size.map(|max_chunk_size| ObjectMetaOptions {
+            max_chunk_size: Some(max_chunk_size),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let stored = |info: &ObjectInfo, data: &[u8]| {
+        data.chunks(info.chunk_size())
+            .map(|chunk| {
+                nats::Message::new(
+                    &chunk_subject(&info.bucket, &info.nuid),
+                    None,
+                    chunk,
+                    None,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let small = b"smaller than a chunk";
+    let info = ObjectInfo::new("assets", meta(None), "A1B2C3", small);
+    assert_eq!(info.chunk_size(), DEFAULT_CHUNK_SIZE);
+    assert_eq!((info.size, info.chunks), (20, 1));
+    assert_eq!(info.digest, ObjectDigest::of(small));
+    assert_eq!(info.assemble(stored(&info, small))?, small);
+
+    let large = vec![7_u8; 1000];
+    let info = ObjectInfo::new("assets", meta(Some(300)), "D4E5F6", &large);
+    assert_eq!(info.chunk_size(), 300);
+    assert_eq!((info.size, info.chunks), (1000, 4));
+    assert_eq!(info.assemble(stored(&info, &large))?, large);
+
+    let json = serde_json::to_value(&info)?;
+    assert_eq!(json["name"], "logo.png");
+    assert_eq!(json["nuid"], "D4E5F6");
+    assert_eq!(json["options"]["max_chunk_size"], 300);
+    assert!(json.get("deleted").is_none());
+    assert_eq!(serde_json::from_value::<ObjectInfo>(json)?, info);
+
+    let empty = ObjectInfo::new("assets", meta(Some(0)), "G7H8I9", b"");
+    assert_eq!(empty.chunk_size(), DEFAULT_CHUNK_SIZE);
+    assert_eq!((empty.size, empty.chunks), (0, 0));
+
+    Ok(())
+}
+
+#[test]
+fn object_store_config() -> std::io::Result<()> {
+    use nats::jetstream::{DiscardPolicy, Duration, StreamConfig};
+    use std::convert::TryFrom;
+
+    let config = StreamConfig::try_from(&ObjectStoreConfig {
+        bucket: "assets".to_string(),
+        max_age: Some(Duration::from_secs(24 * 60 * 60)),
+        max_bytes: 1 << 30,
+        ..Default::default()
+    })?;
+    assert_eq!(config.name, "OBJ_assets");
+    assert_eq!(config.max_age.to_string(), "24h");
+    assert_eq!(config.discard, DiscardPolicy::New);
+    assert_eq!(config.num_replicas, 1);
+
+    let forever = StreamConfig::try_from(&ObjectStoreConfig {
+        bucket: "assets".to_string(),
+        ..Default::default()
+    })?;
+    assert_eq!(forever.max_age, Duration::default());
+    Ok(())
+}